@@ -0,0 +1,112 @@
+//! Unpacking of archived fixtures ('archives' feature).
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+use crate::failure::Failure;
+
+/// Unpacks the '.tar', '.tar.gz'/'.tgz' or '.zip' file 'archive' into the existing directory
+/// 'dir'. Entries which would escape 'dir' are rejected.
+#[track_caller]
+pub(crate) fn unpack(archive: &Path, dir: &Path) {
+    let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let file = match File::open(archive) {
+        Ok(file) => BufReader::new(file),
+        Err(err) => Failure::new(
+            "install_archive",
+            format!("can not open archive {:?}: {}", archive, err),
+        )
+        .fail(),
+    };
+
+    let result = if name.ends_with(".tar") {
+        tar::Archive::new(file).unpack(dir)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        tar::Archive::new(GzDecoder::new(file)).unpack(dir)
+    } else if name.ends_with(".zip") {
+        zip::ZipArchive::new(file)
+            .and_then(|mut zip| zip.extract(dir))
+            .map_err(std::io::Error::other)
+    } else {
+        Failure::new(
+            "install_archive",
+            format!(
+                "unsupported archive type {:?}, expected .tar, .tar.gz, .tgz or .zip",
+                archive
+            ),
+        )
+        .fail();
+    };
+
+    if let Err(err) = result {
+        Failure::new(
+            "install_archive",
+            format!("unpacking {:?} failed: {}", archive, err),
+        )
+        .fail();
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn tar_gz() {
+        let source = TempDir::new().expect("TempDir created");
+        let archive = source.path().join("fixture.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&archive).expect("archive created"),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "data/hello.txt", &b"hello\n"[..])
+            .expect("entry appended");
+        tar.into_inner()
+            .expect("tar finished")
+            .finish()
+            .expect("gzip finished");
+
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .install_archive(&archive)
+            .assert_file_utf8("data/hello.txt", "^hello\n$");
+    }
+
+    #[test]
+    fn zip() {
+        let source = TempDir::new().expect("TempDir created");
+        let archive = source.path().join("fixture.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).expect("archive created"));
+        zip.start_file("data/hello.txt", zip::write::SimpleFileOptions::default())
+            .expect("entry started");
+        zip.write_all(b"hello\n").expect("entry written");
+        zip.finish().expect("zip finished");
+
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .install_archive(&archive)
+            .assert_file_utf8("data/hello.txt", "^hello\n$");
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported archive type")]
+    fn unsupported() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        std::fs::write(tmpdir.path().join("fixture.rar"), "").expect("written");
+        tmpdir.install_archive(tmpdir.path().join("fixture.rar"));
+    }
+}
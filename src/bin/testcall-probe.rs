@@ -0,0 +1,74 @@
+//! A controllable target process for testing 'testcall' itself and for downstream users who
+//! need a well known program to exercise their test setups.
+//!
+//! Every argument is a command which is executed in order:
+//!
+//! | command      | action                                                             |
+//! |--------------|--------------------------------------------------------------------|
+//! | `out:TEXT`   | writes TEXT and a newline to stdout                                |
+//! | `err:TEXT`   | writes TEXT and a newline to stderr                                |
+//! | `args`       | writes all arguments, one per line, to stdout                      |
+//! | `env:NAME`   | writes the value of the environment variable NAME or '<unset>'     |
+//! | `cwd`        | writes the current directory to stdout                             |
+//! | `cat`        | copies stdin to stdout until end of file                           |
+//! | `sleep:MS`   | sleeps MS milliseconds                                             |
+//! | `exit:CODE`  | exits immediately with CODE                                        |
+//!
+//! Without an `exit:` command the probe exits with 0. Unknown commands exit with 2.
+use std::env;
+use std::io::{self, Write};
+use std::process::exit;
+use std::thread::sleep;
+use std::time::Duration;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    for arg in &args {
+        let (command, param) = match arg.split_once(':') {
+            Some((command, param)) => (command, Some(param)),
+            None => (arg.as_str(), None),
+        };
+
+        match (command, param) {
+            ("out", Some(text)) => writeln!(stdout, "{}", text).expect("write stdout"),
+            ("err", Some(text)) => {
+                stdout.flush().expect("flush stdout");
+                eprintln!("{}", text);
+            }
+            ("args", None) => {
+                for arg in &args {
+                    writeln!(stdout, "{}", arg).expect("write stdout");
+                }
+            }
+            ("env", Some(name)) => match env::var_os(name) {
+                Some(value) => writeln!(stdout, "{}", value.to_string_lossy()),
+                None => writeln!(stdout, "<unset>"),
+            }
+            .expect("write stdout"),
+            ("cwd", None) => writeln!(
+                stdout,
+                "{}",
+                env::current_dir().expect("current dir").display()
+            )
+            .expect("write stdout"),
+            ("cat", None) => {
+                io::copy(&mut io::stdin().lock(), &mut stdout).expect("copy stdin");
+            }
+            ("sleep", Some(ms)) => {
+                stdout.flush().expect("flush stdout");
+                sleep(Duration::from_millis(ms.parse().expect("milliseconds")));
+            }
+            ("exit", Some(code)) => {
+                stdout.flush().expect("flush stdout");
+                exit(code.parse().expect("exit code"));
+            }
+            _ => {
+                eprintln!("testcall-probe: unknown command: {}", arg);
+                exit(2);
+            }
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::process::Child;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::failure::Failure;
+
+/// What happens when a call writes more than the output limit to stdout or stderr. The
+/// captured output is always cut at the limit.
+#[derive(Clone, Debug, Default)]
+pub enum OutputPolicy {
+    /// Fails the call with an "output exceeded" panic. Reading stops at the limit and on
+    /// unix the child is killed right away, elsewhere a child writing more fails with a
+    /// broken pipe error.
+    #[default]
+    Panic,
+    /// Discards everything beyond the limit, the child keeps running undisturbed.
+    Truncate,
+    /// Like 'Truncate' but the complete output is written to the files 'stdout' and
+    /// 'stderr' in the given directory.
+    SpillToFile(PathBuf),
+}
+
+/// Reacts on a child exceeding the output limit, like killing it.
+pub(crate) type OnExceeded = Arc<dyn Fn() + Send + Sync>;
+
+/// Where captured output is echoed to when teeing is enabled.
+#[derive(Clone, Copy)]
+enum Echo {
+    Stdout,
+    Stderr,
+}
+
+impl Echo {
+    /// Uses 'print!'/'eprint!' so that the test harness output capturing applies.
+    fn print(self, data: &[u8]) {
+        let text = String::from_utf8_lossy(data);
+        match self {
+            Echo::Stdout => print!("{}", text),
+            Echo::Stderr => eprint!("{}", text),
+        }
+    }
+}
+
+#[derive(Default)]
+struct StreamState {
+    data: Vec<u8>,
+    first: Option<Instant>,
+    closed: bool,
+    exceeded: bool,
+}
+
+/// The output collected from one stream of a child so far. Waiters are woken whenever new
+/// data arrives and when the stream is closed.
+#[derive(Default)]
+pub(crate) struct Stream {
+    state: Mutex<StreamState>,
+    cond: Condvar,
+}
+
+impl Stream {
+    fn lock(&self) -> MutexGuard<'_, StreamState> {
+        self.state.lock().expect("lock")
+    }
+
+    /// Appends 'data', returns false when this pushed the stream over 'limit'. The data
+    /// is kept up to the limit.
+    fn append(&self, data: &[u8], limit: Option<usize>) -> bool {
+        let mut state = self.lock();
+        if state.first.is_none() {
+            state.first = Some(Instant::now());
+        }
+        if state.exceeded {
+            return false;
+        }
+        state.data.extend_from_slice(data);
+        if let Some(limit) = limit {
+            if state.data.len() > limit {
+                state.data.truncate(limit);
+                state.exceeded = true;
+            }
+        }
+        self.cond.notify_all();
+        !state.exceeded
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.cond.notify_all();
+    }
+
+    /// Returns the time of the first byte, if any arrived so far.
+    pub(crate) fn first(&self) -> Option<Instant> {
+        self.lock().first
+    }
+
+    /// Returns whether the writer closed the stream.
+    fn closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    /// Returns whether the stream was cut off at the output limit.
+    pub(crate) fn exceeded(&self) -> bool {
+        self.lock().exceeded
+    }
+
+    /// Number of bytes collected so far.
+    pub(crate) fn len(&self) -> usize {
+        self.lock().data.len()
+    }
+
+    /// Returns a copy of the data collected so far.
+    pub(crate) fn snapshot(&self) -> Vec<u8> {
+        self.lock().data.clone()
+    }
+
+    /// Waits until 'ready' returns true for the data collected so far, the stream closed or
+    /// 'deadline' passed. Returns whether 'ready' was satisfied.
+    pub(crate) fn wait_until<F>(&self, deadline: Instant, mut ready: F) -> bool
+    where
+        F: FnMut(&[u8]) -> bool,
+    {
+        let mut state = self.lock();
+        loop {
+            if ready(&state.data) {
+                return true;
+            }
+            let now = Instant::now();
+            if state.closed || now >= deadline {
+                return false;
+            }
+            state = self
+                .cond
+                .wait_timeout(state, deadline - now)
+                .expect("lock")
+                .0;
+        }
+    }
+
+    /// Returns a copy of the data collected after the first 'start' bytes.
+    pub(crate) fn since(&self, start: usize) -> Vec<u8> {
+        self.lock().data.get(start..).unwrap_or_default().to_vec()
+    }
+
+    /// Like 'since()' but while the stream is open an incomplete utf8 sequence at the end is
+    /// left out, the rest of a character split across writes arrives later.
+    pub(crate) fn since_utf8(&self, start: usize) -> Vec<u8> {
+        let state = self.lock();
+        let data = state.data.get(start..).unwrap_or_default();
+        let len = if state.closed {
+            data.len()
+        } else {
+            complete_utf8(data)
+        };
+        data[..len].to_vec()
+    }
+
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.lock().data)
+    }
+}
+
+/// Collects stdout and stderr of a child process in background threads. This prevents
+/// deadlocks when the child fills one pipe while the other is read and allows to echo and
+/// inspect the output while the child is still running.
+pub(crate) struct Capture {
+    stdout: Arc<Stream>,
+    stderr: Arc<Stream>,
+    threads: Vec<JoinHandle<()>>,
+    limit: Option<usize>,
+    policy: OutputPolicy,
+}
+
+impl Capture {
+    /// Takes the piped stdout/stderr handles from 'child' and starts collecting them.
+    /// When 'tee' is set, all output is echoed line by line to the test's stdout/stderr.
+    /// A stream which grows beyond 'limit' bytes is handled by 'policy', under
+    /// 'OutputPolicy::Panic' 'on_exceeded' is called at once then.
+    pub(crate) fn start(
+        child: &mut Child,
+        tee: bool,
+        limit: Option<usize>,
+        policy: OutputPolicy,
+        on_exceeded: Option<OnExceeded>,
+    ) -> Capture {
+        let stdout = Arc::new(Stream::default());
+        let stderr = Arc::new(Stream::default());
+        let spill = |name| match (&policy, limit) {
+            (OutputPolicy::SpillToFile(dir), Some(_)) => {
+                Some(File::create(dir.join(name)).expect("spill file created"))
+            }
+            _ => None,
+        };
+        let drain = limit.is_some() && !matches!(policy, OutputPolicy::Panic);
+        let mut threads = Vec::new();
+        if let Some(source) = child.stdout.take() {
+            threads.push(collect(
+                source,
+                if tee { Some(Echo::Stdout) } else { None },
+                Arc::clone(&stdout),
+                limit,
+                Sink {
+                    drain,
+                    spill: spill("stdout"),
+                    on_exceeded: on_exceeded.clone(),
+                },
+            ));
+        } else {
+            stdout.close();
+        }
+        if let Some(source) = child.stderr.take() {
+            threads.push(collect(
+                source,
+                if tee { Some(Echo::Stderr) } else { None },
+                Arc::clone(&stderr),
+                limit,
+                Sink {
+                    drain,
+                    spill: spill("stderr"),
+                    on_exceeded,
+                },
+            ));
+        } else {
+            // not piped, like stderr merged into a pty
+            stderr.close();
+        }
+        Capture {
+            stdout,
+            stderr,
+            threads,
+            limit,
+            policy,
+        }
+    }
+
+    /// The stdout collected so far.
+    pub(crate) fn stdout(&self) -> &Stream {
+        &self.stdout
+    }
+
+    /// The stderr collected so far.
+    pub(crate) fn stderr(&self) -> &Stream {
+        &self.stderr
+    }
+
+    /// The failure of a call which exceeded the output limit when the policy is 'Panic'.
+    pub(crate) fn limit_failure(&self) -> Option<Failure> {
+        if !matches!(self.policy, OutputPolicy::Panic) {
+            return None;
+        }
+        [("stdout", &self.stdout), ("stderr", &self.stderr)]
+            .iter()
+            .find(|(_, stream)| stream.exceeded())
+            .map(|(name, _)| {
+                Failure::new(
+                    "max_output",
+                    format!(
+                        "{} output exceeded {}",
+                        name,
+                        human_size(self.limit.unwrap_or_default())
+                    ),
+                )
+            })
+    }
+
+    /// Waits until the child closed its output streams and returns (stdout, stderr). With a
+    /// 'deadline' this waits at most until then, streams which are still held open, like by
+    /// grandchildren which inherited them, are returned as collected so far.
+    pub(crate) fn finish(&mut self, deadline: Option<Instant>) -> (Vec<u8>, Vec<u8>) {
+        if let Some(deadline) = deadline {
+            for stream in [&self.stdout, &self.stderr] {
+                stream.wait_until(deadline, |_| false);
+            }
+            if !(self.stdout.closed() && self.stderr.closed()) {
+                // the collectors end by themselves when the last writer is gone
+                self.threads.clear();
+            }
+        }
+        for thread in self.threads.drain(..) {
+            thread.join().expect("output collected");
+        }
+        (self.stdout.take(), self.stderr.take())
+    }
+}
+
+/// What a collector does with output beyond the limit.
+struct Sink {
+    /// Keep reading (and discarding) instead of closing the pipe.
+    drain: bool,
+    /// Gets the complete output.
+    spill: Option<File>,
+    /// Called when the limit was exceeded and reading stops.
+    on_exceeded: Option<OnExceeded>,
+}
+
+fn collect<R: Read + Send + 'static>(
+    mut source: R,
+    echo: Option<Echo>,
+    stream: Arc<Stream>,
+    limit: Option<usize>,
+    mut sink: Sink,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        // data not echoed yet because its line is incomplete
+        let mut pending = Vec::new();
+        let mut buffer = [0u8; 8192];
+        loop {
+            match source.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if let Some(spill) = &mut sink.spill {
+                        spill.write_all(&buffer[..n]).expect("output spilled");
+                    }
+                    if !stream.append(&buffer[..n], limit) && !sink.drain {
+                        if let Some(on_exceeded) = &sink.on_exceeded {
+                            on_exceeded();
+                        }
+                        break;
+                    }
+                    if let Some(echo) = echo {
+                        pending.extend_from_slice(&buffer[..n]);
+                        if let Some(pos) = pending.iter().rposition(|&b| b == b'\n') {
+                            echo.print(&pending[..=pos]);
+                            pending.drain(..=pos);
+                        }
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+        stream.close();
+        if let Some(echo) = echo {
+            if !pending.is_empty() {
+                echo.print(&pending);
+            }
+        }
+    })
+}
+
+/// Length of 'data' without a trailing incomplete utf8 sequence.
+fn complete_utf8(data: &[u8]) -> usize {
+    for back in 1..=data.len().min(3) {
+        let byte = data[data.len() - back];
+        if byte & 0xc0 == 0x80 {
+            continue;
+        }
+        let needed = match byte {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        return if needed > back {
+            data.len() - back
+        } else {
+            data.len()
+        };
+    }
+    data.len()
+}
+
+/// Renders a size in bytes with the largest binary unit that divides it evenly.
+fn human_size(bytes: usize) -> String {
+    match bytes {
+        0 => String::from("0 bytes"),
+        b if b % (1 << 30) == 0 => format!("{} GiB", b >> 30),
+        b if b % (1 << 20) == 0 => format!("{} MiB", b >> 20),
+        b if b % (1 << 10) == 0 => format!("{} KiB", b >> 10),
+        b => format!("{} bytes", b),
+    }
+}
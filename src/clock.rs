@@ -0,0 +1,168 @@
+//! Fixing the time seen by called programs, for snapshot stable outputs which contain
+//! timestamps.
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::TestCall;
+
+/// The variable telling reproducible builds and many tools which time to use.
+const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// The variable the dynamic loader preloads libraries from.
+#[cfg(target_os = "macos")]
+const PRELOAD: &str = "DYLD_INSERT_LIBRARIES";
+#[cfg(not(target_os = "macos"))]
+const PRELOAD: &str = "LD_PRELOAD";
+
+impl TestCall<'_> {
+    /// Makes all following calls see 'time' as the current time, frozen. The time is passed
+    /// as seconds since the epoch in 'SOURCE_DATE_EPOCH' (or the variable set with
+    /// 'fake_time_env()'). When 'libfaketime()' is found it is preloaded as well, then the
+    /// clock functions of the libc return 'time' too. Statically linked executables and
+    /// direct system calls are not affected by libfaketime.
+    pub fn fake_time(&mut self, time: SystemTime) -> &mut Self {
+        self.fake_time = Some(
+            time.duration_since(UNIX_EPOCH)
+                .expect("fake time after the epoch")
+                .as_secs(),
+        );
+        self
+    }
+
+    /// Passes the time set by 'fake_time()' in the variable 'key' instead of
+    /// 'SOURCE_DATE_EPOCH', for programs with their own convention.
+    pub fn fake_time_env<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.fake_time_env = Some(key.as_ref().to_os_string());
+        self
+    }
+
+    /// The variables implementing 'fake_time()' for the next call, empty when it is not set.
+    pub(crate) fn fake_time_envs(&self) -> Vec<(OsString, OsString)> {
+        match self.fake_time {
+            Some(secs) => fake_clock_envs(
+                secs,
+                self.fake_time_env
+                    .as_deref()
+                    .unwrap_or_else(|| OsStr::new(SOURCE_DATE_EPOCH)),
+                libfaketime().as_deref(),
+                env::var_os(PRELOAD),
+            ),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Returns the path of the libfaketime preload library, None when it is not installed.
+/// 'TESTCALL_LIBFAKETIME' overrides the search of the common install locations.
+pub fn libfaketime() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("TESTCALL_LIBFAKETIME") {
+        return Some(PathBuf::from(path));
+    }
+    if cfg!(windows) {
+        return None;
+    }
+    [
+        format!(
+            "/usr/lib/{}-linux-gnu/faketime/libfaketime.so.1",
+            env::consts::ARCH
+        ),
+        String::from("/usr/lib/faketime/libfaketime.so.1"),
+        String::from("/usr/lib64/faketime/libfaketime.so.1"),
+        String::from("/usr/local/lib/faketime/libfaketime.so.1"),
+        String::from("/opt/homebrew/lib/faketime/libfaketime.1.dylib"),
+        String::from("/usr/local/lib/faketime/libfaketime.1.dylib"),
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .find(|path| path.is_file())
+}
+
+/// Builds the variables freezing the clock at 'secs' since the epoch: 'key' with the
+/// seconds and, when 'library' is given, the libfaketime configuration with the library
+/// put in front of the libraries 'preloaded' already.
+fn fake_clock_envs(
+    secs: u64,
+    key: &OsStr,
+    library: Option<&Path>,
+    preloaded: Option<OsString>,
+) -> Vec<(OsString, OsString)> {
+    let mut envs = vec![(key.to_os_string(), OsString::from(secs.to_string()))];
+    if let Some(library) = library {
+        let mut preload = library.as_os_str().to_os_string();
+        if let Some(preloaded) = preloaded.filter(|preloaded| !preloaded.is_empty()) {
+            preload.push(":");
+            preload.push(preloaded);
+        }
+        envs.push((OsString::from(PRELOAD), preload));
+        #[cfg(target_os = "macos")]
+        envs.push((
+            OsString::from("DYLD_FORCE_FLAT_NAMESPACE"),
+            OsString::from("1"),
+        ));
+        // an absolute time without '@' stops the clock
+        envs.push((OsString::from("FAKETIME_FMT"), OsString::from("%s")));
+        envs.push((OsString::from("FAKETIME"), OsString::from(secs.to_string())));
+        envs.push((OsString::from("FAKETIME_NO_CACHE"), OsString::from("1")));
+    }
+    envs
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use super::{fake_clock_envs, libfaketime, PRELOAD};
+    use crate::*;
+    use std::ffi::{OsStr, OsString};
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn envs() {
+        assert_eq!(
+            fake_clock_envs(86400, OsStr::new("SOURCE_DATE_EPOCH"), None, None),
+            [(OsString::from("SOURCE_DATE_EPOCH"), OsString::from("86400"))]
+        );
+        let envs = fake_clock_envs(
+            86400,
+            OsStr::new("BUILD_TIME"),
+            Some(Path::new("/lib/libfaketime.so.1")),
+            Some(OsString::from("/lib/other.so")),
+        );
+        assert_eq!(envs[0].0, "BUILD_TIME");
+        assert!(envs.contains(&(
+            OsString::from(PRELOAD),
+            OsString::from("/lib/libfaketime.so.1:/lib/other.so")
+        )));
+        assert!(envs.contains(&(OsString::from("FAKETIME"), OsString::from("86400"))));
+    }
+
+    #[test]
+    fn source_date_epoch() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.fake_time(UNIX_EPOCH + Duration::from_secs(1_000_000_000));
+
+        testcall
+            .call_args(["-c", "echo $SOURCE_DATE_EPOCH"])
+            .assert_stdout_utf8("^1000000000\n$");
+        testcall
+            .fake_time_env("BUILD_TIME")
+            .call_args(["-c", "echo ${SOURCE_DATE_EPOCH:-unset} $BUILD_TIME"])
+            .assert_stdout_utf8("^unset 1000000000\n$");
+        assert!(!testcall.command_line(NO_ARGS, NO_ENVS).env_cleared());
+    }
+
+    #[test]
+    fn frozen_clock() {
+        if libfaketime().is_none() {
+            return;
+        }
+        let mut testcall = TestCall::external_command(Path::new("date"));
+        testcall.fake_time(UNIX_EPOCH + Duration::from_secs(1_000_000_000));
+
+        testcall
+            .call_args(["-u", "+%s"])
+            .assert_stdout_utf8("^1000000000\n$");
+    }
+}
@@ -0,0 +1,292 @@
+//! Rendering the fully resolved command line of a call without executing it.
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::Duration;
+
+use crate::history;
+use crate::testcall::PreparedCommand;
+use crate::{CallOutput, TestCall};
+
+/// Everything a call would execute: the resolved executable, the arguments, the working
+/// directory and the environment. Returned by 'TestCall::command_line()', its Display
+/// implementation renders a shell quoted command line which can be pasted into a terminal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandLine {
+    program: PathBuf,
+    args: Vec<OsString>,
+    current_dir: Option<PathBuf>,
+    envs: BTreeMap<OsString, OsString>,
+    env_cleared: bool,
+    // None removes the variable
+    env_changes: Vec<(OsString, Option<OsString>)>,
+}
+
+impl CommandLine {
+    /// Resolves 'command', 'env_cleared' tells whether it starts with an empty environment.
+    pub(crate) fn new(command: &Command, env_cleared: bool) -> Self {
+        let mut envs: BTreeMap<OsString, OsString> = if env_cleared {
+            BTreeMap::new()
+        } else {
+            env::vars_os().collect()
+        };
+        let env_changes: Vec<(OsString, Option<OsString>)> = command
+            .get_envs()
+            .map(|(key, value)| (key.to_os_string(), value.map(OsStr::to_os_string)))
+            .collect();
+        for (key, value) in &env_changes {
+            match value {
+                Some(value) => envs.insert(key.clone(), value.clone()),
+                None => envs.remove(key),
+            };
+        }
+        let program = search_path(command.get_program(), envs.get(OsStr::new("PATH")));
+        CommandLine {
+            program,
+            args: command.get_args().map(OsStr::to_os_string).collect(),
+            current_dir: command.get_current_dir().map(Path::to_path_buf),
+            envs,
+            env_cleared,
+            env_changes,
+        }
+    }
+
+    /// The executable as resolved through 'BinTest' or the 'PATH' of the call, or the wrapper
+    /// running it.
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    /// The arguments without the program.
+    pub fn args(&self) -> &[OsString] {
+        &self.args
+    }
+
+    /// The working directory, None when the call runs in the test's working directory.
+    pub fn current_dir(&self) -> Option<&Path> {
+        self.current_dir.as_deref()
+    }
+
+    /// The complete environment of the call, sorted by name.
+    pub fn envs(&self) -> &BTreeMap<OsString, OsString> {
+        &self.envs
+    }
+
+    /// The value of the environment variable 'key' as the call would see it.
+    pub fn env<K: AsRef<OsStr>>(&self, key: K) -> Option<&OsStr> {
+        self.envs.get(key.as_ref()).map(OsString::as_os_str)
+    }
+
+    /// Returns true when the call does not inherit the environment of the test.
+    pub fn env_cleared(&self) -> bool {
+        self.env_cleared
+    }
+}
+
+impl Display for CommandLine {
+    /// Renders 'cd <dir> && env -i|-u <key> <key>=<value> <program> <args>', where only the
+    /// variables which differ from the test's environment are given.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(dir) = &self.current_dir {
+            write!(f, "cd {} && ", shell_quote(dir.as_os_str()))?;
+        }
+        if self.env_cleared {
+            f.write_str("env -i ")?;
+            for (key, value) in &self.envs {
+                write!(f, "{} ", env_assignment(key, value))?;
+            }
+        } else {
+            let removed: Vec<&OsString> = self
+                .env_changes
+                .iter()
+                .filter(|(_, value)| value.is_none())
+                .map(|(key, _)| key)
+                .collect();
+            if !removed.is_empty() {
+                f.write_str("env ")?;
+                for key in removed {
+                    write!(f, "-u {} ", shell_quote(key))?;
+                }
+            }
+            for (key, value) in &self.env_changes {
+                if let Some(value) = value {
+                    write!(f, "{} ", env_assignment(key, value))?;
+                }
+            }
+        }
+        f.write_str(&shell_quote(self.program.as_os_str()))?;
+        for arg in &self.args {
+            write!(f, " {}", shell_quote(arg))?;
+        }
+        Ok(())
+    }
+}
+
+/// Resolves a bare 'program' name through the 'PATH' of the call like the operating system
+/// will do, other names are returned as they are.
+fn search_path(program: &OsStr, path: Option<&OsString>) -> PathBuf {
+    let program = Path::new(program);
+    if program.components().count() == 1 {
+        if let Some(path) = path {
+            if let Some(found) = env::split_paths(path)
+                .map(|dir| dir.join(program))
+                .find(|candidate| candidate.is_file())
+            {
+                return found;
+            }
+        }
+    }
+    program.to_path_buf()
+}
+
+/// Quotes 'word' for a POSIX shell, words made only of safe characters are left as is.
+pub(crate) fn shell_quote(word: &OsStr) -> String {
+    let word = word.to_string_lossy();
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-+./:,=@%".contains(c))
+    {
+        word.into_owned()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+fn env_assignment(key: &OsStr, value: &OsStr) -> String {
+    format!("{}={}", key.to_string_lossy(), shell_quote(value))
+}
+
+impl TestCall<'_> {
+    /// Resolves what calling the executable with the given arguments and environment would
+    /// execute, without executing it. For asserting on the argument and environment
+    /// construction of configured TestCalls and for debugging them.
+    #[track_caller]
+    pub fn command_line<IA, S, IE, K, V>(&self, args: IA, envs: IE) -> CommandLine
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+        IE: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        let command = self.command(args, envs);
+        CommandLine::new(&command, command.env_cleared())
+    }
+
+    /// The output of a call in dry run mode: successful with the command line on stdout.
+    pub(crate) fn dry_run_output(&self, command: &PreparedCommand) -> CallOutput {
+        let command_line = CommandLine::new(command, command.env_cleared());
+        let output = Output {
+            status: crate::replay::from_raw_status(0),
+            stdout: format!("{}\n", command_line).into_bytes(),
+            stderr: Vec::new(),
+        };
+        history::record(command, output.status, Duration::ZERO, None);
+        CallOutput::new(output, Duration::ZERO, None)
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use super::shell_quote;
+    use crate::*;
+    use std::ffi::OsStr;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(shell_quote(OsStr::new("--file=a.txt")), "--file=a.txt");
+        assert_eq!(shell_quote(OsStr::new("")), "''");
+        assert_eq!(shell_quote(OsStr::new("two words")), "'two words'");
+        assert_eq!(shell_quote(OsStr::new("it's")), "'it'\\''s'");
+        assert_eq!(shell_quote(OsStr::new("$HOME")), "'$HOME'");
+    }
+
+    #[test]
+    fn command_line() {
+        let dir = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall
+            .current_dir(&dir)
+            .base_args(["-c"])
+            .env("GREETING", "hello world")
+            .env_remove("HOME");
+
+        let command_line = testcall.command_line(["echo $GREETING"], NO_ENVS);
+        assert!(command_line.program().is_absolute());
+        assert_eq!(command_line.args(), ["-c", "echo $GREETING"]);
+        assert_eq!(command_line.current_dir(), Some(dir.path()));
+        assert_eq!(
+            command_line.env("GREETING"),
+            Some(OsStr::new("hello world"))
+        );
+        assert_eq!(command_line.env("HOME"), None);
+        assert!(!command_line.env_cleared());
+        assert!(command_line.envs().contains_key(OsStr::new("PATH")));
+        assert_eq!(
+            command_line.to_string(),
+            format!(
+                "cd {} && env -u HOME GREETING='hello world' {} -c 'echo $GREETING'",
+                dir.path().display(),
+                command_line.program().display()
+            )
+        );
+
+        testcall.coverage_env(false);
+        let command_line = testcall.command_line(["true"], [("ONLY", "this")]);
+        assert!(command_line.env_cleared());
+        assert_eq!(command_line.envs().len(), 2);
+        assert!(command_line
+            .to_string()
+            .contains("env -i GREETING='hello world' ONLY=this "));
+        assert!(testcall
+            .command_line(["true"], [("GREETING", "again")])
+            .env_cleared());
+    }
+
+    #[test]
+    fn dry_run() {
+        let dir = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&dir).dry_run(true);
+
+        testcall
+            .call_args(["-c", "touch created"])
+            .assert_success()
+            .assert_stdout_utf8("^cd .* && /.*/sh -c 'touch created'\n$")
+            .assert_stderr_utf8("^$");
+        assert!(!dir.path().join("created").exists());
+    }
+
+    #[test]
+    fn dry_run_batch_capture_pipe() {
+        let dir = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&dir).dry_run(true);
+
+        let outputs = testcall.call_batch([["-c", "touch one"], ["-c", "touch two"]]);
+        outputs[0].assert_stdout_utf8("sh -c 'touch one'\n$");
+        outputs[1].assert_stdout_utf8("sh -c 'touch two'\n$");
+        testcall
+            .capture_to_dir(&dir, ["-c", "touch three"])
+            .assert_success()
+            .assert_stdout_any_line("sh -c 'touch three'$");
+        let mut cat = TestCall::external_command(Path::new("cat"));
+        cat.current_dir(&dir);
+        testcall
+            .base_args(["-c", "touch four"])
+            .pipe(&cat)
+            .run()
+            .assert_all_success()
+            .assert_stdout_utf8("sh -c 'touch four'\n$");
+        for name in ["one", "two", "three", "four"] {
+            assert!(!dir.path().join(name).exists());
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Collecting crash diagnostics (backtraces and core dumps) of calls which crashed, see
+//! 'TestCall::crash_diagnostics()'.
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use crate::output::{crashed, describe_status};
+
+/// Makes the child of 'command' leave diagnostics when it crashes: Rust programs print a
+/// backtrace unless 'RUST_BACKTRACE' is set already, on unix core dumps are enabled.
+pub(crate) fn prepare(command: &mut Command) {
+    let given = command
+        .get_envs()
+        .any(|(key, _)| key == OsStr::new("RUST_BACKTRACE"));
+    if !given && env::var_os("RUST_BACKTRACE").is_none() {
+        command.env("RUST_BACKTRACE", "1");
+    }
+
+    #[cfg(unix)]
+    // SAFETY: the closure only makes async signal safe syscalls
+    unsafe {
+        use std::os::unix::process::CommandExt;
+        command.pre_exec(|| {
+            let mut limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            // raising the soft limit up to the hard limit is always permitted
+            if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) == 0 {
+                limit.rlim_cur = limit.rlim_max;
+                libc::setrlimit(libc::RLIMIT_CORE, &limit);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// When 'status' is a crash, collects the diagnostics of the child 'pid' which ran in 'cwd'
+/// into 'crash-<pid>' below 'dir' and returns that directory. It holds the 'stderr' with
+/// the backtrace, the 'core' dump when one was written to 'cwd' and a 'report' describing
+/// the crash and where else the core may be.
+pub(crate) fn collect(
+    status: ExitStatus,
+    pid: u32,
+    cwd: &Path,
+    stderr: &[u8],
+    dir: &Path,
+) -> Option<PathBuf> {
+    if !crashed(status) {
+        return None;
+    }
+    let report_dir = dir.join(format!("crash-{}", pid));
+    fs::create_dir_all(&report_dir).ok()?;
+    let _ = fs::write(report_dir.join("stderr"), stderr);
+
+    let mut report = format!("{}\n", describe_status(status));
+    let core = [cwd.join(format!("core.{}", pid)), cwd.join("core")]
+        .iter()
+        .find(|core| core.is_file())
+        .cloned();
+    match core {
+        Some(core) => {
+            let target = report_dir.join("core");
+            if fs::rename(&core, &target).is_err() {
+                let _ = fs::copy(&core, &target).and_then(|_| fs::remove_file(&core));
+            }
+            report.push_str("core dumped to 'core'\n");
+        }
+        None => match fs::read_to_string("/proc/sys/kernel/core_pattern") {
+            Ok(pattern) if pattern.starts_with('|') => report.push_str(&format!(
+                "core handed to {}, see 'coredumpctl' or the handlers documentation\n",
+                pattern.trim()
+            )),
+            Ok(pattern) => report.push_str(&format!(
+                "no core found, core_pattern is {}\n",
+                pattern.trim()
+            )),
+            Err(_) => report.push_str("no core found\n"),
+        },
+    }
+    let _ = fs::write(report_dir.join("report"), report);
+    Some(report_dir)
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    #[test]
+    #[should_panic(expected = "crash diagnostics are in")]
+    fn crash_report() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&tmpdir).crash_diagnostics(true);
+
+        let output = testcall.call_args([
+            "-c",
+            "echo \"backtrace=$RUST_BACKTRACE\" >&2; kill -SEGV $$",
+        ]);
+        let report = output
+            .crash_report()
+            .expect("crash collected")
+            .to_path_buf();
+        assert!(report.starts_with(tmpdir.path()));
+        assert_eq!(
+            std::fs::read_to_string(report.join("stderr")).expect("stderr"),
+            "backtrace=1\n"
+        );
+        assert!(std::fs::read_to_string(report.join("report"))
+            .expect("report")
+            .starts_with("termination by SIGSEGV"));
+        output.assert_success();
+    }
+
+    #[test]
+    fn no_crash_report() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.crash_diagnostics(true);
+
+        assert!(testcall
+            .call_args(["-c", "exit 1"])
+            .crash_report()
+            .is_none());
+    }
+}
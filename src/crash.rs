@@ -0,0 +1,97 @@
+use std::ffi::{OsStr, OsString};
+use std::time::{Duration, Instant};
+
+use crate::failure::Failure;
+use crate::regex::is_match_bytes;
+use crate::{CallOutput, TestCall, TestChild};
+
+impl TestCall<'_> {
+    /// Codifies the crash recovery test of a daemon: spawns the executable with 'args', waits
+    /// until its stdout matches 'kill_after' (at most 'timeout'), kills it hard (SIGKILL on
+    /// unix) and spawns it again with the same arguments. Returns the output of the crashed
+    /// run and the restarted child, recovery assertions on the test directory and the new
+    /// child follow in the test.
+    ///
+    /// 'kill_after' is matched by the selected regex engine. Panics with the output collected
+    /// so far when it does not show up in time.
+    #[track_caller]
+    pub fn crash_and_restart<IA, S>(
+        &self,
+        args: IA,
+        kill_after: &str,
+        timeout: Duration,
+    ) -> (CallOutput, TestChild)
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        // an invalid regex panics here, before anything is spawned
+        is_match_bytes(&[], kill_after);
+        let args: Vec<OsString> = args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_os_string())
+            .collect();
+
+        let mut child = self.spawn_args(&args);
+        let reached = child
+            .capture
+            .stdout()
+            .wait_until(Instant::now() + timeout, |data| {
+                is_match_bytes(data, kill_after)
+            });
+        if !reached {
+            let stdout = child.capture.stdout().snapshot();
+            child.kill();
+            Failure::new(
+                "crash_and_restart",
+                format!(
+                    "stdout did not match {:?} within {:?}, nothing to crash\nstdout was:\n{}",
+                    kill_after,
+                    timeout,
+                    String::from_utf8_lossy(&stdout)
+                ),
+            )
+            .fail();
+        }
+
+        let _ = child.child.kill();
+        let crashed = child.wait();
+        (crashed, self.spawn_args(&args))
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+    use std::time::Duration;
+    use testpath::{PathAssertions, TestPath};
+
+    const DAEMON: &str = "if [ -e journal ]; then echo \"recovered $(cat journal)\"; fi; \
+                          echo 1 > journal; echo committed; exec sleep 10";
+
+    #[test]
+    fn crash_recovery() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&tmpdir);
+
+        let (crashed, mut restarted) =
+            testcall.crash_and_restart(["-c", DAEMON], "committed", Duration::from_secs(5));
+        assert!(!crashed.status.success());
+        crashed.assert_stdout_utf8("^committed\n$");
+        tmpdir.sub_path("journal").assert_exists();
+
+        restarted.wait_for_stdout("^recovered 1$", Duration::from_secs(5));
+        restarted.kill();
+    }
+
+    #[test]
+    #[should_panic(expected = "stdout did not match \"committed\" within 100ms")]
+    fn crash_point_missed() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall.crash_and_restart(["started"], "committed", Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::failure::Failure;
+use crate::{CallOutput, TestCall};
+use std::ffi::OsStr;
+use std::fmt::Write;
+
+/// At most this many varying lines are listed in the failure summary.
+const MAX_REPORTED_LINES: usize = 10;
+
+impl TestCall<'_> {
+    /// Calls the executable 'runs' times with identical 'args' and asserts that exit status,
+    /// stdout and stderr are the same every time. On failure the panic message summarizes
+    /// which lines varied and how often each variant was seen, which helps to hunt down
+    /// thread ordering nondeterminism. Returns the output of the first run.
+    #[track_caller]
+    pub fn assert_deterministic<S: AsRef<OsStr>>(&self, args: &[S], runs: usize) -> CallOutput {
+        self.assert_deterministic_with(args, runs, |output| output.to_vec())
+    }
+
+    /// Like 'assert_deterministic()' but stdout and stderr are passed through 'normalize'
+    /// before comparing, to blank out parts that legitimately vary (timestamps, pids).
+    #[track_caller]
+    pub fn assert_deterministic_with<S, N>(
+        &self,
+        args: &[S],
+        runs: usize,
+        normalize: N,
+    ) -> CallOutput
+    where
+        S: AsRef<OsStr>,
+        N: Fn(&[u8]) -> Vec<u8>,
+    {
+        assert!(runs > 1, "determinism needs at least two runs");
+        let outputs: Vec<CallOutput> = (0..runs).map(|_| self.call_args(args)).collect();
+
+        let mut summary = String::new();
+        let statuses: Vec<String> = outputs.iter().map(|o| o.status.to_string()).collect();
+        if statuses.iter().any(|status| *status != statuses[0]) {
+            summarize(&mut summary, "exit status", &statuses);
+        }
+        for (name, stream) in [("stdout", true), ("stderr", false)] {
+            let texts: Vec<String> = outputs
+                .iter()
+                .map(|output| {
+                    let data = if stream {
+                        &output.stdout
+                    } else {
+                        &output.stderr
+                    };
+                    String::from_utf8_lossy(&normalize(data)).into_owned()
+                })
+                .collect();
+            if texts.iter().any(|text| *text != texts[0]) {
+                let lines: Vec<Vec<String>> = texts
+                    .iter()
+                    .map(|text| text.lines().map(String::from).collect())
+                    .collect();
+                summarize_lines(&mut summary, name, &lines);
+            }
+        }
+
+        if !summary.is_empty() {
+            Failure::new(
+                "assert_deterministic",
+                format!("output not deterministic over {} runs:{}", runs, summary),
+            )
+            .fail();
+        }
+        outputs.into_iter().next().expect("first output")
+    }
+}
+
+/// Lists how often each variant of a value was seen.
+fn summarize(summary: &mut String, what: &str, values: &[String]) {
+    let mut variants: Vec<(&str, usize)> = Vec::new();
+    for value in values {
+        match variants.iter_mut().find(|(seen, _)| seen == value) {
+            Some((_, count)) => *count += 1,
+            None => variants.push((value.as_str(), 1)),
+        }
+    }
+    let _ = write!(
+        summary,
+        "\n  {} varied ({} variants):",
+        what,
+        variants.len()
+    );
+    for (value, count) in variants {
+        let _ = write!(summary, "\n    {}x: {:?}", count, value);
+    }
+}
+
+fn summarize_lines(summary: &mut String, stream: &str, lines: &[Vec<String>]) {
+    let max_lines = lines.iter().map(Vec::len).max().unwrap_or(0);
+    let mut reported = 0;
+    for n in 0..max_lines {
+        let column: Vec<String> = lines
+            .iter()
+            .map(|run| {
+                run.get(n)
+                    .cloned()
+                    .unwrap_or_else(|| String::from("<missing>"))
+            })
+            .collect();
+        if column.iter().any(|line| *line != column[0]) {
+            if reported == MAX_REPORTED_LINES {
+                let _ = write!(summary, "\n  {} has more varying lines", stream);
+                break;
+            }
+            summarize(summary, &format!("{} line {}", stream, n + 1), &column);
+            reported += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn deterministic() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .assert_deterministic(&["same"], 3)
+            .assert_stdout_utf8("same");
+    }
+
+    #[test]
+    #[should_panic(expected = "stdout line 2 varied (2 variants)")]
+    fn nondeterministic() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&tmpdir);
+
+        // alternates between two outputs on successive runs
+        testcall.assert_deterministic(
+            &[
+                "-c",
+                "echo first; if [ -e flag ]; then rm flag; echo B; else touch flag; echo A; fi",
+            ],
+            4,
+        );
+    }
+
+    #[test]
+    fn deterministic_normalized() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall.assert_deterministic_with(&["-c", "echo pid $$"], 3, |output| {
+            output
+                .iter()
+                .filter(|b| !b.is_ascii_digit())
+                .copied()
+                .collect()
+        });
+    }
+}
@@ -0,0 +1,246 @@
+//! Line based unified diffs for failure messages.
+use std::fmt::Write;
+
+/// Lines of context shown around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Remove,
+    Add,
+}
+
+/// Renders the differences between 'expected' and 'actual' as unified diff with '-' for
+/// expected and '+' for actual lines. Lines missing a final newline are marked.
+pub(crate) fn unified(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.split_inclusive('\n').collect();
+    let new: Vec<&str> = actual.split_inclusive('\n').collect();
+    let edits = edits(&old, &new);
+
+    // (edit, line, position in old, position in new)
+    let mut lines = Vec::with_capacity(edits.len());
+    let (mut o, mut n) = (0, 0);
+    for edit in edits {
+        match edit {
+            Edit::Keep => {
+                lines.push((edit, old[o], o, n));
+                o += 1;
+                n += 1;
+            }
+            Edit::Remove => {
+                lines.push((edit, old[o], o, n));
+                o += 1;
+            }
+            Edit::Add => {
+                lines.push((edit, new[n], o, n));
+                n += 1;
+            }
+        }
+    }
+
+    let mut diff = String::new();
+    let mut i = 0;
+    while let Some(first) = (i..lines.len()).find(|&i| lines[i].0 != Edit::Keep) {
+        let start = first.saturating_sub(CONTEXT);
+        // extend the hunk while changes are close enough that their context overlaps
+        let mut end = first;
+        while let Some(next) = (end + 1..lines.len())
+            .take(2 * CONTEXT + 1)
+            .find(|&i| lines[i].0 != Edit::Keep)
+        {
+            end = next;
+        }
+        let end = (end + CONTEXT + 1).min(lines.len());
+
+        let hunk = &lines[start..end];
+        let old_len = hunk.iter().filter(|line| line.0 != Edit::Add).count();
+        let new_len = hunk.iter().filter(|line| line.0 != Edit::Remove).count();
+        let _ = writeln!(
+            diff,
+            "@@ -{},{} +{},{} @@",
+            hunk[0].2 + usize::from(old_len > 0),
+            old_len,
+            hunk[0].3 + usize::from(new_len > 0),
+            new_len
+        );
+        for (edit, line, _, _) in hunk {
+            diff.push(match edit {
+                Edit::Keep => ' ',
+                Edit::Remove => '-',
+                Edit::Add => '+',
+            });
+            diff.push_str(line);
+            if !line.ends_with('\n') {
+                diff.push_str("\n\\ No newline at end of text\n");
+            }
+        }
+        i = end;
+    }
+    diff
+}
+
+/// The shortest edit script turning 'old' into 'new'. Uses the linear space variant of
+/// Myers' algorithm, memory stays proportional to the number of lines.
+fn edits(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old.len() + new.len());
+    diff_into(old, new, &mut edits);
+    // within each run of changes the removals come first, like diff(1) does
+    let mut start = 0;
+    while start < edits.len() {
+        let end = (start..edits.len())
+            .find(|&i| edits[i] == Edit::Keep)
+            .unwrap_or(edits.len());
+        edits[start..end].sort_by_key(|edit| *edit == Edit::Add);
+        start = end + 1;
+    }
+    edits
+}
+
+/// Appends the edits turning 'old' into 'new' to 'edits'.
+fn diff_into(old: &[&str], new: &[&str], edits: &mut Vec<Edit>) {
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    edits.extend(std::iter::repeat_n(Edit::Keep, prefix));
+    if old.is_empty() || new.is_empty() {
+        edits.extend(std::iter::repeat_n(Edit::Remove, old.len()));
+        edits.extend(std::iter::repeat_n(Edit::Add, new.len()));
+    } else {
+        let (x, y) = middle(old, new);
+        diff_into(&old[..x], &new[..y], edits);
+        diff_into(&old[x..], &new[y..], edits);
+    }
+    edits.extend(std::iter::repeat_n(Edit::Keep, suffix));
+}
+
+/// Returns a point on a shortest edit path from the start to the end of 'old' and 'new',
+/// found by searching from both ends until the paths meet. Both must be non-empty and
+/// differ in their first and last lines.
+fn middle(old: &[&str], new: &[&str]) -> (usize, usize) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    // furthest x reached on each diagonal k = x - y, from the front and from the back
+    let mut front = vec![-1; 2 * offset as usize + 1];
+    let mut back = vec![-1; 2 * offset as usize + 1];
+    front[offset as usize + 1] = 0;
+    back[offset as usize + 1] = 0;
+    // diagonals which left the grid are not extended any more
+    let (mut front_start, mut front_end, mut back_start, mut back_end) = (0, 0, 0, 0);
+    for d in 0..=max {
+        let mut k = -d + front_start;
+        while k <= d - front_end {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && front[i - 1] < front[i + 1]) {
+                front[i + 1]
+            } else {
+                front[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            front[i] = x;
+            if x > n {
+                front_end += 2;
+            } else if y > m {
+                front_start += 2;
+            } else if odd {
+                let j = offset + delta - k;
+                if j >= 0
+                    && (j as usize) < back.len()
+                    && back[j as usize] != -1
+                    && x >= n - back[j as usize]
+                {
+                    return (x as usize, y as usize);
+                }
+            }
+            k += 2;
+        }
+
+        let mut k = -d + back_start;
+        while k <= d - back_end {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && back[i - 1] < back[i + 1]) {
+                back[i + 1]
+            } else {
+                back[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            back[i] = x;
+            if x > n {
+                back_end += 2;
+            } else if y > m {
+                back_start += 2;
+            } else if !odd {
+                let j = offset + delta - k;
+                if j >= 0
+                    && (j as usize) < front.len()
+                    && front[j as usize] != -1
+                    && front[j as usize] >= n - x
+                {
+                    let x = front[j as usize];
+                    return (x as usize, (x - (j - offset)) as usize);
+                }
+            }
+            k += 2;
+        }
+    }
+    unreachable!("the paths meet at the latest after (n + m) / 2 steps")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changed_line() {
+        assert_eq!(
+            unified("a\nb\nc\n", "a\nB\nc\n"),
+            "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+    }
+
+    #[test]
+    fn separate_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "one\n2\n3\n4\n5\n6\n7\n8\n9\nten\n";
+        assert_eq!(
+            unified(old, new),
+            "@@ -1,4 +1,4 @@\n-1\n+one\n 2\n 3\n 4\n@@ -7,4 +7,4 @@\n 7\n 8\n 9\n-10\n+ten\n"
+        );
+    }
+
+    #[test]
+    fn large_inputs() {
+        let old: String = (0..100_000).map(|n| format!("{}\n", n)).collect();
+        let new = old.replacen("50000\n", "fifty thousand\n", 1);
+        assert_eq!(
+            unified(&old, &new),
+            "@@ -49998,7 +49998,7 @@\n 49997\n 49998\n 49999\n-50000\n+fifty thousand\n 50001\n 50002\n 50003\n"
+        );
+    }
+
+    #[test]
+    fn missing_newline() {
+        assert_eq!(
+            unified("a\n", "a"),
+            "@@ -1,1 +1,1 @@\n-a\n+a\n\\ No newline at end of text\n"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Rendering of assertion failures. All 'TestOutput' assertions describe what went wrong as
+//! a 'Failure' which is turned into the panic message by the selected 'FailureFormatter'.
+//! Projects can install their own formatter to render failures colored or as JSON for
+//! tooling.
+use std::fmt;
+use std::sync::RwLock;
+
+use crate::history::{self, CallRecord};
+use crate::testcall::env_flag;
+
+/// An assertion that did not hold.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    kind: &'static str,
+    message: String,
+    expected: Option<String>,
+    subject: &'static str,
+    actual: Option<String>,
+    diff: Option<String>,
+    hint: Option<String>,
+    calls: Vec<CallRecord>,
+}
+
+impl Failure {
+    /// Creates a failure of the assertion 'kind' (the method name) described by 'message'.
+    pub(crate) fn new(kind: &'static str, message: String) -> Self {
+        Failure {
+            kind,
+            message,
+            expected: None,
+            subject: "",
+            actual: None,
+            diff: None,
+            hint: None,
+            calls: history::recent(),
+        }
+    }
+
+    /// Adds what the assertion expected, like a regex.
+    pub(crate) fn with_expected(mut self, expected: &str) -> Self {
+        self.expected = Some(String::from(expected));
+        self
+    }
+
+    /// Adds what was actually found in 'subject', like "stdout".
+    pub(crate) fn with_actual(mut self, subject: &'static str, actual: &str) -> Self {
+        self.subject = subject;
+        self.actual = Some(String::from(actual));
+        self
+    }
+
+    /// Adds a unified diff from the expected to the actual value.
+    pub(crate) fn with_diff(mut self, diff: String) -> Self {
+        self.diff = Some(diff);
+        self
+    }
+
+    /// Adds an explanation of where the actual value deviates, like the closest match of a
+    /// regex.
+    pub(crate) fn with_hint(mut self, hint: Option<String>) -> Self {
+        self.hint = hint;
+        self
+    }
+
+    /// Panics with this failure rendered by the current formatter.
+    #[track_caller]
+    pub(crate) fn fail(self) -> ! {
+        crate::report::failure(&self);
+        let message = formatter().format(&self);
+        panic!("{}", message)
+    }
+
+    /// The name of the failed assertion, like "assert_stdout_utf8".
+    pub fn kind(&self) -> &str {
+        self.kind
+    }
+
+    /// What went wrong in a single line.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// What the assertion expected, if it compares against something.
+    pub fn expected(&self) -> Option<&str> {
+        self.expected.as_deref()
+    }
+
+    /// What was checked, like "stdout" or "stderr", empty when there is no actual value.
+    pub fn subject(&self) -> &str {
+        self.subject
+    }
+
+    /// What was actually found, if there is something to show.
+    pub fn actual(&self) -> Option<&str> {
+        self.actual.as_deref()
+    }
+
+    /// A unified diff from the expected to the actual value, for equality assertions.
+    pub fn diff(&self) -> Option<&str> {
+        self.diff.as_deref()
+    }
+
+    /// Where the actual value deviates from the expectation, when this can be determined.
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    /// The most recent calls of the failing test, oldest first. The cause of a failure is
+    /// often an earlier step.
+    pub fn calls(&self) -> &[CallRecord] {
+        &self.calls
+    }
+}
+
+/// The error returned by the 'try_*' counterparts of the assertions, carries the 'Failure'
+/// the panicking version would have reported.
+#[derive(Clone, Debug)]
+pub struct TestError {
+    failure: Box<Failure>,
+}
+
+impl TestError {
+    /// The assertion failure this error describes.
+    pub fn failure(&self) -> &Failure {
+        &self.failure
+    }
+
+    /// Panics like the assertion which returned this error would have done.
+    #[track_caller]
+    pub(crate) fn fail(self) -> ! {
+        self.failure.fail()
+    }
+}
+
+impl From<Failure> for TestError {
+    fn from(failure: Failure) -> Self {
+        TestError {
+            failure: Box::new(failure),
+        }
+    }
+}
+
+impl fmt::Display for TestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&formatter().format(&self.failure))
+    }
+}
+
+impl std::error::Error for TestError {}
+
+/// Renders failures into panic messages.
+pub trait FailureFormatter: Send + Sync {
+    /// Returns the panic message for 'failure'.
+    fn format(&self, failure: &Failure) -> String;
+}
+
+/// The plain text rendering used unless another formatter was selected. Diffs are colored
+/// when 'TESTCALL_COLOR' is set to anything but "0".
+pub struct DefaultFormatter;
+
+impl FailureFormatter for DefaultFormatter {
+    fn format(&self, failure: &Failure) -> String {
+        let mut message = failure.message().to_string();
+        if let Some(diff) = failure.diff() {
+            if env_flag("TESTCALL_COLOR") {
+                message.push_str(&format!(":\n{}", colored(diff)));
+            } else {
+                message.push_str(&format!(":\n{}", diff));
+            }
+        } else {
+            match (failure.expected(), failure.actual()) {
+                (Some(expected), Some(actual)) => {
+                    message.push_str(&format!(
+                        ":\n{}\n{} was:\n{}",
+                        expected,
+                        failure.subject(),
+                        actual
+                    ));
+                }
+                (Some(text), None) | (None, Some(text)) => {
+                    message.push_str(&format!(":\n{}", text));
+                }
+                (None, None) => {}
+            }
+        }
+        if let Some(hint) = failure.hint() {
+            message.push_str(&format!("\n{}", hint));
+        }
+        message.push_str(&history::render_timeline(history::len(), failure.calls()));
+        message
+    }
+}
+
+/// Colors removed lines red, added lines green and hunk headers cyan.
+fn colored(diff: &str) -> String {
+    diff.split_inclusive('\n')
+        .map(|line| {
+            let color = match line.as_bytes().first() {
+                Some(b'-') => "31",
+                Some(b'+') => "32",
+                Some(b'@') => "36",
+                _ => return line.to_string(),
+            };
+            let text = line.strip_suffix('\n').unwrap_or(line);
+            let newline = &line[text.len()..];
+            format!("\x1b[{}m{}\x1b[0m{}", color, text, newline)
+        })
+        .collect()
+}
+
+static FORMATTER: RwLock<&'static dyn FailureFormatter> = RwLock::new(&DefaultFormatter);
+
+/// Selects the formatter for all following failures in this process, the default is
+/// 'DefaultFormatter'.
+pub fn set_failure_formatter(formatter: &'static dyn FailureFormatter) {
+    *FORMATTER.write().expect("lock") = formatter;
+}
+
+fn formatter() -> &'static dyn FailureFormatter {
+    *FORMATTER.read().expect("lock")
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use super::*;
+    use crate::*;
+    use std::path::Path;
+
+    struct Json;
+
+    impl FailureFormatter for Json {
+        fn format(&self, failure: &Failure) -> String {
+            format!(
+                "{{\"kind\":{:?},\"expected\":{:?},\"actual\":{:?},\"calls\":{}}}",
+                failure.kind(),
+                failure.expected().unwrap_or_default(),
+                failure.actual().unwrap_or_default(),
+                failure.calls().len()
+            )
+        }
+    }
+
+    #[test]
+    fn default_format() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+        testcall.call_args(["hello"]);
+
+        let failure = Failure::new("assert_stdout_utf8", String::from("stdout does not match"))
+            .with_expected("^bye")
+            .with_actual("stdout", "hello\n");
+        let message = DefaultFormatter.format(&failure);
+        assert!(message.starts_with("stdout does not match:\n^bye\nstdout was:\nhello\n"));
+        assert!(message.contains("recent calls (oldest first):\n  #1 "));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            colored("@@ -1,2 +1,2 @@\n a\n-b\n+B\n"),
+            "\x1b[36m@@ -1,2 +1,2 @@\x1b[0m\n a\n\x1b[31m-b\x1b[0m\n\x1b[32m+B\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "stdout was:\nfirst\nsecond\n\nmatched up to line 2")]
+    fn closest_match_hint() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["first\\nsecond\\n"])
+            .assert_stdout_utf8("^first\nthird");
+    }
+
+    #[test]
+    fn custom_format() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+        testcall.call_args(["hello"]);
+
+        let failure = Failure::new("assert_stdout_utf8", String::from("stdout does not match"))
+            .with_expected("^bye")
+            .with_actual("stdout", "hello\n");
+        assert_eq!(
+            Json.format(&failure),
+            r#"{"kind":"assert_stdout_utf8","expected":"^bye","actual":"hello\n","calls":1}"#
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::any::Any;
+use std::convert::{TryFrom, TryInto};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Encoding of the length prefix in front of every frame of a binary stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameLength {
+    /// Protobuf style base 128 varint (LEB128), as written by 'write_delimited_to'.
+    Varint,
+    /// 4 byte big endian (network byte order).
+    U32Be,
+    /// 4 byte little endian.
+    U32Le,
+}
+
+/// Splits 'input' into its length prefixed frames. Panics with the frame index and offset
+/// when the stream is malformed or truncated.
+#[track_caller]
+pub fn split_frames(input: &[u8], length: FrameLength) -> Vec<&[u8]> {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while offset < input.len() {
+        let (len, prefix) = match read_length(&input[offset..], length) {
+            Some(found) => found,
+            None => panic!(
+                "frame #{} at offset {}: malformed or truncated length prefix",
+                frames.len(),
+                offset
+            ),
+        };
+        let start = offset + prefix;
+        let end = start.checked_add(len).filter(|end| *end <= input.len());
+        match end {
+            Some(end) => {
+                frames.push(&input[start..end]);
+                offset = end;
+            }
+            None => panic!(
+                "frame #{} at offset {}: truncated, {} bytes announced, {} available",
+                frames.len(),
+                offset,
+                len,
+                input.len() - start
+            ),
+        }
+    }
+    frames
+}
+
+/// Hands every frame of 'input' to 'decoder'. When the decoder panics (e.g. a failed
+/// assertion within it), the panic is reraised with the index and offset of its frame.
+#[track_caller]
+pub fn decode_frames<F>(input: &[u8], length: FrameLength, mut decoder: F)
+where
+    F: FnMut(usize, &[u8]),
+{
+    for (index, frame) in split_frames(input, length).into_iter().enumerate() {
+        let frame_offset = frame.as_ptr() as usize - input.as_ptr() as usize;
+        if let Err(cause) = panic::catch_unwind(AssertUnwindSafe(|| decoder(index, frame))) {
+            panic!(
+                "frame #{} ({} bytes, content at offset {}): {}",
+                index,
+                frame.len(),
+                frame_offset,
+                panic_message(&cause)
+            );
+        }
+    }
+}
+
+pub(crate) fn panic_message(cause: &Box<dyn Any + Send>) -> &str {
+    cause
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| cause.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("panicked without message")
+}
+
+/// Returns the decoded length and the size of the prefix.
+fn read_length(input: &[u8], length: FrameLength) -> Option<(usize, usize)> {
+    match length {
+        FrameLength::Varint => {
+            let mut value: u64 = 0;
+            for (n, byte) in input.iter().take(10).enumerate() {
+                value |= u64::from(byte & 0x7f) << (7 * n);
+                if byte & 0x80 == 0 {
+                    return usize::try_from(value).ok().map(|value| (value, n + 1));
+                }
+            }
+            None
+        }
+        FrameLength::U32Be => Some((
+            u32::from_be_bytes(input.get(..4)?.try_into().ok()?) as usize,
+            4,
+        )),
+        FrameLength::U32Le => Some((
+            u32::from_le_bytes(input.get(..4)?.try_into().ok()?) as usize,
+            4,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn varint() {
+        let mut input = vec![3, b'a', b'b', b'c', 0, 0x80, 0x01];
+        input.extend(std::iter::repeat_n(b'x', 128));
+        let frames = split_frames(&input, FrameLength::Varint);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0], b"abc");
+        assert_eq!(frames[1], b"");
+        assert_eq!(frames[2].len(), 128);
+    }
+
+    #[test]
+    fn u32() {
+        let frames = split_frames(b"\0\0\0\x02hi\0\0\0\x01!", FrameLength::U32Be);
+        assert_eq!(frames, [&b"hi"[..], &b"!"[..]]);
+        let frames = split_frames(b"\x02\0\0\0hi", FrameLength::U32Le);
+        assert_eq!(frames, [&b"hi"[..]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "frame #1 at offset 3: truncated")]
+    fn truncated() {
+        split_frames(&[2, b'o', b'k', 5, b'x'], FrameLength::Varint);
+    }
+
+    #[test]
+    #[should_panic(expected = "frame #1 (1 bytes, content at offset 4): bad frame")]
+    fn decoder_failure() {
+        decode_frames(
+            &[2, b'o', b'k', 1, b'x'],
+            FrameLength::Varint,
+            |_, frame| {
+                assert!(frame != b"x", "bad frame");
+            },
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Auditing the files a child opens, linux only because it uses 'strace'.
+use regex::Regex;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::failure::Failure;
+use crate::{CallOutput, TestCall, NO_ENVS};
+
+/// Files opened by the dynamic loader and the C library of most programs, to be added to the
+/// allowlist for dynamically linked executables.
+pub const SYSTEM_LIBRARY_PATHS: &[&str] = &[
+    "/etc/ld.so.cache",
+    "/lib/**",
+    "/lib64/**",
+    "/usr/lib/**",
+    "/usr/lib64/**",
+    "/usr/lib32/**",
+    "/usr/share/locale/**",
+    "/usr/lib/locale/**",
+];
+
+impl TestCall<'_> {
+    /// Calls the executable with 'args' under 'strace' and expects that every file it and
+    /// its children opened successfully matches one of the 'allowed' glob patterns. In the
+    /// patterns '**' matches anything, '*' anything but '/', '?' a single character but '/'
+    /// and '\\' escapes the next character. '$TESTDIR' is replaced by the directory set with
+    /// 'current_dir()'. Relative paths are resolved against that directory as well, changes
+    /// of the working directory by the child are not tracked. '.' and '..' are resolved
+    /// lexically. Paths relative to other directory descriptors than the working directory
+    /// can not be resolved and always fail. On failure all paths outside of the allowlist
+    /// are listed.
+    #[track_caller]
+    pub fn assert_file_access_within<IA, S, P>(&self, args: IA, allowed: &[P]) -> CallOutput
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+        P: AsRef<str>,
+    {
+        static TRACES: AtomicUsize = AtomicUsize::new(0);
+        let trace = std::env::temp_dir().join(format!(
+            "testcall-strace-{}-{}",
+            process::id(),
+            TRACES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let wrapper: Vec<OsString> = [
+            "strace",
+            "-f",
+            "-qq",
+            "-e",
+            "trace=open,openat,openat2,creat",
+            "-o",
+        ]
+        .iter()
+        .map(OsString::from)
+        .chain([trace.clone().into_os_string(), OsString::from("--")])
+        .collect();
+
+        let output = self.run(self.command_wrapped(&wrapper, args, NO_ENVS), None);
+        let log = fs::read_to_string(&trace).unwrap_or_else(|err| {
+            panic!(
+                "no strace log ({}), is strace installed?\nstderr was:\n{}",
+                err,
+                String::from_utf8_lossy(&output.stderr)
+            )
+        });
+        let _ = fs::remove_file(&trace);
+
+        let testdir = self
+            .dir
+            .map(|dir| dir.path().to_path_buf())
+            .unwrap_or_else(|| std::env::current_dir().expect("current dir"));
+        let outside = outside_allowlist(&log, &testdir, allowed);
+        if !outside.is_empty() {
+            Failure::new(
+                "assert_file_access_within",
+                format!(
+                    "files opened outside of the allowlist:\n  {}",
+                    outside.join("\n  ")
+                ),
+            )
+            .fail();
+        }
+        output
+    }
+}
+
+/// Returns the files opened according to the strace 'log' which match none of the 'allowed'
+/// patterns, sorted and without duplicates. Relative paths are resolved against 'testdir'.
+fn outside_allowlist<P: AsRef<str>>(log: &str, testdir: &Path, allowed: &[P]) -> Vec<String> {
+    let testdir = normalize(testdir);
+    let escaped = glob_escape(&testdir.to_string_lossy());
+    let allowed: Vec<Regex> = allowed
+        .iter()
+        .map(|pattern| glob_regex(&pattern.as_ref().replace("$TESTDIR", &escaped)))
+        .collect();
+
+    let mut outside: Vec<String> = traced_calls(log, OPEN_CALLS)
+        .into_iter()
+        .filter_map(|call| {
+            let path = Path::new(&call.path);
+            match call.dirfd.as_deref() {
+                Some(dirfd) if dirfd != "AT_FDCWD" && path.is_relative() => {
+                    Some(format!("{} (relative to fd {})", call.path, dirfd))
+                }
+                _ => {
+                    let path = normalize(&testdir.join(path));
+                    let path = path.to_string_lossy();
+                    if allowed.iter().any(|regex| regex.is_match(&path)) {
+                        None
+                    } else {
+                        Some(path.into_owned())
+                    }
+                }
+            }
+        })
+        .collect();
+    outside.sort();
+    outside.dedup();
+    outside
+}
+
+/// Resolves '.' and '..' in 'path' lexically, without looking at the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Escapes the glob metacharacters in 'text'.
+fn glob_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Translates a glob pattern into an anchored regex.
+pub(crate) fn glob_regex(glob: &str) -> Regex {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '\\' if chars.peek().is_some() => {
+                let c = chars.next().expect("escaped character");
+                regex.push_str(&regex::escape(&c.to_string()));
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).expect("valid glob")
+}
+
+/// The syscalls which open files.
+const OPEN_CALLS: &[&str] = &["open", "openat", "openat2", "creat"];
+
+/// A successful syscall with a path argument from a 'strace -f' log.
+pub(crate) struct TracedCall {
+    /// The directory descriptor the path is relative to, like "AT_FDCWD" or "3", None for
+    /// calls without one.
+    pub(crate) dirfd: Option<String>,
+    pub(crate) path: String,
+}
+
+/// Extracts the paths of all successful open calls from a 'strace -f' log.
+pub(crate) fn opened_paths(log: &str) -> Vec<String> {
+    traced_calls(log, OPEN_CALLS)
+        .into_iter()
+        .map(|call| call.path)
+        .collect()
+}
+
+/// Extracts all successful calls of the syscalls 'names' from a 'strace -f' log. Their first
+/// argument is the path, optionally after a directory descriptor. Calls interrupted by other
+/// processes are logged as "unfinished" and "resumed" halves.
+pub(crate) fn traced_calls(log: &str, names: &[&str]) -> Vec<TracedCall> {
+    let call = Regex::new(&format!(
+        r#"^(\d*)\s*(?:{})\((?:([^"]*), )?"((?:[^"\\]|\\.)*)""#,
+        names.join("|")
+    ))
+    .expect("valid regex");
+    let resumed = Regex::new(r"^(\d*)\s*<\.\.\. \w+ resumed>").expect("valid regex");
+    let result = Regex::new(r"\)\s+=\s+(-?\d+)").expect("valid regex");
+    let success = |line: &str| {
+        result
+            .captures(line)
+            .is_some_and(|captures| !captures[1].starts_with('-'))
+    };
+
+    let mut unfinished: Vec<(&str, TracedCall)> = Vec::new();
+    let mut calls = Vec::new();
+    for line in log.lines() {
+        if let Some(captures) = call.captures(line) {
+            let pid = captures.get(1).expect("pid").as_str();
+            let traced = TracedCall {
+                dirfd: captures.get(2).map(|dirfd| String::from(dirfd.as_str())),
+                path: captures[3].replace("\\\"", "\"").replace("\\\\", "\\"),
+            };
+            if line.ends_with("<unfinished ...>") {
+                unfinished.push((pid, traced));
+            } else if success(line) {
+                calls.push(traced);
+            }
+        } else if let Some(captures) = resumed.captures(line) {
+            let pid = captures.get(1).expect("pid").as_str();
+            if let Some(index) = unfinished.iter().position(|(waiting, _)| *waiting == pid) {
+                let (_, traced) = unfinished.remove(index);
+                if success(line) {
+                    calls.push(traced);
+                }
+            }
+        }
+    }
+    calls
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob_regex("/etc/ssl/**").is_match("/etc/ssl/certs/ca.pem"));
+        assert!(glob_regex("/tmp/*.conf").is_match("/tmp/a.conf"));
+        assert!(!glob_regex("/tmp/*.conf").is_match("/tmp/sub/a.conf"));
+        assert!(glob_regex("/dev/tty?").is_match("/dev/tty1"));
+        assert!(!glob_regex("/a+b").is_match("/aab"));
+        assert!(glob_regex(r"/a\*/\\").is_match(r"/a*/\"));
+        assert!(!glob_regex(r"/a\*").is_match("/ab"));
+    }
+
+    #[test]
+    fn allowlist() {
+        let log = r#"1  openat(AT_FDCWD, "../../etc/passwd", O_RDONLY) = 3
+1  openat(AT_FDCWD, "sub/../config", O_RDONLY) = 3
+1  openat(AT_FDCWD, "/tmp/te*st/./data", O_RDONLY) = 3
+1  openat(AT_FDCWD, "/tmp/teXst/data", O_RDONLY) = 3
+1  openat(3, "relative", O_RDONLY) = 4
+1  openat(3, "/etc/hosts", O_RDONLY) = 4
+1  open("/tmp/te*st/../te*st2/x", O_RDONLY) = 4
+"#;
+        assert_eq!(
+            outside_allowlist(
+                log,
+                Path::new("/tmp/te*st/sub/.."),
+                &["$TESTDIR/**", "/etc/hosts"]
+            ),
+            [
+                "/etc/passwd",
+                "/tmp/te*st2/x",
+                "/tmp/teXst/data",
+                "relative (relative to fd 3)",
+            ]
+        );
+    }
+
+    #[test]
+    fn strace_log() {
+        let log = r#"4711  openat(AT_FDCWD, "/etc/ld.so.cache", O_RDONLY|O_CLOEXEC) = 3
+4711  openat(AT_FDCWD, "/usr/lib/missing.so", O_RDONLY|O_CLOEXEC) = -1 ENOENT (No such file or directory)
+4712  open("config.toml", O_RDONLY) = 4
+4712  openat(AT_FDCWD, "/home/user/.secret", O_RDONLY <unfinished ...>
+4712  <... openat resumed>) = 5
+4711  creat("out \"quoted\"", 0644) = 6
+"#;
+        assert_eq!(
+            opened_paths(log),
+            [
+                "/etc/ld.so.cache",
+                "config.toml",
+                "/home/user/.secret",
+                "out \"quoted\"",
+            ]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+use crate::{TestCall, TestOutput};
+
+/// Subcommands are not recursed deeper than this.
+const MAX_SUBCOMMAND_DEPTH: usize = 8;
+
+/// A section of a help text, started by a non indented line like 'Options:' or 'USAGE:'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelpSection {
+    /// The title without the trailing colon.
+    pub title: String,
+    /// All lines of the section, including text following the title on the same line.
+    pub lines: Vec<String>,
+}
+
+/// The structure parsed from the '--help' output of a program. Understands the common layout
+/// produced by clap, docopt, argparse and GNU tools.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelpText {
+    sections: Vec<HelpSection>,
+}
+
+impl HelpText {
+    /// Parses a help text. Text before the first section title goes into a section with an
+    /// empty title.
+    pub fn parse(text: &str) -> HelpText {
+        let title = Regex::new(r"^([A-Za-z][A-Za-z -]*):\s*(.*)$").expect("valid regex");
+        let mut sections = vec![HelpSection {
+            title: String::new(),
+            lines: Vec::new(),
+        }];
+
+        for line in text.lines() {
+            if let Some(captures) = title.captures(line) {
+                let mut lines = Vec::new();
+                if !captures[2].is_empty() {
+                    lines.push(String::from(&captures[2]));
+                }
+                sections.push(HelpSection {
+                    title: String::from(&captures[1]),
+                    lines,
+                });
+            } else if !line.trim().is_empty() {
+                sections
+                    .last_mut()
+                    .expect("one section")
+                    .lines
+                    .push(String::from(line));
+            }
+        }
+
+        sections.retain(|section| !(section.title.is_empty() && section.lines.is_empty()));
+        HelpText { sections }
+    }
+
+    /// All sections in order of appearance.
+    pub fn sections(&self) -> &[HelpSection] {
+        &self.sections
+    }
+
+    /// Returns the first section whose title matches 'title' case insensitively.
+    pub fn section(&self, title: &str) -> Option<&HelpSection> {
+        self.sections
+            .iter()
+            .find(|section| section.title.eq_ignore_ascii_case(title))
+    }
+
+    /// All flags documented in the option sections, like '-h' and '--help'.
+    pub fn flags(&self) -> Vec<String> {
+        let flag = Regex::new(r"(?:^|[\s,\[])(--?[A-Za-z0-9][A-Za-z0-9_-]*)").expect("valid regex");
+        let mut flags = Vec::new();
+        for section in self.sections_containing(&["option", "flag", "argument"]) {
+            for line in &section.lines {
+                let line = line.trim_start();
+                if !line.starts_with('-') {
+                    continue;
+                }
+                // only the part before the description, which is separated by two spaces
+                let spec = line.split("  ").next().unwrap_or_default();
+                for captures in flag.captures_iter(spec) {
+                    let found = String::from(&captures[1]);
+                    if !flags.contains(&found) {
+                        flags.push(found);
+                    }
+                }
+            }
+        }
+        flags
+    }
+
+    /// All subcommands listed in the command sections.
+    pub fn subcommands(&self) -> Vec<String> {
+        let mut subcommands = Vec::new();
+        for section in self.sections_containing(&["command"]) {
+            for line in &section.lines {
+                if let Some(name) = line.split_whitespace().next() {
+                    let name = name.trim_end_matches(',');
+                    if !name.starts_with('-') && !subcommands.iter().any(|s| s == name) {
+                        subcommands.push(String::from(name));
+                    }
+                }
+            }
+        }
+        subcommands
+    }
+
+    /// Asserts that the flags documented in the help text are exactly the flags in
+    /// 'reference'. Order does not matter, on mismatch the panic message lists the flags
+    /// missing on either side.
+    #[track_caller]
+    pub fn assert_flags<S: AsRef<str>>(&self, reference: &[S]) -> &Self {
+        let flags = self.flags();
+        let reference: Vec<&str> = reference.iter().map(|s| s.as_ref()).collect();
+
+        let missing_in_help: Vec<&str> = reference
+            .iter()
+            .copied()
+            .filter(|flag| !flags.iter().any(|f| f == flag))
+            .collect();
+        let missing_in_reference: Vec<&str> = flags
+            .iter()
+            .map(String::as_str)
+            .filter(|flag| !reference.contains(flag))
+            .collect();
+
+        if !missing_in_help.is_empty() || !missing_in_reference.is_empty() {
+            let mut message = String::from("help flags and reference differ:");
+            for (title, flags) in [
+                ("in reference but missing in --help", &missing_in_help),
+                ("in --help but missing in reference", &missing_in_reference),
+            ] {
+                if !flags.is_empty() {
+                    message.push_str(&format!("\n  {}:", title));
+                    for flag in flags.iter() {
+                        message.push_str(&format!("\n    {}", flag));
+                    }
+                }
+            }
+            panic!("{}", message);
+        }
+        self
+    }
+
+    fn sections_containing<'a>(
+        &'a self,
+        words: &'a [&str],
+    ) -> impl Iterator<Item = &'a HelpSection> + 'a {
+        self.sections.iter().filter(move |section| {
+            let title = section.title.to_ascii_lowercase();
+            words.iter().any(|word| title.contains(word))
+        })
+    }
+}
+
+/// Extracts the flags documented in a manpage. Understands the roff 'man' macros (flags in
+/// '.TP', '.B', '.BR', '.IP' and '.OP' paragraphs) and the 'mdoc' '.Fl' macro.
+pub fn manpage_flags(manpage: &str) -> Vec<String> {
+    let escapes = Regex::new(r"\\f[BIRP]|\\f\(..|\\f\[[^\]]*\]|\\&|\\\(..").expect("valid regex");
+    let flag = Regex::new(r"(?:^|[\s,\[])(--?[A-Za-z0-9][A-Za-z0-9_-]*)").expect("valid regex");
+    let mdoc_flag = Regex::new(r"\bFl\s+(-?[A-Za-z0-9][A-Za-z0-9_-]*)").expect("valid regex");
+
+    let mut flags = Vec::new();
+    let mut add = |found: String| {
+        if !flags.contains(&found) {
+            flags.push(found);
+        }
+    };
+    let mut tagged_paragraph = false;
+
+    for line in manpage.lines() {
+        let line = escapes.replace_all(line, "").replace("\\-", "-");
+        let (mac, rest) = match line.strip_prefix('.') {
+            Some(line) => {
+                let mut parts = line.splitn(2, char::is_whitespace);
+                (
+                    parts.next().unwrap_or_default(),
+                    parts.next().unwrap_or_default(),
+                )
+            }
+            None => ("", line.as_str()),
+        };
+
+        for captures in mdoc_flag.captures_iter(&line) {
+            add(format!("-{}", &captures[1]));
+        }
+
+        let spec = match mac {
+            "TP" => {
+                tagged_paragraph = true;
+                continue;
+            }
+            "B" | "BR" | "BI" | "IP" | "OP" => rest,
+            "" if tagged_paragraph => rest,
+            _ => "",
+        };
+        tagged_paragraph = false;
+
+        let spec = spec.trim_start().trim_start_matches('"');
+        if spec.starts_with('-') {
+            for captures in flag.captures_iter(spec.split("  ").next().unwrap_or_default()) {
+                add(String::from(&captures[1]));
+            }
+        }
+    }
+    flags
+}
+
+impl TestCall<'_> {
+    /// Calls the executable with 'subcommands' followed by '--help', asserts success and
+    /// returns the parsed help text.
+    #[track_caller]
+    pub fn help<S: AsRef<str>>(&self, subcommands: &[S]) -> HelpText {
+        let mut args: Vec<&str> = subcommands.iter().map(|s| s.as_ref()).collect();
+        args.push("--help");
+        let output = self.call_args(&args);
+        output.assert_success();
+        HelpText::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Asserts that the flags in '--help' are the same as documented in the manpage at
+    /// 'path'. See 'manpage_flags()' for the supported formats.
+    #[track_caller]
+    pub fn assert_help_matches_manpage<P: AsRef<Path>>(&self, path: P) -> &Self {
+        let manpage = fs::read_to_string(path.as_ref()).expect("readable manpage");
+        self.help::<&str>(&[])
+            .assert_flags(&manpage_flags(&manpage));
+        self
+    }
+
+    /// Asserts that '--help' works for the executable and recursively for every subcommand
+    /// listed in its help text. Returns every subcommand path that was checked.
+    #[track_caller]
+    pub fn assert_subcommands_help(&self) -> Vec<Vec<String>> {
+        let mut checked = Vec::new();
+        let mut pending = vec![Vec::new()];
+        while let Some(path) = pending.pop() {
+            let help = self.help(&path);
+            assert!(
+                !help.sections().is_empty(),
+                "empty help text for subcommand {:?}",
+                path
+            );
+            if path.len() < MAX_SUBCOMMAND_DEPTH {
+                for subcommand in help.subcommands() {
+                    // 'help help' is usually listed but not interesting
+                    if subcommand == "help" {
+                        continue;
+                    }
+                    let mut subpath = path.clone();
+                    subpath.push(subcommand);
+                    pending.push(subpath);
+                }
+            }
+            checked.push(path);
+        }
+        checked
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CLAP_HELP: &str = "\
+The program
+
+Usage: prog [OPTIONS] <COMMAND>
+
+Commands:
+  build  Builds the thing
+  run    Runs the thing
+  help   Print this message or the help of the given subcommand(s)
+
+Options:
+  -v, --verbose...     More output
+  -c, --config <FILE>  Config file, defaults to --foo
+  -h, --help           Print help
+";
+
+    #[test]
+    fn parse_clap() {
+        let help = HelpText::parse(CLAP_HELP);
+        assert_eq!(help.sections()[0].lines, ["The program"]);
+        assert_eq!(
+            help.section("usage").expect("usage").lines,
+            ["prog [OPTIONS] <COMMAND>"]
+        );
+        assert_eq!(help.subcommands(), ["build", "run", "help"]);
+        assert_eq!(
+            help.flags(),
+            ["-v", "--verbose", "-c", "--config", "-h", "--help"]
+        );
+    }
+
+    #[test]
+    fn manpage() {
+        let manpage = r#".TH PROG 1
+.SH OPTIONS
+.TP
+\fB\-v\fR, \fB\-\-verbose\fR
+More output
+.TP
+.BR \-c ", " \-\-config =\fIFILE\fR
+Config file, mentions \-\-foo
+.B \-h, \-\-help
+.It Fl n
+"#;
+        assert_eq!(
+            manpage_flags(manpage),
+            ["-v", "--verbose", "-c", "--config", "-h", "--help", "-n"]
+        );
+    }
+
+    #[test]
+    fn flags_match() {
+        HelpText::parse(CLAP_HELP).assert_flags(&[
+            "--help",
+            "-h",
+            "-v",
+            "--verbose",
+            "-c",
+            "--config",
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "in --help but missing in reference:\n    --config")]
+    fn flags_drift() {
+        HelpText::parse(CLAP_HELP).assert_flags(&["--help", "-h", "-v", "--verbose", "-c"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn subcommands_help() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let script = tmpdir.path().join("prog");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\n\
+             case \"$*\" in\n\
+             '--help') printf 'Usage: prog\\n\\nCommands:\\n  one  First\\n  two  Second\\n' ;;\n\
+             'one --help') printf 'Usage: prog one\\n\\nCommands:\\n  deep  Deeper\\n' ;;\n\
+             'one deep --help'|'two --help') echo 'Usage: prog sub' ;;\n\
+             *) exit 1 ;;\n\
+             esac\n",
+        )
+        .expect("script written");
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
+            .expect("script executable");
+
+        let testcall = TestCall::external_command(&script);
+        let mut checked = testcall.assert_subcommands_help();
+        checked.sort();
+        assert_eq!(
+            checked,
+            [vec![], vec!["one"], vec!["one", "deep"], vec!["two"]]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Every finished call is recorded in a per thread history. Since each test runs on its own
+//! thread this is the history of the current test. It is used to give context on assertion
+//! failures and can be inspected for meta-assertions like "exactly two calls happened".
+//!
+//! The history belongs to the thread which waited for the call, not to the 'TestCall'.
+//! Calls made from threads a test spawns are recorded in the history of that thread and
+//! are invisible to 'calls()', 'len()' and the timeline appended to failures on the test
+//! thread. Inspect them from within that thread when needed.
+use std::cell::RefCell;
+use std::fmt::{self, Display, Write};
+use std::process::{Command, ExitStatus};
+use std::time::Duration;
+
+/// How many of the most recent calls are appended to assertion failure messages.
+const TIMELINE_LEN: usize = 5;
+
+/// A single finished invocation of an executable.
+#[derive(Clone, Debug)]
+pub struct CallRecord {
+    command: String,
+    status: ExitStatus,
+    duration: Duration,
+    failure: Option<String>,
+}
+
+impl CallRecord {
+    /// The command line as rendered by 'std::process::Command's Debug implementation.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// The exit status of the call.
+    pub fn status(&self) -> ExitStatus {
+        self.status
+    }
+
+    /// Wall clock time from starting the executable until it was waited for.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Why the call itself failed, like exceeding the output limit, None when it did not.
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.as_deref()
+    }
+}
+
+impl Display for CallRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} in {:?}",
+            self.command, self.status, self.duration
+        )?;
+        match &self.failure {
+            Some(failure) => write!(f, " ({})", failure),
+            None => Ok(()),
+        }
+    }
+}
+
+thread_local! {
+    // Tests run each on their own thread, thus the history is per test.
+    static HISTORY: RefCell<Vec<CallRecord>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Records a finished call in the history of the current thread, with the 'failure' of the
+/// call itself when there is one.
+pub(crate) fn record(
+    command: &Command,
+    status: ExitStatus,
+    duration: Duration,
+    failure: Option<&str>,
+) {
+    crate::verbose::call(command, status, duration);
+    let record = CallRecord {
+        command: format!("{:?}", command),
+        status,
+        duration,
+        failure: failure.map(String::from),
+    };
+    crate::report::call(&record);
+    HISTORY.with(|history| history.borrow_mut().push(record));
+}
+
+/// Returns a copy of all calls made so far by the current thread, oldest first. Calls
+/// waited for on other threads are not included, see the module documentation.
+pub fn calls() -> Vec<CallRecord> {
+    HISTORY.with(|history| history.borrow().clone())
+}
+
+/// Returns the number of calls made so far by the current thread.
+pub fn len() -> usize {
+    HISTORY.with(|history| history.borrow().len())
+}
+
+/// Forgets all calls recorded by the current thread.
+pub fn clear() {
+    HISTORY.with(|history| history.borrow_mut().clear());
+}
+
+/// Prints all calls made so far by the current thread to stdout.
+pub fn dump() {
+    HISTORY.with(|history| {
+        for (n, record) in history.borrow().iter().enumerate() {
+            println!("#{} {}", n + 1, record);
+        }
+    });
+}
+
+/// Returns the most recent calls of the current thread which give context to assertion
+/// failures, oldest first.
+pub(crate) fn recent() -> Vec<CallRecord> {
+    HISTORY.with(|history| {
+        let history = history.borrow();
+        let skip = history.len().saturating_sub(TIMELINE_LEN);
+        history[skip..].to_vec()
+    })
+}
+
+/// Renders 'recent' calls as the end of a history which is 'total' calls long, for inclusion
+/// in failure messages. Returns an empty string when nothing was called yet.
+pub(crate) fn render_timeline(total: usize, recent: &[CallRecord]) -> String {
+    if recent.is_empty() {
+        return String::new();
+    }
+    let mut timeline = String::from("\nrecent calls (oldest first):");
+    for (n, record) in recent.iter().enumerate() {
+        let _ = write!(timeline, "\n  #{} {}", total - recent.len() + n + 1, record);
+    }
+    timeline
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn timeline() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+        for n in 0..7 {
+            testcall.call_args(["-c", &format!("exit {}", n)]);
+        }
+
+        let timeline = super::render_timeline(super::len(), &super::recent());
+        assert!(!timeline.contains("#2 "));
+        assert!(timeline.contains("#3 "));
+        assert!(timeline.contains("#7 "));
+        assert!(timeline.contains("exit 6"));
+    }
+
+    #[test]
+    fn calls() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+        testcall.call_args(["-c", "exit 0"]);
+        history::clear();
+
+        testcall.call_args(["-c", "exit 0"]);
+        testcall.spawn_args(["-c", "exit 3"]).wait();
+
+        let calls = history::calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(history::len(), 2);
+        assert!(calls[0].status().success());
+        assert_eq!(calls[1].status().code(), Some(3));
+        assert!(calls[1].command().contains("exit 3"));
+    }
+
+    #[test]
+    fn per_thread() {
+        history::clear();
+
+        std::thread::spawn(|| {
+            TestCall::external_command(Path::new("true")).call();
+            assert_eq!(history::len(), 1);
+        })
+        .join()
+        .expect("called on other thread");
+
+        assert_eq!(history::len(), 0);
+    }
+
+    #[test]
+    fn failure_recorded() {
+        let mut testcall = TestCall::external_command(Path::new("yes"));
+        testcall.max_output(1 << 10);
+        history::clear();
+
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| testcall.call()));
+
+        let calls = history::calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].failure(), Some("stdout output exceeded 1 KiB"));
+        assert!(calls[0]
+            .to_string()
+            .ends_with("(stdout output exceeded 1 KiB)"));
+    }
+
+    #[test]
+    #[should_panic(expected = "recent calls")]
+    fn assertion_includes_timeline() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+        testcall.call_args(["-c", "echo wrong side effect"]);
+        testcall.call_args(["-c", "exit 1"]).assert_success();
+    }
+
+    #[test]
+    #[should_panic(expected = "recent calls (oldest first):\n  #1 ")]
+    fn helper_includes_timeline() {
+        let testcall = TestCall::external_command(Path::new("true"));
+        testcall.call();
+        crate::net::wait_for_port("127.0.0.1:1", std::time::Duration::ZERO);
+    }
+}
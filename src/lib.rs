@@ -10,14 +10,15 @@
 //!
 //! # Initial Example
 //!
-//! ```rust
-//! #[test]
+//! ```rust,no_run
+//! # use bintest::BinTest;
+//! # use testcall::*;
 //! fn myprogram_test() {
 //!     let executables = BinTest::new();
-//!     let mut myprogram = TestCall::new(&executables, "myprogram");
+//!     let myprogram = TestCall::new(&executables, "myprogram");
 //!
 //!     myprogram
-//!         .call(["--version"])
+//!         .call_args(["--version"])
 //!         .assert_success()
 //!         .assert_stdout_utf8("myprogram 0.1.*");
 //! }
@@ -36,14 +37,82 @@
 //! augmenting standard/existing things assert capture regex
 //! ## Regular Expressions and Captures
 //!
-//!
-//!
+//! Output is matched with the 'regex' crate by default. Expectations which need
+//! backreferences or look-around can switch to 'regex::FancyEngine' ('fancy' feature) or
+//! 'regex::Pcre2Engine' ('pcre' feature) with 'regex::set_regex_engine()'.
 //!
 //! ## TestCall
 //!
 //! Allows setting up and calling programs build by your project through the 'bintest' crate
 //! or any other executable. Augments 'std::process::Command'. The result of running tests is
-//! collected and returned in a 'std::process::Output'.
+//! collected and returned in a 'CallOutput' which dereferences to 'std::process::Output' and
+//! additionally carries the wall clock duration and, on unix, the consumed CPU time and peak
+//! memory (RSS).
+//! Configured TestCalls can be cloned to serve as templates for similar tests.
+//! Programs on windows write "\r\n" line endings, 'TestCall::normalize_newlines()' converts
+//! them so the same assertions hold on every platform.
+//! With the 'pty' feature on unix, 'TestCall::pty()' runs programs on a pseudo terminal to
+//! test behavior which depends on 'isatty()'.
+//! 'TestCall::crash_diagnostics()' collects backtraces and core dumps of crashing calls.
+//! On unix 'TestCall::run_as()' and 'TestCall::run_via_sudo()' run calls as another user.
+//! 'TestCall::replay()' records outputs of expensive calls once and replays them later.
+//! 'TestCall::fake_time()' freezes the time programs see, through 'SOURCE_DATE_EPOCH' and
+//! libfaketime when installed.
+//! 'TestCall::command_line()' and 'TestCall::dry_run()' show what a call would execute.
+//! Filter programs are tested in compositions with 'TestCall::pipe()', which connects
+//! TestCalls like a shell pipe.
+//!
+//!
+//! ## SharedResource
+//!
+//! A named lock which serializes tests that must not run concurrently, also across the
+//! processes of different test binaries.
+//!
+//!
+//! ## Shims
+//!
+//! On unix 'TestCall::install_shim()' puts scripted fake executables in front of 'PATH' and
+//! records how they are invoked, to test how a program calls tools like 'git' or 'ssh'.
+//!
+//!
+//! ## Signals
+//!
+//! On unix the 'signal' module adds sending signals and scripted signal sequences to
+//! 'TestChild', for testing configuration reloads and shutdown behavior of daemons.
+//!
+//!
+//! ## File Access Audit
+//!
+//! On linux 'TestCall::assert_file_access_within()' traces a call with 'strace' and checks
+//! that all opened files match an allowlist of glob patterns.
+//! With the 'trace' feature 'TestCall::call_traced()' keeps the trace for assertions on
+//! single files and executed programs, like 'assert_opened("config.toml")'.
+//!
+//!
+//! ## Process Trees
+//!
+//! On linux 'TestChild::observe_process_tree()' samples the processes a child spawns, for
+//! asserting on tools which orchestrate worker subprocesses.
+//!
+//!
+//! ## Servers
+//!
+//! The 'net' module allocates free ports for servers under test and waits until a spawned
+//! server accepts connections or answers HTTP requests, instead of sleeping.
+//! On unix its 'SocketScript' serves scripted replies on a unix domain socket in the test
+//! directory and records what the tested program sends.
+//!
+//!
+//! ## Crash Recovery
+//!
+//! 'TestCall::crash_and_restart()' kills a daemon hard once a given point shows up on its
+//! output and starts it again, for testing that it recovers its state.
+//!
+//!
+//! ## Argument Matrix
+//!
+//! 'TestCall::assert_arg_matrix()' runs all or pairwise combinations of alternative
+//! arguments with shared assertions and reports every failing combination.
 //!
 //!
 //! ## TestOutput
@@ -51,7 +120,68 @@
 //! A Trait that augments 'std::process::Output' with assertions and regex capturing functions
 //! to validate the result of a test run. Note that 'std::process::Output' stores the results
 //! of a call in memory. Thus testing should not generate excessive outputs (on
-//! stdout/stderr).
+//! stdout/stderr). When an assertion fails, the most recent calls made by the current test
+//! (thread) are appended to the panic message because the cause is often an earlier step.
+//! The full history can be inspected through the 'history' module. How failures are
+//! rendered can be customized with a 'failure::FailureFormatter', the default one colors
+//! diffs when 'TESTCALL_COLOR' is set. Setting 'TESTCALL_REPORT_JUNIT' or
+//! 'TESTCALL_REPORT_TAP' to a file name writes all calls and assertion failures as JUnit XML
+//! or TAP report for CI dashboards, one file per test binary. Setting 'TESTCALL_VERBOSE'
+//! traces every call with its command line, working directory, environment changes, exit
+//! status and duration on stderr, the 'log' feature logs them through the 'log' crate. The
+//! main assertions have 'try_*' counterparts which return a 'TestError' instead of
+//! panicking, for harnesses that collect failures.
+//!
+//!
+//! With the 'netns' feature on linux, calls can be isolated from the network to test that a
+//! program degrades gracefully when offline.
+//! The 'sandbox' feature goes further, 'TestCall::sandbox()' runs calls in fresh mount, PID
+//! and network namespaces where only the system directories and the test directory are
+//! visible.
+//!
+//!
+//! Decoding of binary serialization formats is available behind the 'cbor' and 'msgpack'
+//! features. Both decode into the same 'Value' type which supports JSON pointer lookups.
+//!
+//!
+//! Fixtures can be unpacked from '.tar', '.tar.gz' and '.zip' archives with the 'archives'
+//! feature. Files can be checked by SHA-256 digest, BLAKE3 is available with the 'blake3'
+//! feature.
+//!
+//!
+//! Golden file tests compare output with snapshots stored in the package, see the 'snapshot'
+//! module.
+//!
+//!
+//! ## DirAssertions
+//!
+//! A Trait that augments every 'testpath::TestPath' with further assertions on the content
+//! of test directories, such as waiting for files created by background processes.
+//!
+//!
+//! ## DirFixtures
+//!
+//! A Trait that augments 'testpath::Fixtures' with generators for stress fixtures (symlink
+//! farms, link cycles, pathological file names).
+//!
+//!
+//! ## TestDir
+//!
+//! A temporary test directory which can be kept for inspection when the test fails.
+//!
+//!
+//! ## DirBackend
+//!
+//! A Trait for custom test directory backends (network mounts, FUSE mocks, overlay
+//! filesystems) with setup, before/after call and cleanup hooks. Attached to a TestCall with
+//! 'TestCall::dir_backend()'.
+//!
+//!
+//! ## testcall-probe
+//!
+//! The crate ships a tiny 'testcall-probe' binary which echoes arguments, environment
+//! variables and stdin and exits with a requested code on demand. It is used by testcall's
+//! own platform independent tests and can serve as a controllable target process.
 //!
 //!
 //! # Future Plans
@@ -59,11 +189,95 @@
 //! New features will be added as needed, PR's are welcome. This is work in progress.
 //!
 //!
+#[cfg(feature = "archives")]
+mod archive;
+mod capture;
+mod clock;
+mod cmdline;
+mod coredump;
+mod crash;
+mod determinism;
+mod diff;
+pub mod failure;
+mod frames;
+#[cfg(target_os = "linux")]
+mod fsaudit;
+mod help;
+pub mod history;
+mod loglevel;
+mod matrix;
+pub mod net;
+#[cfg(all(target_os = "linux", feature = "netns"))]
+mod netns;
+pub mod normalize;
 mod output;
+mod pipeline;
+#[cfg(unix)]
+mod privilege;
+#[cfg(target_os = "linux")]
+mod proctree;
+#[cfg(all(unix, feature = "pty"))]
+mod pty;
 pub mod regex;
+mod replay;
+mod report;
+mod resolve;
+mod resource;
+mod rusage;
+#[cfg(all(target_os = "linux", feature = "sandbox"))]
+mod sandbox;
+#[cfg(unix)]
+mod shims;
+#[cfg(unix)]
+pub mod signal;
+pub mod snapshot;
+mod spill;
+mod stackdump;
 mod testcall;
+mod testdir;
+#[cfg(all(target_os = "linux", feature = "trace"))]
+mod trace;
+#[cfg(unix)]
+mod unixsocket;
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+mod value;
+mod verbose;
+mod version;
 
-pub use crate::output::TestOutput;
+pub use crate::capture::OutputPolicy;
+pub use crate::clock::libfaketime;
+pub use crate::cmdline::CommandLine;
+pub use crate::failure::TestError;
+pub use crate::frames::{decode_frames, split_frames, FrameLength};
+#[cfg(target_os = "linux")]
+pub use crate::fsaudit::SYSTEM_LIBRARY_PATHS;
+pub use crate::help::{manpage_flags, HelpSection, HelpText};
+pub use crate::history::CallRecord;
+pub use crate::loglevel::{LogLevel, LogPatterns};
+pub use crate::matrix::Coverage;
+pub use crate::output::{CallOutput, TestOutput};
+pub use crate::pipeline::{Pipeline, PipelineOutput};
+#[cfg(unix)]
+pub use crate::privilege::{can_run_as, can_sudo};
+#[cfg(target_os = "linux")]
+pub use crate::proctree::ProcessTree;
 pub use crate::regex::Captured;
-pub use crate::testcall::{TestCall, TestChild};
+pub use crate::replay::ReplayMode;
+pub use crate::resource::SharedResource;
+pub use crate::rusage::CpuTime;
+#[cfg(unix)]
+pub use crate::shims::{Shim, ShimCall};
+pub use crate::spill::TestOutputFiles;
+pub use crate::testcall::{ConfigVia, TestCall, TestChild};
 pub use crate::testcall::{NO_ARGS, NO_ENVS};
+pub use crate::testdir::{DirAssertions, DirBackend, DirFixtures, DirSnapshot, TestDir};
+#[cfg(all(target_os = "linux", feature = "trace"))]
+pub use crate::trace::SyscallTrace;
+#[cfg(feature = "cbor")]
+pub use crate::value::decode_cbor;
+#[cfg(feature = "msgpack")]
+pub use crate::value::decode_msgpack;
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+pub use crate::value::Value;
+pub use crate::version::extract_version;
+pub use semver;
@@ -0,0 +1,126 @@
+use regex::Regex;
+
+/// Severity of a log line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    const ALL: [LogLevel; 5] = [
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Trace,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The patterns used to detect the level of log lines. Lines are checked from the most severe
+/// level down, the first matching level wins. Lines matching no pattern are unclassified.
+///
+/// The defaults recognize uppercase level tokens anywhere in a line (`[ERROR]`, `WARN foo:`)
+/// and lowercase levels followed by a colon (`error: ...`, `warning: ...`).
+pub struct LogPatterns {
+    patterns: [Option<Regex>; 5],
+}
+
+impl Default for LogPatterns {
+    fn default() -> Self {
+        let regex = |pattern: &str| Some(Regex::new(pattern).expect("valid regex"));
+        LogPatterns {
+            patterns: [
+                regex(r"\b(ERROR|FATAL|CRITICAL)\b|(?i:\b(error|fatal)\s*:)"),
+                regex(r"\b(WARN|WARNING)\b|(?i:\bwarn(ing)?\s*:)"),
+                regex(r"\bINFO\b|(?i:\binfo\s*:)"),
+                regex(r"\bDEBUG\b|(?i:\bdebug\s*:)"),
+                regex(r"\bTRACE\b|(?i:\btrace\s*:)"),
+            ],
+        }
+    }
+}
+
+impl LogPatterns {
+    /// Creates patterns which classify nothing, to be populated with 'LogPatterns::level()'.
+    pub fn empty() -> Self {
+        LogPatterns {
+            patterns: [None, None, None, None, None],
+        }
+    }
+
+    /// Replaces the pattern for 'level'.
+    #[track_caller]
+    pub fn level(mut self, level: LogLevel, regex: &str) -> Self {
+        self.patterns[level.index()] = Some(Regex::new(regex).expect("valid regex"));
+        self
+    }
+
+    /// Returns the level of a single line.
+    pub fn classify(&self, line: &str) -> Option<LogLevel> {
+        LogLevel::ALL.iter().copied().find(|level| {
+            self.patterns[level.index()]
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(line))
+        })
+    }
+
+    /// Classifies all lines of 'input' (lossy converted to utf8), unclassified lines are
+    /// skipped.
+    pub fn classify_lines(&self, input: &[u8]) -> Vec<(LogLevel, String)> {
+        String::from_utf8_lossy(input)
+            .lines()
+            .filter_map(|line| self.classify(line).map(|level| (level, String::from(line))))
+            .collect()
+    }
+
+    /// Returns the lines of 'input' that are classified as 'level'.
+    pub fn lines_at(&self, input: &[u8], level: LogLevel) -> Vec<String> {
+        self.classify_lines(input)
+            .into_iter()
+            .filter(|(found, _)| *found == level)
+            .map(|(_, line)| line)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classify_default() {
+        let patterns = LogPatterns::default();
+        assert_eq!(patterns.classify("[ERROR] broken"), Some(LogLevel::Error));
+        assert_eq!(patterns.classify("error: broken"), Some(LogLevel::Error));
+        assert_eq!(
+            patterns.classify("2021-01-01 WARN module: careful"),
+            Some(LogLevel::Warn)
+        );
+        assert_eq!(patterns.classify("warning: unused"), Some(LogLevel::Warn));
+        assert_eq!(
+            patterns.classify("ERROR: not a warning"),
+            Some(LogLevel::Error)
+        );
+        assert_eq!(patterns.classify("0 errors found"), None);
+        assert_eq!(patterns.classify("plain output"), None);
+    }
+
+    #[test]
+    fn classify_custom() {
+        let patterns = LogPatterns::empty().level(LogLevel::Error, "^E ");
+        assert_eq!(patterns.classify("E something"), Some(LogLevel::Error));
+        assert_eq!(patterns.classify("[ERROR] something"), None);
+        assert_eq!(
+            patterns.lines_at(b"E one\nW two\nE three\n", LogLevel::Error),
+            ["E one", "E three"]
+        );
+    }
+}
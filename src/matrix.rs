@@ -0,0 +1,217 @@
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::failure::Failure;
+use crate::frames::panic_message;
+use crate::{CallOutput, TestCall};
+
+/// Which combinations of an argument matrix are run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coverage {
+    /// Every combination, the product of the sizes of all dimensions.
+    All,
+    /// A reduced set of combinations that still contains every pair of alternatives from
+    /// two different dimensions at least once. Most interaction bugs involve only two
+    /// options, this grows much slower than 'All'.
+    Pairwise,
+}
+
+impl TestCall<'_> {
+    /// Calls the executable with combinations of arguments and applies the shared 'check' to
+    /// each result. Every dimension is a list of mutually exclusive alternatives, each
+    /// alternative is split at ascii whitespace like in 'call_argstr()', an empty alternative
+    /// leaves the dimension out. Examples are '&["", "--verbose"]' or
+    /// '&["--color=never", "--color=always"]'.
+    ///
+    /// All combinations are run even when some fail, then the test panics listing every
+    /// failing combination with its failure. Returns the number of combinations run.
+    #[track_caller]
+    pub fn assert_arg_matrix<F>(
+        &self,
+        dimensions: &[&[&str]],
+        coverage: Coverage,
+        check: F,
+    ) -> usize
+    where
+        F: Fn(&[&str], &CallOutput),
+    {
+        assert!(
+            dimensions.iter().all(|dimension| !dimension.is_empty()),
+            "every dimension needs at least one alternative"
+        );
+        let combinations = match coverage {
+            Coverage::All => all_combinations(dimensions),
+            Coverage::Pairwise => pairwise_combinations(dimensions),
+        };
+
+        let mut failures = String::new();
+        let mut failed = 0;
+        for combination in &combinations {
+            let args: Vec<&str> = combination
+                .iter()
+                .enumerate()
+                .flat_map(|(dimension, &alternative)| {
+                    dimensions[dimension][alternative].split_ascii_whitespace()
+                })
+                .collect();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let output = self.call_args(&args);
+                check(&args, &output);
+            }));
+            if let Err(cause) = result {
+                failed += 1;
+                let message = panic_message(&cause);
+                let _ = write!(
+                    failures,
+                    "\n  {:?}: {}",
+                    args,
+                    message.lines().next().unwrap_or_default()
+                );
+            }
+        }
+
+        if failed != 0 {
+            Failure::new(
+                "assert_arg_matrix",
+                format!(
+                    "{} of {} argument combinations failed:{}",
+                    failed,
+                    combinations.len(),
+                    failures
+                ),
+            )
+            .fail();
+        }
+        combinations.len()
+    }
+}
+
+/// Returns every combination as indices into the dimensions.
+fn all_combinations(dimensions: &[&[&str]]) -> Vec<Vec<usize>> {
+    let mut combinations = vec![Vec::new()];
+    for dimension in dimensions {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                (0..dimension.len()).map(move |alternative| {
+                    let mut combination = combination.clone();
+                    combination.push(alternative);
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+/// Greedily builds combinations until every pair of alternatives is covered. Each new
+/// combination starts with the first uncovered pair, the remaining dimensions pick the
+/// alternative covering the most still uncovered pairs.
+fn pairwise_combinations(dimensions: &[&[&str]]) -> Vec<Vec<usize>> {
+    if dimensions.len() < 3 {
+        return all_combinations(dimensions);
+    }
+
+    // (dimension a, alternative a, dimension b, alternative b) with a < b
+    let mut uncovered = Vec::new();
+    for a in 0..dimensions.len() {
+        for b in a + 1..dimensions.len() {
+            for x in 0..dimensions[a].len() {
+                for y in 0..dimensions[b].len() {
+                    uncovered.push((a, x, b, y));
+                }
+            }
+        }
+    }
+
+    let mut combinations = Vec::new();
+    while let Some(&(a, x, b, y)) = uncovered.first() {
+        let mut combination: Vec<Option<usize>> = vec![None; dimensions.len()];
+        combination[a] = Some(x);
+        combination[b] = Some(y);
+        for dimension in 0..dimensions.len() {
+            if combination[dimension].is_some() {
+                continue;
+            }
+            let gain = |alternative: usize| {
+                uncovered
+                    .iter()
+                    .filter(|&&(a, x, b, y)| {
+                        (a == dimension && x == alternative && combination[b] == Some(y))
+                            || (b == dimension && y == alternative && combination[a] == Some(x))
+                    })
+                    .count()
+            };
+            // max_by_key returns the last maximum, prefer the first alternative on ties
+            let best = (0..dimensions[dimension].len())
+                .rev()
+                .max_by_key(|&alternative| gain(alternative))
+                .unwrap_or(0);
+            combination[dimension] = Some(best);
+        }
+
+        let combination: Vec<usize> = combination.into_iter().flatten().collect();
+        uncovered.retain(|&(a, x, b, y)| !(combination[a] == x && combination[b] == y));
+        combinations.push(combination);
+    }
+    combinations
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(unix)]
+    use crate::TestOutput;
+
+    #[test]
+    fn pairwise_covers_all_pairs() {
+        let dimensions: &[&[&str]] = &[&["a", "b", "c"], &["d", "e"], &["f", "g"], &["h", "i"]];
+        let combinations = pairwise_combinations(dimensions);
+        assert!(combinations.len() < all_combinations(dimensions).len());
+        for a in 0..dimensions.len() {
+            for b in a + 1..dimensions.len() {
+                for x in 0..dimensions[a].len() {
+                    for y in 0..dimensions[b].len() {
+                        assert!(combinations.iter().any(|c| c[a] == x && c[b] == y));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn matrix() {
+        let testcall = TestCall::external_command(std::path::Path::new("echo"));
+
+        let runs = testcall.assert_arg_matrix(
+            &[&["", "-n"], &["one", "two three"]],
+            Coverage::All,
+            |args, output| {
+                let expected = args
+                    .iter()
+                    .filter(|arg| **arg != "-n")
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                assert_eq!(String::from_utf8_lossy(&output.stdout).trim_end(), expected);
+            },
+        );
+        assert_eq!(runs, 4);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    #[should_panic(expected = "2 of 4 argument combinations failed:\n  [\"-n\", \"one\"]")]
+    fn matrix_failures() {
+        let testcall = TestCall::external_command(std::path::Path::new("echo"));
+
+        testcall.assert_arg_matrix(
+            &[&["", "-n"], &["one", "two"]],
+            Coverage::Pairwise,
+            |_, output| {
+                output.assert_stdout_utf8("\n$");
+            },
+        );
+    }
+}
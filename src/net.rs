@@ -0,0 +1,206 @@
+//! Helpers for testing servers: allocating free ports and waiting until a spawned server
+//! accepts connections, instead of sleeping for a guessed time. On unix scripted unix
+//! domain socket servers and clients test daemons and clients which talk over local
+//! sockets.
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::failure::Failure;
+#[cfg(unix)]
+pub use crate::unixsocket::{socket_request, wait_for_socket, SocketScript, SocketServer};
+
+/// How often readiness is probed while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Returns a TCP port on localhost which is free right now, to be passed to a server under
+/// test. Another process may still take it before the server binds it, which is unlikely
+/// since the operating system hands out ephemeral ports in sequence.
+#[track_caller]
+pub fn free_local_port() -> u16 {
+    TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .expect("free port allocated")
+        .port()
+}
+
+/// Waits until 'addr' accepts TCP connections. Panics with the last connection error when
+/// this did not happen within 'timeout'.
+#[track_caller]
+pub fn wait_for_port<A: ToSocketAddrs>(addr: A, timeout: Duration) {
+    let addrs = resolve(addr);
+    let deadline = Instant::now() + timeout;
+    loop {
+        let error = match connect(&addrs, deadline) {
+            Ok(_) => return,
+            Err(err) => err,
+        };
+        if Instant::now() >= deadline {
+            Failure::new(
+                "wait_for_port",
+                format!(
+                    "{:?} did not accept connections within {:?}: {}",
+                    addrs, timeout, error
+                ),
+            )
+            .fail();
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Waits until a GET request of the plain 'http://' 'url' is answered with a 2xx status.
+/// Panics with the last response status or connection error when this did not happen
+/// within 'timeout'.
+#[track_caller]
+pub fn wait_for_http_ok(url: &str, timeout: Duration) {
+    let (host, path) = match url.strip_prefix("http://") {
+        Some(rest) => match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        },
+        None => panic!("only 'http://' urls are supported, got {:?}", url),
+    };
+    let addrs = resolve(if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    });
+    let deadline = Instant::now() + timeout;
+    loop {
+        let error = match get(&addrs, host, path, deadline) {
+            Ok(status)
+                if status
+                    .split(' ')
+                    .nth(1)
+                    .is_some_and(|code| code.starts_with('2')) =>
+            {
+                return
+            }
+            Ok(status) => status,
+            Err(err) => err.to_string(),
+        };
+        if Instant::now() >= deadline {
+            Failure::new(
+                "wait_for_http_ok",
+                format!(
+                    "{} did not answer with success within {:?}, last: {}",
+                    url, timeout, error
+                ),
+            )
+            .fail();
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[track_caller]
+fn resolve<A: ToSocketAddrs>(addr: A) -> Vec<SocketAddr> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs().expect("address resolved").collect();
+    assert!(!addrs.is_empty(), "address resolves to nothing");
+    addrs
+}
+
+/// Connects to the first of 'addrs' accepting the connection, gives up at 'deadline'.
+fn connect(addrs: &[SocketAddr], deadline: Instant) -> io::Result<TcpStream> {
+    let mut error = io::Error::from(io::ErrorKind::TimedOut);
+    for addr in addrs {
+        let left = deadline
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_millis(1));
+        match TcpStream::connect_timeout(addr, left) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => error = err,
+        }
+    }
+    Err(error)
+}
+
+/// Requests 'path' and returns the status line of the response.
+fn get(addrs: &[SocketAddr], host: &str, path: &str, deadline: Instant) -> io::Result<String> {
+    let mut stream = connect(addrs, deadline)?;
+    let left = deadline
+        .saturating_duration_since(Instant::now())
+        .max(Duration::from_millis(1));
+    stream.set_read_timeout(Some(left))?;
+    stream.set_write_timeout(Some(left))?;
+    // a single write, servers may answer and close before reading further parts
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream.write_all(request.as_bytes())?;
+    let mut response = Vec::new();
+    let mut buffer = [0; 256];
+    while !response.contains(&b'\n') {
+        match stream.read(&mut buffer)? {
+            0 => break,
+            n => response.extend_from_slice(&buffer[..n]),
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    match response.lines().next() {
+        Some(status) if status.starts_with("HTTP/") => Ok(status.to_string()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no HTTP status line in the response",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Serves 'status' to every request on a free port after 'delay'.
+    fn serve(status: &'static str, delay: Duration) -> u16 {
+        let port = free_local_port();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let listener = TcpListener::bind(("127.0.0.1", port)).expect("listening");
+            for stream in listener.incoming() {
+                let mut stream = stream.expect("connection accepted");
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request);
+                let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn port() {
+        let port = serve("200 OK", Duration::from_millis(100));
+
+        wait_for_port(("127.0.0.1", port), Duration::from_secs(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "did not accept connections within")]
+    fn port_timeout() {
+        wait_for_port(("127.0.0.1", free_local_port()), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn http_ok() {
+        let port = serve("204 No Content", Duration::from_millis(100));
+
+        wait_for_http_ok(
+            &format!("http://127.0.0.1:{}/health", port),
+            Duration::from_secs(5),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "last: HTTP/1.1 503 Service Unavailable")]
+    fn http_error() {
+        let port = serve("503 Service Unavailable", Duration::ZERO);
+
+        wait_for_http_ok(
+            &format!("http://127.0.0.1:{}", port),
+            Duration::from_millis(300),
+        );
+    }
+}
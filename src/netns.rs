@@ -0,0 +1,131 @@
+//! Running children without network access by moving them into a fresh network namespace.
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use crate::failure::Failure;
+use crate::{CallOutput, TestCall, NO_ENVS};
+
+/// Moves the child into a new network namespace right before it executes. When this is not
+/// permitted (not root) a user namespace mapping the current uid/gid onto themself is
+/// created along, so file ownership stays the same for the child.
+pub(crate) fn isolate(command: &mut Command) {
+    // SAFETY: these calls can not fail
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    // prepared here, allocating between fork and exec is not allowed
+    let uid_map = format!("{} {} 1", uid, uid);
+    let gid_map = format!("{} {} 1", gid, gid);
+
+    // SAFETY: the closure only makes async signal safe syscalls
+    unsafe {
+        command.pre_exec(move || {
+            if libc::unshare(libc::CLONE_NEWNET) == 0 {
+                return Ok(());
+            }
+            if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            write_file(b"/proc/self/setgroups\0", b"deny")?;
+            write_file(b"/proc/self/uid_map\0", uid_map.as_bytes())?;
+            write_file(b"/proc/self/gid_map\0", gid_map.as_bytes())
+        });
+    }
+}
+
+/// Writes 'data' to the file at the nul terminated 'path' without allocating.
+pub(crate) fn write_file(path: &[u8], data: &[u8]) -> io::Result<()> {
+    // SAFETY: 'path' is nul terminated, 'data' is valid for its length
+    unsafe {
+        let fd = libc::open(
+            path.as_ptr() as *const libc::c_char,
+            libc::O_WRONLY | libc::O_CLOEXEC,
+        );
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let written = libc::write(fd, data.as_ptr() as *const libc::c_void, data.len());
+        let result = if written == data.len() as isize {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        };
+        libc::close(fd);
+        result
+    }
+}
+
+impl TestCall<'_> {
+    /// Calls the executable with 'args' without network access and asserts that it degrades
+    /// gracefully: it must exit normally (not by a signal), must not panic (exit code 101 or
+    /// "panicked at" on stderr). Exiting with a failure is fine, most programs can not do
+    /// their work offline. Returns the output for further assertions, like checking for a
+    /// meaningful error message.
+    #[track_caller]
+    pub fn assert_degrades_offline<IA, S>(&self, args: IA) -> CallOutput
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut isolated;
+        let testcall = if self.isolate_network {
+            self
+        } else {
+            // through 'command()' the isolation comes before switching the user
+            isolated = self.clone();
+            isolated.isolate_network = true;
+            &isolated
+        };
+        let output = testcall.run(testcall.command(args, NO_ENVS), None);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.code().is_none()
+            || output.status.code() == Some(101)
+            || stderr.contains("panicked at")
+        {
+            Failure::new(
+                "assert_degrades_offline",
+                format!(
+                    "no graceful degradation without network, exited with {}:\nstderr was:\n{}",
+                    output.status, stderr
+                ),
+            )
+            .fail();
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn isolated() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.isolate_network(true);
+
+        // only the loopback device exists
+        testcall
+            .call_args(["-c", "tail -n +3 /proc/net/dev | cut -d: -f1"])
+            .assert_success()
+            .assert_stdout_utf8("^ *lo\n$");
+    }
+
+    #[test]
+    fn degrades_offline() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall
+            .assert_degrades_offline(["-c", "echo 'error: network unreachable' >&2; exit 1"])
+            .assert_failure();
+    }
+
+    #[test]
+    #[should_panic(expected = "no graceful degradation without network")]
+    fn crashes_offline() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall.assert_degrades_offline(["-c", "kill -SEGV $$"]);
+    }
+}
@@ -0,0 +1,363 @@
+//! Normalizers that transform captured output before it is asserted on.
+use std::path::{Component, Path};
+
+/// Interprets carriage returns, backspaces, tabs and the common VT100 cursor movement and
+/// erase sequences the way a terminal would and returns the final visible text. Programs
+/// with progress bars or spinners can then be asserted on their end state. Color and other
+/// unknown escape sequences are dropped, trailing whitespace on each line is removed.
+pub fn fold_terminal(text: &str) -> String {
+    let mut screen = Screen::default();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => screen.newline(),
+            '\r' => screen.col = 0,
+            '\x08' => screen.col = screen.col.saturating_sub(1),
+            '\t' => screen.col = (screen.col / 8 + 1) * 8,
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    let mut params = String::new();
+                    let mut command = None;
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            command = Some(c);
+                            break;
+                        }
+                        params.push(c);
+                    }
+                    if let Some(command) = command {
+                        screen.csi(&params, command);
+                    }
+                }
+                Some(']') => {
+                    // operating system command, terminated by BEL or ESC \
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            c if c.is_control() => {}
+            c => screen.put(c),
+        }
+    }
+
+    screen
+        .rows
+        .iter()
+        .map(|row| row.iter().collect::<String>().trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Removes ANSI/VT100 escape sequences (colors, cursor movements, terminal titles) and
+/// leaves all other text as is, unlike 'fold_terminal()'.
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            plain.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                // control sequence, parameters and intermediates up to the final byte
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                // operating system command, terminated by BEL or ESC \
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // character set selection takes one more character
+            Some('(' | ')') => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    plain
+}
+
+/// The unicode normalization forms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnicodeForm {
+    /// Canonical composition, 'e' followed by a combining accent becomes 'é'.
+    Nfc,
+    /// Canonical decomposition as produced by macOS filesystems.
+    Nfd,
+}
+
+/// Brings 'text' into the unicode normalization 'form' so that precomposed and decomposed
+/// characters compare equal.
+pub fn unicode(text: &str, form: UnicodeForm) -> String {
+    use unicode_normalization::UnicodeNormalization;
+    match form {
+        UnicodeForm::Nfc => text.nfc().collect(),
+        UnicodeForm::Nfd => text.nfd().collect(),
+    }
+}
+
+/// Brings the literal text of 'regex' into the unicode normalization 'form'. Escapes and
+/// character classes are kept as written, decomposing '[é]' would make it a class of two
+/// code points.
+pub(crate) fn unicode_regex(regex: &str, form: UnicodeForm) -> String {
+    let mut normalized = String::with_capacity(regex.len());
+    let mut literal = String::new();
+    let mut chars = regex.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | '[' => {
+                normalized.push_str(&unicode(&literal, form));
+                literal.clear();
+                normalized.push(c);
+                if c == '\\' {
+                    normalized.extend(chars.next());
+                    continue;
+                }
+                let mut depth = 1;
+                let mut first = true;
+                while depth > 0 {
+                    let c = match chars.next() {
+                        Some(c) => c,
+                        None => break,
+                    };
+                    normalized.push(c);
+                    match c {
+                        '\\' => normalized.extend(chars.next()),
+                        '^' if first => continue,
+                        ']' if !first => depth -= 1,
+                        '[' => {
+                            depth += 1;
+                            first = true;
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    first = false;
+                }
+            }
+            _ => literal.push(c),
+        }
+    }
+    normalized.push_str(&unicode(&literal, form));
+    normalized
+}
+
+struct Screen {
+    rows: Vec<Vec<char>>,
+    row: usize,
+    col: usize,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen {
+            rows: vec![Vec::new()],
+            row: 0,
+            col: 0,
+        }
+    }
+}
+
+impl Screen {
+    fn line(&mut self) -> &mut Vec<char> {
+        while self.rows.len() <= self.row {
+            self.rows.push(Vec::new());
+        }
+        &mut self.rows[self.row]
+    }
+
+    fn newline(&mut self) {
+        self.row += 1;
+        self.col = 0;
+        self.line();
+    }
+
+    fn put(&mut self, c: char) {
+        let col = self.col;
+        let line = self.line();
+        if line.len() <= col {
+            line.resize(col, ' ');
+            line.push(c);
+        } else {
+            line[col] = c;
+        }
+        self.col += 1;
+    }
+
+    fn csi(&mut self, params: &str, command: char) {
+        let mut numbers = params
+            .trim_start_matches('?')
+            .split(';')
+            .map(|n| n.parse::<usize>().ok());
+        let first = numbers.next().flatten();
+        let count = first.unwrap_or(1).max(1);
+
+        match command {
+            'A' => self.row = self.row.saturating_sub(count),
+            'B' => self.row += count,
+            'C' => self.col += count,
+            'D' => self.col = self.col.saturating_sub(count),
+            'E' => {
+                self.row += count;
+                self.col = 0;
+            }
+            'F' => {
+                self.row = self.row.saturating_sub(count);
+                self.col = 0;
+            }
+            'G' => self.col = count - 1,
+            'H' | 'f' => {
+                self.row = count - 1;
+                self.col = numbers.next().flatten().unwrap_or(1).max(1) - 1;
+            }
+            'K' => {
+                let col = self.col;
+                let line = self.line();
+                match first.unwrap_or(0) {
+                    0 => line.truncate(col),
+                    1 => line.iter_mut().take(col + 1).for_each(|c| *c = ' '),
+                    _ => line.clear(),
+                }
+            }
+            'J' => match first.unwrap_or(0) {
+                0 => {
+                    let col = self.col;
+                    self.line().truncate(col);
+                    self.rows.truncate(self.row + 1);
+                }
+                1 => {
+                    let col = self.col;
+                    for row in &mut self.rows[..self.row] {
+                        row.clear();
+                    }
+                    self.line().iter_mut().take(col + 1).for_each(|c| *c = ' ');
+                }
+                _ => self.rows.iter_mut().for_each(Vec::clear),
+            },
+            _ => {}
+        }
+        self.line();
+    }
+}
+
+/// Replaces each "\r\n" by "\n", lone carriage returns are kept. Makes output of windows
+/// programs match the same regexes as on unix, see 'TestCall::normalize_newlines()'.
+pub fn unix_newlines(bytes: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().peekable();
+    while let Some(&byte) = iter.next() {
+        if byte != b'\r' || iter.peek() != Some(&&b'\n') {
+            normalized.push(byte);
+        }
+    }
+    normalized
+}
+
+/// Renders 'path' with '/' as separator on every platform, like "C:/tmp/out.txt" on windows.
+/// Backslashes within unix file names are kept since they are not separators there.
+pub fn slash_path<P: AsRef<Path>>(path: P) -> String {
+    let mut rendered = String::new();
+    for component in path.as_ref().components() {
+        match component {
+            Component::Prefix(prefix) => rendered.push_str(&prefix.as_os_str().to_string_lossy()),
+            Component::RootDir => rendered.push('/'),
+            component => {
+                if !rendered.is_empty() && !rendered.ends_with('/') {
+                    rendered.push('/');
+                }
+                rendered.push_str(&component.as_os_str().to_string_lossy());
+            }
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn carriage_return() {
+        assert_eq!(
+            fold_terminal("progress 10%\rprogress 50%\rprogress 100%\ndone\n"),
+            "progress 100%\ndone\n"
+        );
+        assert_eq!(fold_terminal("abcdef\rxy\n"), "xycdef\n");
+        assert_eq!(fold_terminal("abcdef\rxy\x1b[K\n"), "xy\n");
+    }
+
+    #[test]
+    fn cursor_up() {
+        assert_eq!(
+            fold_terminal(
+                "task1 ...\ntask2 ...\n\x1b[2Atask1 ok \n\x1b[1B\x1b[32mall done\x1b[0m\n"
+            ),
+            "task1 ok\ntask2 ...\nall done\n"
+        );
+    }
+
+    #[test]
+    fn ansi() {
+        assert_eq!(
+            strip_ansi("\x1b[1;31merror:\x1b[0m bad\r\n\x1b]0;title\x07\x1b(Bok\x1b[2K"),
+            "error: bad\r\nok"
+        );
+    }
+
+    #[test]
+    fn backspace_and_osc() {
+        assert_eq!(fold_terminal("spin|\x08/\x08-\x08\\\n"), "spin\\\n");
+        assert_eq!(fold_terminal("\x1b]0;title\x07text"), "text");
+    }
+
+    #[test]
+    fn unicode_forms() {
+        let decomposed = "cafe\u{301}";
+        assert_eq!(unicode(decomposed, UnicodeForm::Nfc), "caf\u{e9}");
+        assert_eq!(unicode("caf\u{e9}", UnicodeForm::Nfd), decomposed);
+    }
+
+    #[test]
+    fn unicode_regexes() {
+        assert_eq!(
+            unicode_regex(
+                "^caf\u{e9} [\u{e9}] [^]\u{e9}] [[:alpha:]\u{e9}]",
+                UnicodeForm::Nfd
+            ),
+            "^cafe\u{301} [\u{e9}] [^]\u{e9}] [[:alpha:]\u{e9}]"
+        );
+        assert_eq!(
+            unicode_regex("\\[e\u{301}\\]", UnicodeForm::Nfc),
+            "\\[\u{e9}\\]"
+        );
+    }
+
+    #[test]
+    fn newlines() {
+        assert_eq!(unix_newlines(b"a\r\nb\rc\r\n\r"), b"a\nb\rc\n\r");
+    }
+
+    #[test]
+    fn slash_paths() {
+        assert_eq!(
+            slash_path(Path::new("a").join("b").join("c.txt")),
+            "a/b/c.txt"
+        );
+        assert_eq!(slash_path("/a/./b/../c"), "/a/b/../c");
+        #[cfg(windows)]
+        assert_eq!(slash_path(r"C:\tmp\out.txt"), "C:/tmp/out.txt");
+    }
+}
@@ -1,112 +1,1037 @@
+use semver::{Version, VersionReq};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::process::Output;
+use std::time::Duration;
 
-use crate::Captured;
+use crate::failure::{Failure, TestError};
+use crate::normalize::{fold_terminal, strip_ansi, UnicodeForm};
+use crate::rusage::Usage;
+use crate::{Captured, CpuTime, FrameLength, LogLevel, LogPatterns};
 
 /// Augment std::process::Output with testing and assertions
 pub trait TestOutput {
+    /// Gives access to the underlying 'std::process::Output'.
+    fn output(&self) -> &Output;
+
+    /// The directory with the diagnostics of a crashed call, see
+    /// 'TestCall::crash_diagnostics()'. None when nothing was collected.
+    fn crash_report(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Like 'assert_success()' but returns the failure instead of panicking.
+    fn try_success(&self) -> Result<&Self, TestError> {
+        if !self.output().status.success() {
+            return Err(Failure::new(
+                "assert_success",
+                format!("expected success at exit, got {}", self.output().status),
+            )
+            .with_hint(crash_hint(self.crash_report()))
+            .into());
+        }
+        Ok(self)
+    }
+
     /// Will panic when the program did not exited successful.
     #[track_caller]
-    fn assert_success(&self) -> &Self;
+    fn assert_success(&self) -> &Self {
+        if let Err(err) = self.try_success() {
+            err.fail();
+        }
+        self
+    }
+
+    /// Like 'assert_failure()' but returns the failure instead of panicking.
+    fn try_failure(&self) -> Result<&Self, TestError> {
+        if self.output().status.success() {
+            return Err(
+                Failure::new("assert_failure", String::from("expected failure at exit")).into(),
+            );
+        }
+        Ok(self)
+    }
 
     /// Expects that the program exited with a failure.
     #[track_caller]
-    fn assert_failure(&self) -> &Self;
+    fn assert_failure(&self) -> &Self {
+        if let Err(err) = self.try_failure() {
+            err.fail();
+        }
+        self
+    }
+
+    /// Like 'assert_exitcode()' but returns the failure instead of panicking.
+    fn try_exitcode(&self, code: i32) -> Result<&Self, TestError> {
+        if self.output().status.code() != Some(code) {
+            return Err(
+                Failure::new("assert_exitcode", String::from("unexpected exitcode"))
+                    .with_expected(&code.to_string())
+                    .with_actual("exit status", &self.output().status.to_string())
+                    .into(),
+            );
+        }
+        Ok(self)
+    }
 
     /// Expects that the program exited with the provided code.
     #[track_caller]
-    fn assert_exitcode(&self, code: i32) -> &Self;
+    fn assert_exitcode(&self, code: i32) -> &Self {
+        if let Err(err) = self.try_exitcode(code) {
+            err.fail();
+        }
+        self
+    }
+
+    /// Expects that a wrapper set by 'TestCall::wrap_with()' did not report errors with its
+    /// error exit code 'code', like valgrind with '--error-exitcode=99'. Other exit codes
+    /// are the ones of the program and left to the other assertions.
+    #[track_caller]
+    fn assert_no_wrapper_error(&self, code: i32) -> &Self {
+        if self.output().status.code() == Some(code) {
+            Failure::new(
+                "assert_no_wrapper_error",
+                format!("the wrapper reported errors with exit code {}", code),
+            )
+            .with_actual("stderr", &String::from_utf8_lossy(&self.output().stderr))
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that the program was terminated by 'signal', like 'signal::SIGSEGV' for a
+    /// crash. Unix only.
+    #[cfg(unix)]
+    #[track_caller]
+    fn assert_signaled(&self, signal: i32) -> &Self {
+        use std::os::unix::process::ExitStatusExt;
+        let status = self.output().status;
+        if status.signal() != Some(signal) {
+            Failure::new(
+                "assert_signaled",
+                format!(
+                    "expected termination by {}, got {}",
+                    crate::signal::name(signal),
+                    describe_status(status)
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that the program exited by itself and was not terminated by a signal, thus
+    /// a crash is distinguished from a clean failure exit. Unix only.
+    #[cfg(unix)]
+    #[track_caller]
+    fn assert_not_signaled(&self) -> &Self {
+        let status = self.output().status;
+        if status.code().is_none() {
+            Failure::new(
+                "assert_not_signaled",
+                format!("expected an exit, got {}", describe_status(status)),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that the program did not crash. On unix a crash is a termination by SIGSEGV,
+    /// SIGBUS, SIGILL, SIGFPE, SIGABRT, SIGTRAP or SIGSYS, on windows an exit code which is
+    /// an NTSTATUS error like 0xc0000005 (access violation). Other failures pass.
+    #[track_caller]
+    fn assert_not_crashed(&self) -> &Self {
+        let status = self.output().status;
+        if crashed(status) {
+            Failure::new(
+                "assert_not_crashed",
+                format!("expected no crash, got {}", describe_status(status)),
+            )
+            .with_hint(crash_hint(self.crash_report()))
+            .fail();
+        }
+        self
+    }
+
+    /// Like 'assert_stdout_utf8()' but returns the failure instead of panicking.
+    fn try_stdout_utf8(&self, regex: &str) -> Result<&Self, TestError> {
+        let (ok, utf8) = crate::regex::regex_match_utf8(&self.output().stdout, regex);
+        if !ok {
+            return Err(
+                Failure::new("assert_stdout_utf8", String::from("stdout does not match"))
+                    .with_expected(regex)
+                    .with_actual("stdout", &utf8)
+                    .with_hint(crate::regex::closest_match(&utf8, regex))
+                    .into(),
+            );
+        }
+        Ok(self)
+    }
 
     /// Applies a regex match check to stdout, will panic when the match failed.
     /// This check matches utf8 text, stdout is lossy convered to utf8 first.
     #[track_caller]
-    fn assert_stdout_utf8(&self, regex: &str) -> &Self;
+    fn assert_stdout_utf8(&self, regex: &str) -> &Self {
+        if let Err(err) = self.try_stdout_utf8(regex) {
+            err.fail();
+        }
+        self
+    }
+
+    /// Calls 'check' with the output and fails with 'message' when it returns false, for
+    /// project specific checks within an assertion chain.
+    #[track_caller]
+    fn assert_with<F>(&self, check: F, message: &str) -> &Self
+    where
+        F: FnOnce(&Output) -> bool,
+    {
+        if !check(self.output()) {
+            Failure::new("assert_with", String::from(message)).fail();
+        }
+        self
+    }
+
+    /// Calls 'inspect' with the output, to print or collect something in the middle of an
+    /// assertion chain.
+    fn inspect<F>(&self, inspect: F) -> &Self
+    where
+        F: FnOnce(&Output),
+    {
+        inspect(self.output());
+        self
+    }
+
+    /// Like 'assert_stderr_utf8()' but returns the failure instead of panicking.
+    fn try_stderr_utf8(&self, regex: &str) -> Result<&Self, TestError> {
+        let (ok, utf8) = crate::regex::regex_match_utf8(&self.output().stderr, regex);
+        if !ok {
+            return Err(
+                Failure::new("assert_stderr_utf8", String::from("stderr does not match"))
+                    .with_expected(regex)
+                    .with_actual("stderr", &utf8)
+                    .with_hint(crate::regex::closest_match(&utf8, regex))
+                    .into(),
+            );
+        }
+        Ok(self)
+    }
 
     /// Applies a regex match check to stderr, will panic when the match failed.
     /// This check matches utf8 text, stdout is lossy convered to utf8 first.
     #[track_caller]
-    fn assert_stderr_utf8(&self, regex: &str) -> &Self;
+    fn assert_stderr_utf8(&self, regex: &str) -> &Self {
+        if let Err(err) = self.try_stderr_utf8(regex) {
+            err.fail();
+        }
+        self
+    }
+
+    /// Expects that stdout as a whole matches the shell style wildcard 'pattern', like
+    /// "myprogram 0.1.*". See 'regex::glob()' for the syntax. Matches utf8 text, stdout is
+    /// lossy converted to utf8 first.
+    #[track_caller]
+    fn assert_stdout_glob(&self, pattern: &str) -> &Self {
+        let (ok, utf8) =
+            crate::regex::regex_match_utf8(&self.output().stdout, &crate::regex::glob(pattern));
+        if !ok {
+            Failure::new("assert_stdout_glob", String::from("stdout does not match"))
+                .with_expected(pattern)
+                .with_actual("stdout", &utf8)
+                .fail();
+        }
+        self
+    }
+
+    /// Expects that stderr as a whole matches the shell style wildcard 'pattern', like
+    /// "myprogram 0.1.*". See 'regex::glob()' for the syntax. Matches utf8 text, stderr is
+    /// lossy converted to utf8 first.
+    #[track_caller]
+    fn assert_stderr_glob(&self, pattern: &str) -> &Self {
+        let (ok, utf8) =
+            crate::regex::regex_match_utf8(&self.output().stderr, &crate::regex::glob(pattern));
+        if !ok {
+            Failure::new("assert_stderr_glob", String::from("stderr does not match"))
+                .with_expected(pattern)
+                .with_actual("stderr", &utf8)
+                .fail();
+        }
+        self
+    }
+
+    /// Like 'assert_stdout_bytes()' but returns the failure instead of panicking.
+    fn try_stdout_bytes(&self, regex: &str) -> Result<&Self, TestError> {
+        let (ok, bytes) = crate::regex::regex_match_bytes(&self.output().stdout, regex);
+        if !ok {
+            return Err(
+                Failure::new("assert_stdout_bytes", String::from("stdout does not match"))
+                    .with_expected(regex)
+                    .with_actual("stdout", &bytes)
+                    .into(),
+            );
+        }
+        Ok(self)
+    }
 
     /// Applies a regex match check to stdout, will panic when the match failed.
     /// This check uses the 'bytes' module from the regex package and matches bytes.
     #[track_caller]
-    fn assert_stdout_bytes(&self, regex: &str) -> &Self;
+    fn assert_stdout_bytes(&self, regex: &str) -> &Self {
+        if let Err(err) = self.try_stdout_bytes(regex) {
+            err.fail();
+        }
+        self
+    }
+
+    /// Like 'assert_stderr_bytes()' but returns the failure instead of panicking.
+    fn try_stderr_bytes(&self, regex: &str) -> Result<&Self, TestError> {
+        let (ok, bytes) = crate::regex::regex_match_bytes(&self.output().stderr, regex);
+        if !ok {
+            return Err(
+                Failure::new("assert_stderr_bytes", String::from("stderr does not match"))
+                    .with_expected(regex)
+                    .with_actual("stderr", &bytes)
+                    .into(),
+            );
+        }
+        Ok(self)
+    }
 
     /// Applies a regex match check to stderr, will panic when the match failed.
     /// This check uses the 'bytes' module from the regex package and matches bytes.
     #[track_caller]
-    fn assert_stderr_bytes(&self, regex: &str) -> &Self;
-
-    /// Applies a regex on stdout, returns named captures as CaptureKey:String map.
-    /// Matches utf8 text, input is lossy convered to utf8 first.
-    fn stdout_captures_utf8(&self, regex: &str) -> Captured;
+    fn assert_stderr_bytes(&self, regex: &str) -> &Self {
+        if let Err(err) = self.try_stderr_bytes(regex) {
+            err.fail();
+        }
+        self
+    }
 
-    /// Applies a regex on stderr, returns named captures as CaptureKey:String map.
-    /// Matches utf8 text, input is lossy convered to utf8 first.
-    fn stderr_captures_utf8(&self, regex: &str) -> Captured;
-}
+    /// Like 'assert_stdout_eq()' but returns the failure instead of panicking.
+    fn try_stdout_eq(&self, expected: &str) -> Result<&Self, TestError> {
+        let actual = String::from_utf8_lossy(&self.output().stdout);
+        if self.output().stdout != expected.as_bytes() {
+            return Err(Failure::new(
+                "assert_stdout_eq",
+                String::from("stdout differs (-expected +actual)"),
+            )
+            .with_expected(expected)
+            .with_actual("stdout", &actual)
+            .with_diff(crate::diff::unified(expected, &actual))
+            .into());
+        }
+        Ok(self)
+    }
 
-impl TestOutput for Output {
-    fn assert_success(&self) -> &Self {
-        assert!(self.status.success(), "expected success at exit");
+    /// Expects that stdout equals 'expected' byte for byte, no substring or pattern matching.
+    /// Prints a unified diff on mismatch.
+    #[track_caller]
+    fn assert_stdout_eq(&self, expected: &str) -> &Self {
+        if let Err(err) = self.try_stdout_eq(expected) {
+            err.fail();
+        }
         self
     }
 
-    fn assert_failure(&self) -> &Self {
-        assert!(!self.status.success(), "expected failure at exit");
+    /// Like 'assert_stdout_eq()' but trailing newlines are ignored on both sides.
+    #[track_caller]
+    fn assert_stdout_eq_trimmed(&self, expected: &str) -> &Self {
+        let actual = String::from_utf8_lossy(&self.output().stdout);
+        let (expected, actual) = (
+            expected.trim_end_matches('\n'),
+            actual.trim_end_matches('\n'),
+        );
+        if expected != actual {
+            Failure::new(
+                "assert_stdout_eq_trimmed",
+                String::from("stdout differs (-expected +actual)"),
+            )
+            .with_expected(expected)
+            .with_actual("stdout", actual)
+            .with_diff(crate::diff::unified(expected, actual))
+            .fail();
+        }
         self
     }
 
-    fn assert_exitcode(&self, code: i32) -> &Self {
-        assert_eq!(self.status.code(), Some(code), "unexpected exitcode");
+    /// Like 'assert_stderr_eq()' but returns the failure instead of panicking.
+    fn try_stderr_eq(&self, expected: &str) -> Result<&Self, TestError> {
+        let actual = String::from_utf8_lossy(&self.output().stderr);
+        if self.output().stderr != expected.as_bytes() {
+            return Err(Failure::new(
+                "assert_stderr_eq",
+                String::from("stderr differs (-expected +actual)"),
+            )
+            .with_expected(expected)
+            .with_actual("stderr", &actual)
+            .with_diff(crate::diff::unified(expected, &actual))
+            .into());
+        }
+        Ok(self)
+    }
+
+    /// Expects that stderr equals 'expected' byte for byte, no substring or pattern matching.
+    /// Prints a unified diff on mismatch.
+    #[track_caller]
+    fn assert_stderr_eq(&self, expected: &str) -> &Self {
+        if let Err(err) = self.try_stderr_eq(expected) {
+            err.fail();
+        }
         self
     }
 
-    fn assert_stdout_utf8(&self, regex: &str) -> &Self {
-        let (ok, utf8) = crate::regex::regex_match_utf8(&self.stdout, regex);
-        assert!(
-            ok,
-            "stdout does not match:\n{}\nstdout was:\n{}",
-            regex, utf8
+    /// Like 'assert_stderr_eq()' but trailing newlines are ignored on both sides.
+    #[track_caller]
+    fn assert_stderr_eq_trimmed(&self, expected: &str) -> &Self {
+        let actual = String::from_utf8_lossy(&self.output().stderr);
+        let (expected, actual) = (
+            expected.trim_end_matches('\n'),
+            actual.trim_end_matches('\n'),
         );
+        if expected != actual {
+            Failure::new(
+                "assert_stderr_eq_trimmed",
+                String::from("stderr differs (-expected +actual)"),
+            )
+            .with_expected(expected)
+            .with_actual("stderr", actual)
+            .with_diff(crate::diff::unified(expected, actual))
+            .fail();
+        }
         self
     }
 
-    fn assert_stderr_utf8(&self, regex: &str) -> &Self {
-        let (ok, utf8) = crate::regex::regex_match_utf8(&self.stderr, regex);
-        assert!(
-            ok,
-            "stderr does not match:\n{}\nstderr was:\n{}",
-            regex, utf8
-        );
+    /// Expects that the regexes in 'patterns' match successive lines of stdout in order.
+    /// Lines which match no pattern may appear in between. Each pattern is matched against a
+    /// single line without its line terminator, stdout is lossy converted to utf8 first.
+    #[track_caller]
+    fn assert_stdout_lines<IP, P>(&self, patterns: IP) -> &Self
+    where
+        IP: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        let text = String::from_utf8_lossy(&self.output().stdout);
+        if let Some(missing) = crate::regex::match_lines(&text, patterns) {
+            Failure::new(
+                "assert_stdout_lines",
+                format!(
+                    "stdout lines do not match in order, missing after line {}",
+                    missing.0
+                ),
+            )
+            .with_expected(&missing.1)
+            .with_actual("stdout", &text)
+            .fail();
+        }
         self
     }
 
-    fn assert_stdout_bytes(&self, regex: &str) -> &Self {
-        let (ok, bytes) = crate::regex::regex_match_bytes(&self.stdout, regex);
-        assert!(
-            ok,
-            "stdout does not match:\n{}\nstdout was:\n{}",
-            regex, bytes
+    /// Expects that the regexes in 'patterns' match successive lines of stderr in order.
+    /// Lines which match no pattern may appear in between. Each pattern is matched against a
+    /// single line without its line terminator, stderr is lossy converted to utf8 first.
+    #[track_caller]
+    fn assert_stderr_lines<IP, P>(&self, patterns: IP) -> &Self
+    where
+        IP: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        let text = String::from_utf8_lossy(&self.output().stderr);
+        if let Some(missing) = crate::regex::match_lines(&text, patterns) {
+            Failure::new(
+                "assert_stderr_lines",
+                format!(
+                    "stderr lines do not match in order, missing after line {}",
+                    missing.0
+                ),
+            )
+            .with_expected(&missing.1)
+            .with_actual("stderr", &text)
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that 'regex' matches nowhere in stdout, for asserting the absence of warnings
+    /// or errors. Reports the offending span on failure. Matches utf8 text, stdout is lossy
+    /// converted to utf8 first.
+    #[track_caller]
+    fn assert_stdout_not_matches(&self, regex: &str) -> &Self {
+        let text = String::from_utf8_lossy(&self.output().stdout);
+        if let Some((range, span)) = crate::regex::find_utf8(&text, regex) {
+            Failure::new(
+                "assert_stdout_not_matches",
+                format!("stdout unexpectedly matches at {:?}: {:?}", range, span),
+            )
+            .with_expected(regex)
+            .with_actual("stdout", &text)
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that 'regex' matches nowhere in stderr, for asserting the absence of warnings
+    /// or errors. Reports the offending span on failure. Matches utf8 text, stderr is lossy
+    /// converted to utf8 first.
+    #[track_caller]
+    fn assert_stderr_not_matches(&self, regex: &str) -> &Self {
+        let text = String::from_utf8_lossy(&self.output().stderr);
+        if let Some((range, span)) = crate::regex::find_utf8(&text, regex) {
+            Failure::new(
+                "assert_stderr_not_matches",
+                format!("stderr unexpectedly matches at {:?}: {:?}", range, span),
+            )
+            .with_expected(regex)
+            .with_actual("stderr", &text)
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that stdout equals the stored snapshot 'name', see the 'snapshot' module
+    /// for storage layout and updating.
+    #[track_caller]
+    fn assert_stdout_snapshot(&self, name: &str) -> &Self {
+        crate::snapshot::assert_snapshot(
+            "assert_stdout_snapshot",
+            name,
+            "stdout",
+            &self.output().stdout,
         );
         self
     }
 
-    fn assert_stderr_bytes(&self, regex: &str) -> &Self {
-        let (ok, bytes) = crate::regex::regex_match_bytes(&self.stderr, regex);
-        assert!(
-            ok,
-            "stderr does not match:\n{}\nstderr was:\n{}",
-            regex, bytes
+    /// Expects that stderr equals the stored snapshot 'name', see the 'snapshot' module
+    /// for storage layout and updating.
+    #[track_caller]
+    fn assert_stderr_snapshot(&self, name: &str) -> &Self {
+        crate::snapshot::assert_snapshot(
+            "assert_stderr_snapshot",
+            name,
+            "stderr",
+            &self.output().stderr,
         );
         self
     }
 
+    /// Expects that the program wrote at most 'bytes' bytes to stdout. Useful to enforce
+    /// quiet by default behavior ('0').
+    #[track_caller]
+    fn assert_stdout_at_most(&self, bytes: usize) -> &Self {
+        let len = self.output().stdout.len();
+        if len > bytes {
+            Failure::new(
+                "assert_stdout_at_most",
+                format!("expected at most {} bytes on stdout, got {}", bytes, len),
+            )
+            .with_actual("stdout", &String::from_utf8_lossy(&self.output().stdout))
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that the program wrote at most 'bytes' bytes to stderr.
+    #[track_caller]
+    fn assert_stderr_at_most(&self, bytes: usize) -> &Self {
+        let len = self.output().stderr.len();
+        if len > bytes {
+            Failure::new(
+                "assert_stderr_at_most",
+                format!("expected at most {} bytes on stderr, got {}", bytes, len),
+            )
+            .with_actual("stderr", &String::from_utf8_lossy(&self.output().stderr))
+            .fail();
+        }
+        self
+    }
+
+    /// Applies a regex on stdout, returns named captures as CaptureKey:String map.
+    /// Matches utf8 text, input is lossy convered to utf8 first.
     fn stdout_captures_utf8(&self, regex: &str) -> Captured {
-        crate::regex::captures_utf8(&self.stdout, regex)
+        crate::regex::captures_utf8(&self.output().stdout, regex)
     }
 
+    /// Applies a regex on stderr, returns named captures as CaptureKey:String map.
+    /// Matches utf8 text, input is lossy convered to utf8 first.
     fn stderr_captures_utf8(&self, regex: &str) -> Captured {
-        crate::regex::captures_utf8(&self.stderr, regex)
+        crate::regex::captures_utf8(&self.output().stderr, regex)
+    }
+
+    /// Applies a regex on stdout and returns the captures of every match, in order.
+    /// Matches utf8 text, input is lossy convered to utf8 first.
+    fn stdout_captures_all_utf8(&self, regex: &str) -> Vec<Captured> {
+        crate::regex::captures_iter_utf8(&self.output().stdout, regex)
+    }
+
+    /// Applies a regex on stderr and returns the captures of every match, in order.
+    /// Matches utf8 text, input is lossy convered to utf8 first.
+    fn stderr_captures_all_utf8(&self, regex: &str) -> Vec<Captured> {
+        crate::regex::captures_iter_utf8(&self.output().stderr, regex)
+    }
+
+    /// Splits stdout into length prefixed frames.
+    #[track_caller]
+    fn stdout_frames(&self, length: FrameLength) -> Vec<&[u8]> {
+        crate::frames::split_frames(&self.output().stdout, length)
+    }
+
+    /// Splits stdout into length prefixed frames and calls 'decoder' with the index and the
+    /// content of each. Assertions failing in the decoder report the index of their frame.
+    #[track_caller]
+    fn assert_stdout_frames<F>(&self, length: FrameLength, decoder: F) -> &Self
+    where
+        F: FnMut(usize, &[u8]),
+    {
+        crate::frames::decode_frames(&self.output().stdout, length, decoder);
+        self
+    }
+
+    /// Decodes the first CBOR item from stdout.
+    #[cfg(feature = "cbor")]
+    #[track_caller]
+    fn stdout_cbor(&self) -> crate::Value {
+        crate::value::decode_cbor(&self.output().stdout)
+    }
+
+    /// Decodes the first MessagePack value from stdout.
+    #[cfg(feature = "msgpack")]
+    #[track_caller]
+    fn stdout_msgpack(&self) -> crate::Value {
+        crate::value::decode_msgpack(&self.output().stdout)
+    }
+
+    /// Extracts the first semantic version from stdout. Panics when there is none.
+    #[track_caller]
+    fn stdout_version(&self) -> Version {
+        crate::version::extract_version(&self.output().stdout).unwrap_or_else(|| {
+            Failure::new("stdout_version", String::from("no version found on stdout"))
+                .with_actual("stdout", &String::from_utf8_lossy(&self.output().stdout))
+                .fail()
+        })
+    }
+
+    /// Expects that the first semantic version on stdout equals 'version', typically
+    /// 'env!("CARGO_PKG_VERSION")'.
+    #[track_caller]
+    fn assert_version_matches(&self, version: &str) -> &Self {
+        let expected = Version::parse(version).expect("valid version");
+        let found = self.stdout_version();
+        if found != expected {
+            Failure::new(
+                "assert_version_matches",
+                format!("expected version {}, found {}", expected, found),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that the first semantic version on stdout satisfies the semver requirement
+    /// 'req' (like ">=1.2, <2" or "^0.3").
+    #[track_caller]
+    fn assert_version_req(&self, req: &str) -> &Self {
+        let req = VersionReq::parse(req).expect("valid version requirement");
+        let found = self.stdout_version();
+        if !req.matches(&found) {
+            Failure::new(
+                "assert_version_req",
+                format!("version {} does not satisfy {}", found, req),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Returns stdout (lossy converted to utf8) as a terminal would finally display it, with
+    /// carriage return overwrites and cursor movements applied. See
+    /// 'normalize::fold_terminal()'.
+    fn stdout_folded(&self) -> String {
+        fold_terminal(&String::from_utf8_lossy(&self.output().stdout))
+    }
+
+    /// Returns stderr as a terminal would finally display it.
+    fn stderr_folded(&self) -> String {
+        fold_terminal(&String::from_utf8_lossy(&self.output().stderr))
+    }
+
+    /// Applies a regex match check to the folded stdout, will panic when the match failed.
+    /// Meant for programs that draw progress bars.
+    #[track_caller]
+    fn assert_stdout_folded_utf8(&self, regex: &str) -> &Self {
+        let folded = self.stdout_folded();
+        let (ok, _) = crate::regex::regex_match_utf8(folded.as_bytes(), regex);
+        if !ok {
+            Failure::new(
+                "assert_stdout_folded_utf8",
+                String::from("folded stdout does not match"),
+            )
+            .with_expected(regex)
+            .with_actual("folded stdout", &folded)
+            .fail();
+        }
+        self
+    }
+
+    /// Returns stdout (lossy converted to utf8) with ANSI escape sequences removed. See
+    /// 'normalize::strip_ansi()'.
+    fn stdout_plain(&self) -> String {
+        strip_ansi(&String::from_utf8_lossy(&self.output().stdout))
+    }
+
+    /// Returns stderr (lossy converted to utf8) with ANSI escape sequences removed.
+    fn stderr_plain(&self) -> String {
+        strip_ansi(&String::from_utf8_lossy(&self.output().stderr))
+    }
+
+    /// Applies a regex match check to stdout with ANSI escape sequences removed, will panic
+    /// when the match failed. Meant for programs that color their output.
+    #[track_caller]
+    fn assert_stdout_utf8_plain(&self, regex: &str) -> &Self {
+        let plain = self.stdout_plain();
+        let (ok, _) = crate::regex::regex_match_utf8(plain.as_bytes(), regex);
+        if !ok {
+            Failure::new(
+                "assert_stdout_utf8_plain",
+                String::from("plain stdout does not match"),
+            )
+            .with_expected(regex)
+            .with_actual("plain stdout", &plain)
+            .fail();
+        }
+        self
+    }
+
+    /// Applies a regex match check to stderr with ANSI escape sequences removed, will panic
+    /// when the match failed.
+    #[track_caller]
+    fn assert_stderr_utf8_plain(&self, regex: &str) -> &Self {
+        let plain = self.stderr_plain();
+        let (ok, _) = crate::regex::regex_match_utf8(plain.as_bytes(), regex);
+        if !ok {
+            Failure::new(
+                "assert_stderr_utf8_plain",
+                String::from("plain stderr does not match"),
+            )
+            .with_expected(regex)
+            .with_actual("plain stderr", &plain)
+            .fail();
+        }
+        self
+    }
+
+    /// Returns stdout (lossy converted to utf8) in unicode normalization 'form'.
+    fn stdout_normalized(&self, form: UnicodeForm) -> String {
+        crate::normalize::unicode(&String::from_utf8_lossy(&self.output().stdout), form)
+    }
+
+    /// Returns stderr (lossy converted to utf8) in unicode normalization 'form'.
+    fn stderr_normalized(&self, form: UnicodeForm) -> String {
+        crate::normalize::unicode(&String::from_utf8_lossy(&self.output().stderr), form)
+    }
+
+    /// Applies a regex match check to stdout after bringing both, stdout and the regex, into
+    /// unicode normalization 'form'. Precomposed and decomposed characters (as produced by
+    /// macOS filenames) then match each other. Character classes in the regex are left as
+    /// written, '[é]' with a precomposed 'é' only matches under 'UnicodeForm::Nfc'.
+    #[track_caller]
+    fn assert_stdout_normalized(&self, regex: &str, form: UnicodeForm) -> &Self {
+        let text = self.stdout_normalized(form);
+        let regex = crate::normalize::unicode_regex(regex, form);
+        let (ok, _) = crate::regex::regex_match_utf8(text.as_bytes(), &regex);
+        if !ok {
+            Failure::new(
+                "assert_stdout_normalized",
+                format!("{:?} normalized stdout does not match", form),
+            )
+            .with_expected(&regex)
+            .with_actual("normalized stdout", &text)
+            .fail();
+        }
+        self
+    }
+
+    /// Applies a regex match check to stderr after bringing both, stderr and the regex, into
+    /// unicode normalization 'form'.
+    #[track_caller]
+    fn assert_stderr_normalized(&self, regex: &str, form: UnicodeForm) -> &Self {
+        let text = self.stderr_normalized(form);
+        let regex = crate::normalize::unicode_regex(regex, form);
+        let (ok, _) = crate::regex::regex_match_utf8(text.as_bytes(), &regex);
+        if !ok {
+            Failure::new(
+                "assert_stderr_normalized",
+                format!("{:?} normalized stderr does not match", form),
+            )
+            .with_expected(&regex)
+            .with_actual("normalized stderr", &text)
+            .fail();
+        }
+        self
+    }
+
+    /// Returns the stderr lines which the default 'LogPatterns' classify as 'level'.
+    fn stderr_log_lines(&self, level: LogLevel) -> Vec<String> {
+        LogPatterns::default().lines_at(&self.output().stderr, level)
+    }
+
+    /// Expects that no stderr line is classified as error by the default 'LogPatterns'.
+    #[track_caller]
+    fn assert_no_errors_logged(&self) -> &Self {
+        self.assert_log_count(&LogPatterns::default(), LogLevel::Error, 0)
+    }
+
+    /// Expects that exactly 'count' stderr lines are classified as warnings by the default
+    /// 'LogPatterns'.
+    #[track_caller]
+    fn assert_warning_count(&self, count: usize) -> &Self {
+        self.assert_log_count(&LogPatterns::default(), LogLevel::Warn, count)
+    }
+
+    /// Expects that exactly 'count' stderr lines are classified as 'level' by 'patterns'.
+    #[track_caller]
+    fn assert_log_count(&self, patterns: &LogPatterns, level: LogLevel, count: usize) -> &Self {
+        let lines = patterns.lines_at(&self.output().stderr, level);
+        if lines.len() != count {
+            Failure::new(
+                "assert_log_count",
+                format!(
+                    "expected {} {:?} lines on stderr, found {}",
+                    count,
+                    level,
+                    lines.len()
+                ),
+            )
+            .with_actual("stderr", &lines.join("\n"))
+            .fail();
+        }
+        self
+    }
+}
+
+pub(crate) fn crash_hint(report: Option<&Path>) -> Option<String> {
+    report.map(|dir| format!("crash diagnostics are in {:?}", dir))
+}
+
+/// Whether 'status' is the result of a crash, see 'TestOutput::assert_not_crashed()'.
+#[cfg(unix)]
+pub(crate) fn crashed(status: std::process::ExitStatus) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    matches!(
+        status.signal(),
+        Some(
+            libc::SIGSEGV
+                | libc::SIGBUS
+                | libc::SIGILL
+                | libc::SIGFPE
+                | libc::SIGABRT
+                | libc::SIGTRAP
+                | libc::SIGSYS
+        )
+    )
+}
+
+#[cfg(not(unix))]
+pub(crate) fn crashed(status: std::process::ExitStatus) -> bool {
+    // windows exit codes are u32, NTSTATUS errors have both severity bits set
+    status
+        .code()
+        .is_some_and(|code| code as u32 & 0xc000_0000 == 0xc000_0000)
+}
+
+/// Renders an exit status with signal names, like "termination by SIGSEGV (core dumped)".
+#[cfg(unix)]
+pub(crate) fn describe_status(status: std::process::ExitStatus) -> String {
+    use std::os::unix::process::ExitStatusExt;
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exit code {}", code),
+        (None, Some(signal)) => format!(
+            "termination by {}{}",
+            crate::signal::name(signal),
+            if status.core_dumped() {
+                " (core dumped)"
+            } else {
+                ""
+            }
+        ),
+        (None, None) => status.to_string(),
+    }
+}
+
+/// Renders an exit status with the names of common NTSTATUS crash codes, like
+/// "exit code 0xc0000005 (STATUS_ACCESS_VIOLATION)".
+#[cfg(not(unix))]
+pub(crate) fn describe_status(status: std::process::ExitStatus) -> String {
+    let code = match status.code() {
+        Some(code) => code as u32,
+        None => return status.to_string(),
+    };
+    let name = match code {
+        0xc000_0005 => "STATUS_ACCESS_VIOLATION",
+        0xc000_001d => "STATUS_ILLEGAL_INSTRUCTION",
+        0xc000_0094 => "STATUS_INTEGER_DIVIDE_BY_ZERO",
+        0xc000_00fd => "STATUS_STACK_OVERFLOW",
+        0xc000_0409 => "STATUS_STACK_BUFFER_OVERRUN",
+        0xc000_013a => "STATUS_CONTROL_C_EXIT",
+        _ if code & 0xc000_0000 == 0xc000_0000 => return format!("exit code {:#x}", code),
+        _ => return format!("exit code {}", code),
+    };
+    format!("exit code {:#x} ({})", code, name)
+}
+
+impl TestOutput for Output {
+    fn output(&self) -> &Output {
+        self
+    }
+}
+
+/// The result of a finished call, the 'std::process::Output' together with measurements taken
+/// while the executable ran. Dereferences to 'Output'.
+#[derive(Clone, Debug)]
+pub struct CallOutput {
+    output: Output,
+    duration: Duration,
+    usage: Option<Usage>,
+    crash_report: Option<PathBuf>,
+}
+
+impl CallOutput {
+    pub(crate) fn new(output: Output, duration: Duration, usage: Option<Usage>) -> Self {
+        CallOutput {
+            output,
+            duration,
+            usage,
+            crash_report: None,
+        }
+    }
+
+    pub(crate) fn with_crash_report(mut self, crash_report: Option<PathBuf>) -> Self {
+        self.crash_report = crash_report;
+        self
+    }
+
+    /// Moves stdout out, leaving it empty.
+    pub(crate) fn take_stdout(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output.stdout)
+    }
+
+    /// Returns the plain 'std::process::Output'.
+    pub fn into_output(self) -> Output {
+        self.output
+    }
+
+    /// Wall clock time from starting the executable until it was waited for.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The CPU time the executable consumed. Only available on unix, None elsewhere.
+    pub fn cpu_time(&self) -> Option<CpuTime> {
+        self.usage.map(|usage| usage.cpu_time)
+    }
+
+    /// The peak resident set size of the executable in bytes. Only available on unix, None
+    /// elsewhere. Linux charges the memory of the test process to a child until it executes,
+    /// when the executable stayed below the peak of the test process its own peak is not
+    /// known and this is None as well.
+    pub fn max_rss(&self) -> Option<u64> {
+        self.usage.and_then(|usage| usage.peak_rss())
+    }
+
+    /// Expects that the call finished within 'limit' wall clock time, for catching
+    /// performance regressions. Measured from spawning until the executable was waited for.
+    #[track_caller]
+    pub fn assert_runs_within(&self, limit: Duration) -> &Self {
+        if self.duration > limit {
+            Failure::new(
+                "assert_runs_within",
+                format!(
+                    "expected to run within {:?}, took {:?}",
+                    limit, self.duration
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that the executable used less than 'limit' CPU time (user and system
+    /// together). Unlike wall clock time this is not affected by a busy test machine and
+    /// catches accidental busy loops and algorithmic regressions. Panics when the CPU time
+    /// is not available on this platform.
+    #[track_caller]
+    pub fn assert_cpu_time_under(&self, limit: Duration) -> &Self {
+        let cpu_time = self
+            .cpu_time()
+            .expect("CPU time measurement supported on this platform");
+        if cpu_time.total() >= limit {
+            Failure::new(
+                "assert_cpu_time_under",
+                format!(
+                    "expected CPU time under {:?}, used {:?} (user {:?}, system {:?})",
+                    limit,
+                    cpu_time.total(),
+                    cpu_time.user,
+                    cpu_time.system
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that the peak resident set size of the executable stayed below 'bytes', for
+    /// catching memory regressions. Panics when this is not measured on this platform. An
+    /// executable which stayed below the peak of the test process (see 'max_rss()') passes
+    /// when that peak is below 'bytes' as well and fails otherwise, the limit should be well
+    /// above what the test process needs.
+    #[track_caller]
+    pub fn assert_max_rss_below(&self, bytes: u64) -> &Self {
+        let usage = self
+            .usage
+            .expect("RSS measurement supported on this platform");
+        match usage.peak_rss() {
+            Some(max_rss) if max_rss >= bytes => Failure::new(
+                "assert_max_rss_below",
+                format!(
+                    "expected a peak RSS below {} bytes, used {} bytes",
+                    bytes, max_rss
+                ),
+            )
+            .fail(),
+            None if usage.spawner_rss >= bytes => Failure::new(
+                "assert_max_rss_below",
+                format!(
+                    "expected a peak RSS below {} bytes, the peak can not be told apart from \
+                     the {} bytes of the test process",
+                    bytes, usage.spawner_rss
+                ),
+            )
+            .fail(),
+            _ => {}
+        }
+        self
+    }
+}
+
+impl Deref for CallOutput {
+    type Target = Output;
+
+    fn deref(&self) -> &Output {
+        &self.output
+    }
+}
+
+impl From<CallOutput> for Output {
+    fn from(output: CallOutput) -> Output {
+        output.output
+    }
+}
+
+impl TestOutput for CallOutput {
+    fn output(&self) -> &Output {
+        &self.output
+    }
+
+    fn crash_report(&self) -> Option<&Path> {
+        self.crash_report.as_deref()
     }
 }
 
@@ -115,6 +1040,7 @@ impl TestOutput for Output {
 mod test {
     use crate::*;
     use std::path::Path;
+    use std::time::Duration;
 
     #[test]
     fn captures() {
@@ -130,4 +1056,470 @@ mod test {
         assert_eq!(&captures["first"], "Hello");
         assert_eq!(&captures["second"], "World!\n");
     }
+
+    #[test]
+    fn captures_all() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        let lines = testcall
+            .call_args(["INFO one\nWARN two\nINFO three\n"])
+            .stdout_captures_all_utf8("(?m)^INFO (?P<msg>.*)$");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(&lines[0]["msg"], "one");
+        assert_eq!(&lines[1]["msg"], "three");
+    }
+
+    #[test]
+    fn at_most() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["1234"])
+            .assert_stdout_at_most(5)
+            .assert_stderr_at_most(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected at most 4 bytes on stdout, got 5")]
+    fn at_most_exceeded() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall.call_args(["1234"]).assert_stdout_at_most(4);
+    }
+
+    #[test]
+    fn normalized() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["cafe\u{301}"])
+            .assert_stdout_normalized("^caf\u{e9}\n$", normalize::UnicodeForm::Nfc)
+            .assert_stdout_normalized("^caf\u{e9}\n$", normalize::UnicodeForm::Nfd);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nfc normalized stdout does not match")]
+    fn normalized_mismatch() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["cafe"])
+            .assert_stdout_normalized("^caf\u{e9}\n$", normalize::UnicodeForm::Nfc);
+    }
+
+    #[test]
+    fn normalized_class() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["cafe\u{301}"])
+            .assert_stdout_normalized("^caf[\u{e9}]\n$", normalize::UnicodeForm::Nfc);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nfd normalized stdout does not match")]
+    fn normalized_class_not_decomposed() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["cafe"])
+            .assert_stdout_normalized("^caf[\u{e9}]\n$", normalize::UnicodeForm::Nfd);
+    }
+
+    #[test]
+    fn eq() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["exact"])
+            .assert_stdout_eq("exact\n")
+            .assert_stdout_eq_trimmed("exact")
+            .assert_stderr_eq("")
+            .assert_stderr_eq_trimmed("\n");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "stdout differs (-expected +actual):\n@@ -1,2 +1,2 @@\n-one\n+two\n three\n"
+    )]
+    fn eq_diff() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["two\nthree\n"])
+            .assert_stdout_eq("one\nthree\n");
+    }
+
+    #[test]
+    fn lines() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["step 1\nnoise\nstep 2\r\ndone\n"])
+            .assert_stdout_lines(["^step 1$", "^step 2$", "done"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "stdout lines do not match in order, missing after line 2:\n^first$")]
+    fn lines_out_of_order() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["first\nsecond\n"])
+            .assert_stdout_lines(["second", "^first$"]);
+    }
+
+    #[test]
+    fn not_matches() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["all fine"])
+            .assert_stdout_not_matches("(?i)warn|error")
+            .assert_stderr_not_matches(".");
+    }
+
+    #[test]
+    #[should_panic(expected = "stdout unexpectedly matches at 5..12: \"warning\":\n(?i)warn\\w*")]
+    fn not_matches_found() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["done warning: deprecated"])
+            .assert_stdout_not_matches(r"(?i)warn\w*");
+    }
+
+    #[test]
+    fn signaled() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall
+            .call_args(["-c", "kill -SEGV $$"])
+            .assert_failure()
+            .assert_signaled(signal::SIGSEGV);
+        testcall
+            .call_args(["-c", "exit 3"])
+            .assert_failure()
+            .assert_not_signaled();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected an exit, got termination by SIGABRT")]
+    fn signaled_crash() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall
+            .call_args(["-c", "kill -ABRT $$"])
+            .assert_not_signaled();
+    }
+
+    #[test]
+    #[should_panic(expected = "the wrapper reported errors with exit code 99")]
+    fn wrapper_error() {
+        let mut testcall = TestCall::external_command(Path::new("false"));
+        testcall.wrap_with("sh", ["-c", "\"$@\" || exit 99; echo leak >&2", "wrapper"]);
+
+        testcall.call().assert_no_wrapper_error(99);
+    }
+
+    #[test]
+    fn not_crashed() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall.call_args(["-c", "exit 1"]).assert_not_crashed();
+        testcall
+            .call_args(["-c", "kill -TERM $$"])
+            .assert_not_crashed();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected no crash, got termination by SIGSEGV")]
+    fn crashed() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall
+            .call_args(["-c", "kill -SEGV $$"])
+            .assert_not_crashed();
+    }
+
+    #[test]
+    fn runs_within() {
+        let testcall = TestCall::external_command(Path::new("true"));
+
+        let output = testcall.call();
+        output.assert_runs_within(Duration::from_secs(5));
+        assert!(output.duration() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected to run within 10ms, took")]
+    fn runs_too_long() {
+        let testcall = TestCall::external_command(Path::new("sleep"));
+
+        testcall
+            .call_args(["0.1"])
+            .assert_runs_within(Duration::from_millis(10));
+    }
+
+    #[test]
+    fn glob() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["myprogram 0.1.3 (a+b)"])
+            .assert_stdout_glob("myprogram 0.1.? (a+b)")
+            .assert_stderr_glob("");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "stdout does not match:\nmyprogram 0.2.*\nstdout was:\nmyprogram 0.1.3"
+    )]
+    fn glob_mismatch() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["myprogram 0.1.3"])
+            .assert_stdout_glob("myprogram 0.2.*");
+    }
+
+    #[test]
+    fn frames() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        let output = testcall.call_args(["\\003abc\\001d"]);
+        assert_eq!(output.stdout_frames(FrameLength::Varint).len(), 2);
+        output.assert_stdout_frames(FrameLength::Varint, |index, frame| {
+            assert_eq!(frame.len(), [3, 1][index]);
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "cbor")]
+    fn cbor() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["\\241\\141\\141\\202\\001\\141\\170"])
+            .stdout_cbor()
+            .assert_pointer("/a/1", "x");
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn msgpack() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["\\201\\241\\141\\222\\001\\241\\170"])
+            .stdout_msgpack()
+            .assert_pointer("/a/0", 1);
+    }
+
+    #[test]
+    fn folded() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["50%%\\r100%%\\ndone\\n"])
+            .assert_stdout_folded_utf8("^100%\ndone\n$");
+    }
+
+    #[test]
+    fn plain() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["\\033[31merror:\\033[0m failed\\n"])
+            .assert_stdout_utf8_plain("^error: failed\n$")
+            .assert_stderr_utf8_plain("^$");
+    }
+
+    #[test]
+    #[should_panic(expected = "plain stdout does not match")]
+    fn plain_mismatch() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        testcall
+            .call_args(["\\033[31merror\\033[0m"])
+            .assert_stdout_utf8_plain("^warning");
+    }
+
+    #[test]
+    fn predicates() {
+        let testcall = TestCall::external_command(Path::new("printf"));
+
+        let mut lines = 0;
+        testcall
+            .call_args(["a\\nb\\nc\\n"])
+            .inspect(|output| lines = output.stdout.split(|&b| b == b'\n').count() - 1)
+            .assert_with(|output| output.stderr.is_empty(), "stderr not empty");
+        assert_eq!(lines, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected three lines")]
+    fn predicate_failed() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call()
+            .assert_with(|output| output.stdout.len() == 6, "expected three lines");
+    }
+
+    #[test]
+    fn try_assertions() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+        let output = testcall.call_args(["hello"]);
+
+        assert!(output.try_success().is_ok());
+        assert!(output.try_stdout_utf8("^hello\n$").is_ok());
+        let err = output
+            .try_stdout_utf8("^bye$")
+            .expect_err("stdout does not match");
+        assert_eq!(err.failure().kind(), "assert_stdout_utf8");
+        assert!(err.to_string().contains("stdout does not match"));
+        assert_eq!(
+            output
+                .try_exitcode(1)
+                .expect_err("exited 0")
+                .failure()
+                .kind(),
+            "assert_exitcode"
+        );
+    }
+
+    #[test]
+    fn version() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .call_args(["myprogram 1.3.0"])
+            .assert_version_matches("1.3.0")
+            .assert_version_req(">=1.2, <2");
+    }
+
+    #[test]
+    #[should_panic(expected = "does not satisfy")]
+    fn version_req_fail() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall.call_args(["v0.9.1"]).assert_version_req("^1");
+    }
+
+    #[test]
+    fn log_levels() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let output = testcall.call_args([
+            "-c",
+            "echo 'WARN one' >&2; echo 'warning: two' >&2; echo ok",
+        ]);
+        output.assert_no_errors_logged().assert_warning_count(2);
+        assert_eq!(
+            output.stderr_log_lines(LogLevel::Warn),
+            ["WARN one", "warning: two"]
+        );
+    }
+
+    #[test]
+    fn cpu_time() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let output = testcall.call_args(["-c", "sleep 0.2"]);
+        output
+            .assert_success()
+            .assert_cpu_time_under(Duration::from_secs(5));
+        assert!(output.duration() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected CPU time under 1ms")]
+    fn cpu_time_busy() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall
+            .call_args(["-c", "i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done"])
+            .assert_cpu_time_under(Duration::from_millis(1));
+    }
+
+    #[test]
+    fn max_rss() {
+        let testcall = TestCall::external_command(Path::new("dd"));
+
+        let output = testcall.call_args(["if=/dev/zero", "of=/dev/null", "bs=256M", "count=1"]);
+        output.assert_max_rss_below(288 << 20);
+        assert!(output.max_rss().expect("measured") >= 256 << 20);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn max_rss_below_test_process() {
+        let testcall = TestCall::external_command(Path::new("true"));
+
+        assert_eq!(testcall.call().max_rss(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a peak RSS below 134217728 bytes, used")]
+    fn max_rss_exceeded() {
+        let testcall = TestCall::external_command(Path::new("dd"));
+
+        testcall
+            .call_args(["if=/dev/zero", "of=/dev/null", "bs=256M", "count=1"])
+            .assert_max_rss_below(128 << 20);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 0 Error lines")]
+    fn errors_logged() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall
+            .call_args(["-c", "echo '[ERROR] failed' >&2"])
+            .assert_no_errors_logged();
+    }
+}
+
+#[cfg(test)]
+#[cfg(windows)]
+mod windows_test {
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn newlines() {
+        let mut testcall = TestCall::external_command(Path::new("cmd"));
+
+        testcall
+            .call_args(["/C", "echo hello"])
+            .assert_stdout_utf8("^hello\r\n$");
+        testcall
+            .normalize_newlines(true)
+            .call_args(["/C", "echo hello"])
+            .assert_success()
+            .assert_stdout_utf8("^hello\n$")
+            .assert_stdout_eq("hello\n");
+    }
+
+    #[test]
+    fn exitcodes() {
+        let testcall = TestCall::external_command(Path::new("cmd"));
+
+        testcall
+            .call_args(["/C", "exit 3"])
+            .assert_failure()
+            .assert_exitcode(3)
+            .assert_not_crashed();
+    }
+
+    #[test]
+    #[should_panic(expected = "exit code 0xc0000005 (STATUS_ACCESS_VIOLATION)")]
+    fn crashed() {
+        let testcall = TestCall::external_command(Path::new("cmd"));
+
+        testcall
+            .call_args(["/C", "exit -1073741819"])
+            .assert_not_crashed();
+    }
 }
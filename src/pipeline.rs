@@ -0,0 +1,241 @@
+//! Connecting TestCalls like a shell pipe, see 'TestCall::pipe()'.
+use std::process::{Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::failure::Failure;
+use crate::{CallOutput, TestCall, TestOutput, NO_ARGS, NO_ENVS};
+
+/// How often running stages are checked for having exited or timed out.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A chain of TestCalls where the stdout of each stage is connected to the stdin of the next
+/// one, like 'a | b | c' in a shell. Each stage is called with its configured base
+/// arguments, directory and environment. Built with 'TestCall::pipe()'.
+#[derive(Clone)]
+pub struct Pipeline<'a> {
+    stages: Vec<TestCall<'a>>,
+}
+
+impl<'a> TestCall<'a> {
+    /// Starts a pipeline which feeds the stdout of this TestCall to the stdin of 'next'.
+    /// Arguments of the stages are given with 'base_args()'.
+    pub fn pipe(&self, next: &TestCall<'a>) -> Pipeline<'a> {
+        Pipeline {
+            stages: vec![self.clone(), next.clone()],
+        }
+    }
+}
+
+impl<'a> Pipeline<'a> {
+    /// Appends 'next' to the end of the pipeline.
+    pub fn pipe(mut self, next: &TestCall<'a>) -> Self {
+        self.stages.push(next.clone());
+        self
+    }
+
+    /// Runs all stages concurrently with the stdin of the first stage closed and waits for
+    /// all of them, each stage within its own timeout (counted from its spawn) when one was
+    /// set. The returned 'PipelineOutput' has the stdout of the last stage and the stderr of
+    /// every stage. When any stage is in dry run or replay mode the stages run one after
+    /// another instead.
+    #[track_caller]
+    pub fn run(&self) -> PipelineOutput {
+        if self.stages.iter().any(TestCall::simulated) {
+            return self.run_sequential();
+        }
+        let mut stdin = Stdio::null();
+        let mut children = Vec::with_capacity(self.stages.len());
+        for (n, stage) in self.stages.iter().enumerate() {
+            let last = n + 1 == self.stages.len();
+            let (child, stdout) =
+                stage.start_piped(stage.command(NO_ARGS, NO_ENVS), Some(stdin), !last);
+            let deadline = stage
+                .timeout
+                .map(|timeout| (timeout, Instant::now() + timeout));
+            children.push(Some((child, deadline)));
+            stdin = stdout.map_or_else(Stdio::null, Stdio::from);
+        }
+
+        // all stages are polled, a hanging stage fails at its deadline even when an earlier
+        // stage is still running
+        let mut stages: Vec<Option<CallOutput>> = vec![None; children.len()];
+        while stages.iter().any(Option::is_none) {
+            for (slot, output) in children.iter_mut().zip(stages.iter_mut()) {
+                let Some((child, deadline)) = slot else {
+                    continue;
+                };
+                if !child.is_running() {
+                    let (mut child, deadline) = slot.take().expect("stage pending");
+                    *output = Some(match deadline {
+                        Some((_, deadline)) => child.wait_until(deadline).expect("stage exited"),
+                        None => child.wait(),
+                    });
+                } else if let Some((timeout, deadline)) = *deadline {
+                    if Instant::now() >= deadline {
+                        let (child, _) = slot.take().expect("stage pending");
+                        child.timed_out(timeout);
+                    }
+                }
+            }
+            if stages.iter().any(Option::is_none) {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        PipelineOutput {
+            stages: stages.into_iter().flatten().collect(),
+        }
+    }
+
+    /// Runs the stages one after another, each fed with the stdout of the previous one. Stages
+    /// in dry run or replay mode are not executed as processes which could be connected.
+    #[track_caller]
+    fn run_sequential(&self) -> PipelineOutput {
+        let mut stages: Vec<CallOutput> = Vec::with_capacity(self.stages.len());
+        for stage in &self.stages {
+            let command = stage.command(NO_ARGS, NO_ENVS);
+            let output = match stages.last_mut() {
+                Some(previous) => {
+                    let input = previous.take_stdout();
+                    stage.run(command, Some(&mut &input[..]))
+                }
+                None => stage.run(command, None),
+            };
+            stages.push(output);
+        }
+        PipelineOutput { stages }
+    }
+}
+
+/// The result of running a 'Pipeline'. Assertions apply to the last stage, the outputs of
+/// the earlier stages (with empty stdout, it was consumed by the next stage) are available
+/// through 'stages()'.
+#[derive(Clone, Debug)]
+pub struct PipelineOutput {
+    stages: Vec<CallOutput>,
+}
+
+impl PipelineOutput {
+    /// The outputs of all stages in pipeline order.
+    pub fn stages(&self) -> &[CallOutput] {
+        &self.stages
+    }
+
+    /// The output of the stage at 'index', panics when there is no such stage.
+    #[track_caller]
+    pub fn stage(&self, index: usize) -> &CallOutput {
+        match self.stages.get(index) {
+            Some(stage) => stage,
+            None => panic!(
+                "no stage {} in a pipeline of {} stages",
+                index,
+                self.stages.len()
+            ),
+        }
+    }
+
+    /// Expects that every stage exited successfully, like 'set -o pipefail' does. Fails with
+    /// the stderr of the first failing stage.
+    #[track_caller]
+    pub fn assert_all_success(&self) -> &Self {
+        if let Some((index, stage)) = self
+            .stages
+            .iter()
+            .enumerate()
+            .find(|(_, stage)| !stage.status.success())
+        {
+            Failure::new(
+                "assert_all_success",
+                format!("stage {} exited with {}", index, stage.status),
+            )
+            .with_actual("stderr", &String::from_utf8_lossy(&stage.stderr))
+            .with_hint(crate::output::crash_hint(stage.crash_report()))
+            .fail();
+        }
+        self
+    }
+}
+
+impl TestOutput for PipelineOutput {
+    fn output(&self) -> &Output {
+        self.stages.last().expect("pipeline has stages")
+    }
+
+    fn crash_report(&self) -> Option<&std::path::Path> {
+        self.stages.last().and_then(TestOutput::crash_report)
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn pipe() {
+        let mut printf = TestCall::external_command(Path::new("printf"));
+        printf.base_args(["b\\na\\nc\\na\\n"]);
+        let mut sort = TestCall::external_command(Path::new("sort"));
+        sort.base_args(["-u"]);
+        let mut count = TestCall::external_command(Path::new("sh"));
+        count.base_args(["-c", "wc -l; echo counted >&2"]);
+
+        let output = printf.pipe(&sort).run();
+        output
+            .assert_all_success()
+            .assert_stdout_utf8("^a\nb\nc\n$");
+        assert_eq!(output.stages().len(), 2);
+        assert!(output.stage(0).stdout.is_empty());
+
+        printf
+            .pipe(&sort)
+            .pipe(&count)
+            .run()
+            .assert_success()
+            .assert_stdout_utf8("^ *3\n$")
+            .assert_stderr_utf8("^counted\n$");
+    }
+
+    #[test]
+    fn pipe_closed_early() {
+        let mut yes = TestCall::external_command(Path::new("yes"));
+        yes.timeout(std::time::Duration::from_secs(10));
+        let mut head = TestCall::external_command(Path::new("head"));
+        head.base_args(["-n1"]);
+
+        let output = yes.pipe(&head).run();
+        output.assert_success().assert_stdout_eq("y\n");
+        assert!(!output.stage(0).status.success());
+    }
+
+    #[test]
+    fn pipe_stage_timeout() {
+        let mut slow = TestCall::external_command(Path::new("sleep"));
+        slow.base_args(["3"]);
+        let mut hanging = TestCall::external_command(Path::new("sleep"));
+        hanging
+            .base_args(["10"])
+            .timeout(std::time::Duration::from_millis(100));
+
+        let started = std::time::Instant::now();
+        let pipeline = slow.pipe(&hanging);
+        let failure = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pipeline.run()))
+            .expect_err("second stage timed out");
+        assert!(failure
+            .downcast_ref::<String>()
+            .is_some_and(|msg| msg.contains("timed out after 100ms")));
+        // does not wait for the first stage before checking the timeout of the second
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "stage 0 exited with")]
+    fn pipe_stage_failed() {
+        let mut fail = TestCall::external_command(Path::new("sh"));
+        fail.base_args(["-c", "echo broken >&2; exit 1"]);
+        let cat = TestCall::external_command(Path::new("cat"));
+
+        fail.pipe(&cat).run().assert_success().assert_all_success();
+    }
+}
@@ -0,0 +1,141 @@
+//! Running calls as another user, for programs whose behavior depends on the invoking user
+//! like permission errors or setuid handling (unix only). Tests using this are meant to be
+//! skipped when the test process lacks the privileges, see 'can_run_as()' and 'can_sudo()'.
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::TestCall;
+
+/// Returns true when the test process may switch calls to other users with
+/// 'TestCall::run_as()', that is when it runs as root.
+pub fn can_run_as() -> bool {
+    // SAFETY: can not fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Returns true when 'sudo' runs commands without asking for a password, as needed by
+/// 'TestCall::run_via_sudo()'. Checked once per test process.
+pub fn can_sudo() -> bool {
+    static CAN_SUDO: OnceLock<bool> = OnceLock::new();
+    *CAN_SUDO.get_or_init(|| {
+        Command::new("sudo")
+            .args(["-n", "true"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+impl TestCall<'_> {
+    /// Runs all following calls with the user id 'uid' and group id 'gid', supplementary
+    /// groups are dropped. Requires root, check 'can_run_as()' to skip the test otherwise.
+    /// The directory of the call and the executable must be accessible to that user. The
+    /// user is switched last, after network isolation and the sandbox are set up.
+    pub fn run_as(&mut self, uid: u32, gid: u32) -> &mut Self {
+        self.run_as = Some((uid, gid));
+        self
+    }
+
+    /// Runs all following calls through 'sudo -n', thus as root, outside of any wrapper set
+    /// with 'wrap_with()'. Needs sudo without password, check 'can_sudo()' to skip the test
+    /// otherwise. Note that sudo applies its own environment policy, variables set on the
+    /// TestCall may not reach the executable.
+    pub fn run_via_sudo(&mut self) -> &mut Self {
+        self.sudo = true;
+        self
+    }
+}
+
+/// Switches the child of 'command' to 'uid' and 'gid' right before it executes. Unlike
+/// 'CommandExt::uid()', which 'std' applies before any 'pre_exec()' closure, this runs after
+/// the closures registered so far, thus these keep the privileges of the test process.
+pub(crate) fn switch_user(command: &mut Command, uid: u32, gid: u32) {
+    // SAFETY: the closure only makes async signal safe syscalls
+    unsafe {
+        command.pre_exec(move || {
+            // changing the credentials resets the parent death signal set by the sandbox
+            #[cfg(target_os = "linux")]
+            let mut death_signal: libc::c_int = 0;
+            #[cfg(target_os = "linux")]
+            libc::prctl(
+                libc::PR_GET_PDEATHSIG,
+                &mut death_signal as *mut libc::c_int,
+            );
+
+            if libc::geteuid() == 0 && libc::setgroups(0, std::ptr::null()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::setgid(gid as libc::gid_t) != 0 || libc::setuid(uid as libc::uid_t) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            #[cfg(target_os = "linux")]
+            if death_signal != 0 {
+                libc::prctl(libc::PR_SET_PDEATHSIG, death_signal);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// The wrapper prepended by 'TestCall::run_via_sudo()'.
+pub(crate) const SUDO: &[&str] = &["sudo", "-n", "--"];
+
+#[cfg(test)]
+mod test {
+    use super::{can_run_as, can_sudo};
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn run_as() {
+        if !can_run_as() {
+            return;
+        }
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.run_as(65534, 65534);
+
+        testcall
+            .call_args(["-c", "id -u; id -g; id -G"])
+            .assert_success()
+            .assert_stdout_utf8("^65534\n65534\n65534\n$");
+        testcall
+            .call_args(["-c", "cat /etc/shadow"])
+            .assert_failure()
+            .assert_stderr_utf8("Permission denied");
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "netns"))]
+    fn run_as_isolated() {
+        if !can_run_as() {
+            return;
+        }
+        let mut testcall = TestCall::external_command(Path::new("id"));
+        testcall.run_as(65534, 65534).isolate_network(true);
+
+        testcall
+            .call_args(["-u"])
+            .assert_success()
+            .assert_stdout_utf8("^65534\n$");
+        testcall.assert_degrades_offline(["-u"]);
+    }
+
+    #[test]
+    fn run_via_sudo() {
+        if !can_sudo() {
+            return;
+        }
+        let mut testcall = TestCall::external_command(Path::new("id"));
+        testcall.run_via_sudo();
+
+        testcall
+            .call_args(["-u"])
+            .assert_success()
+            .assert_stdout_utf8("^0\n$");
+    }
+}
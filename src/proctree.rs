@@ -0,0 +1,261 @@
+//! Observing the processes spawned by a child, linux only since it samples '/proc'.
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::failure::Failure;
+use crate::TestChild;
+
+/// A process that was seen in the tree, identified by pid and start time since pids get
+/// reused.
+#[derive(Clone, Debug)]
+struct Seen {
+    pid: u32,
+    start_time: u64,
+    cmdline: String,
+}
+
+#[derive(Default)]
+struct Observed {
+    seen: Vec<Seen>,
+    max_concurrent: usize,
+}
+
+/// Samples the descendants of a child in a background thread until the child exits or the
+/// observer is dropped. Processes which start and end between two samples are missed, thus
+/// the sampling interval should be shorter than the lifetime of the observed workers.
+pub struct ProcessTree {
+    observed: Arc<Mutex<Observed>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestChild {
+    /// Starts observing the processes this child spawns, sampling every 'interval'.
+    pub fn observe_process_tree(&self, interval: Duration) -> ProcessTree {
+        let root = self.id();
+        let observed = Arc::new(Mutex::new(Observed::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let observed = Arc::clone(&observed);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) && sample(root, &observed) {
+                    thread::sleep(interval);
+                }
+            })
+        };
+        ProcessTree {
+            observed,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl ProcessTree {
+    /// Command lines of all descendants seen so far, in order of appearance.
+    pub fn spawned(&self) -> Vec<String> {
+        self.lock()
+            .seen
+            .iter()
+            .map(|seen| seen.cmdline.clone())
+            .collect()
+    }
+
+    /// The most descendants seen alive at the same time.
+    pub fn max_concurrent(&self) -> usize {
+        self.lock().max_concurrent
+    }
+
+    /// Expects that at most 'max' descendants were spawned in total.
+    #[track_caller]
+    pub fn assert_spawned_max(&self, max: usize) -> &Self {
+        let spawned = self.spawned();
+        if spawned.len() > max {
+            Failure::new(
+                "assert_spawned_max",
+                format!(
+                    "expected at most {} spawned processes, saw {}:\n  {}",
+                    max,
+                    spawned.len(),
+                    spawned.join("\n  ")
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that at most 'max' descendants were alive at the same time.
+    #[track_caller]
+    pub fn assert_concurrent_max(&self, max: usize) -> &Self {
+        let concurrent = self.max_concurrent();
+        if concurrent > max {
+            Failure::new(
+                "assert_concurrent_max",
+                format!(
+                    "expected at most {} concurrent processes, saw {}",
+                    max, concurrent
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that at least one descendant was spawned whose command line matches 'regex'.
+    #[track_caller]
+    pub fn assert_spawned_matching(&self, regex: &str) -> &Self {
+        let spawned = self.spawned();
+        if !spawned
+            .iter()
+            .any(|cmdline| crate::regex::is_match_bytes(cmdline.as_bytes(), regex))
+        {
+            Failure::new(
+                "assert_spawned_matching",
+                String::from("no spawned process matches"),
+            )
+            .with_expected(regex)
+            .with_actual("spawned", &spawned.join("\n"))
+            .fail();
+        }
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Observed> {
+        self.observed.lock().expect("lock")
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Adds the current descendants of 'root' to 'observed'. Returns false once 'root' is gone
+/// or a zombie.
+fn sample(root: u32, observed: &Mutex<Observed>) -> bool {
+    let mut parents: HashMap<u32, Vec<(u32, u64)>> = HashMap::new();
+    let mut root_alive = false;
+    for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        if let Some((state, ppid, start_time)) = stat(pid) {
+            if pid == root {
+                root_alive = state != 'Z';
+            }
+            parents.entry(ppid).or_default().push((pid, start_time));
+        }
+    }
+    if !root_alive {
+        return false;
+    }
+
+    let mut alive = Vec::new();
+    let mut pending = vec![root];
+    while let Some(parent) = pending.pop() {
+        for &(pid, start_time) in parents.get(&parent).into_iter().flatten() {
+            alive.push((pid, start_time));
+            pending.push(pid);
+        }
+    }
+
+    let mut observed = observed.lock().expect("lock");
+    observed.max_concurrent = observed.max_concurrent.max(alive.len());
+    for (pid, start_time) in alive {
+        let cmdline = match cmdline(pid) {
+            Some(cmdline) => cmdline,
+            None => continue,
+        };
+        // refreshed on every sample, a forked child may not have executed its program yet
+        match observed
+            .seen
+            .iter_mut()
+            .find(|seen| seen.pid == pid && seen.start_time == start_time)
+        {
+            Some(seen) => seen.cmdline = cmdline,
+            None => observed.seen.push(Seen {
+                pid,
+                start_time,
+                cmdline,
+            }),
+        }
+    }
+    true
+}
+
+/// Returns (state, parent pid, start time) from '/proc/PID/stat'.
+fn stat(pid: u32) -> Option<(char, u32, u64)> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the command name in parenthesis may contain anything, fields follow the last ')'
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    Some((
+        fields.first()?.chars().next()?,
+        fields.get(1)?.parse().ok()?,
+        fields.get(19)?.parse().ok()?,
+    ))
+}
+
+/// The command line with arguments separated by spaces, the command name for processes
+/// without a command line.
+fn cmdline(pid: u32) -> Option<String> {
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    if cmdline.is_empty() {
+        let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        return Some(String::from(comm.trim_end()));
+    }
+    Some(
+        cmdline
+            .split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn process_tree() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args(["-c", "sleep 0.3 & sleep 0.31 & wait"]);
+        let tree = child.observe_process_tree(Duration::from_millis(10));
+        child.wait().assert_success();
+
+        tree.assert_spawned_max(2)
+            .assert_concurrent_max(2)
+            .assert_spawned_matching("^sleep 0.31$");
+        assert_eq!(tree.max_concurrent(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "no spawned process matches:\nffmpeg")]
+    fn process_tree_missing() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args(["-c", "sleep 0.2 & wait"]);
+        let tree = child.observe_process_tree(Duration::from_millis(10));
+        child.wait();
+        tree.assert_spawned_matching("ffmpeg.*");
+    }
+}
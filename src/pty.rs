@@ -0,0 +1,115 @@
+//! Running children on a pseudo terminal ('pty' feature).
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::ptr;
+
+/// Opens a pseudo terminal of 'cols' x 'rows' cells and connects stdin, stdout and stderr of
+/// 'command' to it. The child becomes session leader with the terminal as controlling
+/// terminal. Returns the master side which has to be handed to 'detach()' after spawning.
+#[track_caller]
+pub(crate) fn attach(command: &mut Command, cols: u16, rows: u16) -> File {
+    let (mut master, mut slave) = (-1, -1);
+    let size = libc::winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: all pointers are valid for the call, the name is not requested, 'size' is only
+    // read (some platforms declare it mutable)
+    let opened = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &size as *const libc::winsize as _,
+        )
+    };
+    if opened != 0 {
+        panic!("opening a pty failed: {}", io::Error::last_os_error());
+    }
+    // SAFETY: openpty returned two file descriptors owned by nobody else
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+    // the child gets the slave as stdio only, the master must not leak into it
+    // SAFETY: plain fcntl on a valid descriptor
+    unsafe {
+        libc::fcntl(master.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+    }
+
+    command
+        .stdin(Stdio::from(slave.try_clone().expect("pty cloned")))
+        .stdout(Stdio::from(slave.try_clone().expect("pty cloned")))
+        .stderr(Stdio::from(slave));
+    // SAFETY: the closure only makes async signal safe syscalls
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    File::from(master)
+}
+
+/// Closes the copies of the slave side held by 'command', thus reading the master ends when
+/// the child exits. The master becomes the stdout of 'child' for capturing and its stdin for
+/// writing terminal input, stderr is merged into the terminal stream.
+pub(crate) fn detach(command: &mut Command, child: &mut Child, master: File) {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let input = master.try_clone().expect("pty cloned");
+    child.stdin = Some(ChildStdin::from(OwnedFd::from(input)));
+    child.stdout = Some(ChildStdout::from(OwnedFd::from(master)));
+    child.stderr = None;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn isatty() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.pty_size(100, 30);
+
+        testcall
+            .call_args([
+                "-c",
+                "test -t 0 && test -t 1 && test -t 2 && stty size; echo err >&2",
+            ])
+            .assert_success()
+            .assert_stdout_utf8("^30 100\r\nerr\r\n$")
+            .assert_stderr_utf8("^$");
+    }
+
+    #[test]
+    fn prompt() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.pty(true).normalize_newlines(true);
+
+        let mut child =
+            testcall.spawn_args(["-c", "printf 'name? '; read name; echo \"hi $name\""]);
+        child.read_until("name\\? ", Duration::from_secs(5));
+        child.write_stdin("joe\n");
+        child
+            .wait()
+            .assert_success()
+            .assert_stdout_utf8("^name\\? joe\nhi joe\n$");
+    }
+
+    #[test]
+    fn no_pty() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        testcall.call_args(["-c", "test -t 1"]).assert_exitcode(1);
+    }
+}
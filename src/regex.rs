@@ -1,5 +1,10 @@
 use std::collections::HashMap;
+use std::fmt::{Debug, Display};
 use std::ops::{Index, Range};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::failure::Failure;
 
 /// Captured keys which can be identified by numeric index or by name.
 #[derive(Hash, PartialEq)]
@@ -33,53 +38,563 @@ impl Index<&str> for Captured {
     }
 }
 
+/// Keys by which a 'Captured' can be looked up, group numbers (usize) and names (&str).
+pub trait CaptureIndex {
+    /// Returns the text captured for this key, None when the group did not participate.
+    fn lookup<'c>(&self, captured: &'c Captured) -> Option<&'c str>;
+}
+
+impl CaptureIndex for usize {
+    fn lookup<'c>(&self, captured: &'c Captured) -> Option<&'c str> {
+        captured.get(&CaptureKey::Index(*self))
+    }
+}
+
+impl CaptureIndex for &str {
+    fn lookup<'c>(&self, captured: &'c Captured) -> Option<&'c str> {
+        captured.get(&CaptureKey::Name(String::from(*self)))
+    }
+}
+
+impl Captured {
+    fn get(&self, key: &CaptureKey) -> Option<&str> {
+        self.captures
+            .get(key)
+            .map(|range| &self.text[range.clone()])
+    }
+
+    /// Returns the text captured for 'key', None when the group did not participate in the
+    /// match.
+    pub fn try_get<K: CaptureIndex>(&self, key: K) -> Option<&str> {
+        key.lookup(self)
+    }
+
+    /// Parses the text captured for 'key' into 'T', like 'captured.get_as::<u32>("port")'.
+    /// Panics when nothing was captured or the text does not parse.
+    #[track_caller]
+    pub fn get_as<T>(&self, key: impl CaptureIndex + Debug) -> T
+    where
+        T: FromStr,
+        T::Err: Debug,
+    {
+        let text = key
+            .lookup(self)
+            .unwrap_or_else(|| panic!("nothing captured for {:?}", key));
+        text.parse().unwrap_or_else(|err| {
+            panic!(
+                "capture {:?} {:?} does not parse as {}: {:?}",
+                key,
+                text,
+                std::any::type_name::<T>(),
+                err
+            )
+        })
+    }
+}
+
 /// Returns the captures from the 'input' data matched by 'regex'.
 /// The input is lossy translated to UTF8.
 pub fn captures_utf8(input: &[u8], regex: &str) -> Captured {
-    let mut captures = HashMap::new();
-    use regex::Regex;
-    let re = Regex::new(regex).expect("valid regex");
-    let text = String::from_utf8_lossy(input).to_string();
+    let text = String::from_utf8_lossy(input);
+    captured(&text, engine().captures(regex, &text))
+}
+
+/// Returns the captures of every non-overlapping match of 'regex' in the 'input' data, in
+/// order. The input is lossy translated to UTF8.
+pub fn captures_iter_utf8(input: &[u8], regex: &str) -> Vec<Captured> {
+    let text = String::from_utf8_lossy(input);
+    engine()
+        .captures_iter(regex, &text)
+        .into_iter()
+        .map(|groups| captured(&text, groups))
+        .collect()
+}
 
+/// Builds a 'Captured' which keeps only the part of 'text' covered by 'groups'.
+fn captured(text: &str, groups: Groups) -> Captured {
     use CaptureKey::*;
 
-    if let Some(c) = re.captures(&text) {
-        for n in 0..c.len() {
-            if let Some(m) = c.get(n) {
-                captures.insert(Index(n), m.range());
-            }
-        }
+    let start = groups
+        .iter()
+        .filter_map(|(_, range)| range.as_ref().map(|range| range.start))
+        .min()
+        .unwrap_or_default();
+    let end = groups
+        .iter()
+        .filter_map(|(_, range)| range.as_ref().map(|range| range.end))
+        .max()
+        .unwrap_or_default();
 
-        for n in re.capture_names() {
-            if let (Some(n), Some(m)) = (n, c.name(n.unwrap_or_default())) {
-                captures.insert(Name(String::from(n)), m.range());
+    let mut captures = HashMap::new();
+    for (n, (name, range)) in groups.into_iter().enumerate() {
+        if let Some(range) = range {
+            let range = range.start - start..range.end - start;
+            if let Some(name) = name {
+                captures.insert(Name(name), range.clone());
             }
+            captures.insert(Index(n), range);
         }
-    };
+    }
 
-    Captured { text, captures }
+    Captured {
+        text: String::from(&text[start..end]),
+        captures,
+    }
 }
 
 /// Checks if the input (lossy translated to utf8) matches the given regex.
 /// Returns a tuple of the test outcome and the utf8 string (for diagnostics).
 pub fn regex_match_utf8(input: &[u8], regex: &str) -> (bool, String) {
-    use regex::Regex;
-    let re = Regex::new(regex).expect("compiled regex");
     let text = String::from_utf8_lossy(input);
-    (re.is_match(&text), text.into_owned())
+    (engine().is_match(regex, &text), text.into_owned())
 }
 
 /// Checks if the input matches the given regex as bytes.
 /// Returns a tuple of the test outcome and the input as lossy utf8 string (for diagnostics).
 pub fn regex_match_bytes(input: &[u8], regex: &str) -> (bool, String) {
-    use regex::bytes::Regex;
-    let re = Regex::new(regex).expect("compiled regex");
     (
-        re.is_match(input),
+        engine().is_match_bytes(regex, input),
         String::from_utf8_lossy(input).into_owned(),
     )
 }
 
+/// Checks if the input matches the given regex as bytes, without a diagnostic copy.
+pub(crate) fn is_match_bytes(input: &[u8], regex: &str) -> bool {
+    engine().is_match_bytes(regex, input)
+}
+
+/// Returns the byte range and the text of the first match of 'regex' in 'text'.
+pub(crate) fn find_utf8<'t>(text: &'t str, regex: &str) -> Option<(Range<usize>, &'t str)> {
+    let range = engine()
+        .captures(regex, text)
+        .into_iter()
+        .next()
+        .and_then(|(_, range)| range)?;
+    Some((range.clone(), &text[range]))
+}
+
+/// Returns the end of the first match of 'regex' in 'input', as an offset into 'input'. The
+/// input is matched as lossy translated utf8.
+pub(crate) fn find_end_bytes(input: &[u8], regex: &str) -> Option<usize> {
+    let text = String::from_utf8_lossy(input);
+    let mut end = engine()
+        .captures(regex, &text)
+        .into_iter()
+        .next()
+        .and_then(|(_, range)| range)?
+        .end;
+    // each invalid sequence became a three byte replacement character
+    let mut offset = 0;
+    for chunk in input.utf8_chunks() {
+        let valid = chunk.valid().len();
+        if end <= valid {
+            return Some(offset + end);
+        }
+        end -= valid;
+        offset += valid;
+        if !chunk.invalid().is_empty() {
+            offset += chunk.invalid().len();
+            if end <= 3 {
+                return Some(offset);
+            }
+            end -= 3;
+        }
+    }
+    Some(offset)
+}
+
+/// Longest regex for which the closest match is searched, longer ones are not analyzed.
+const CLOSEST_LIMIT: usize = 256;
+
+/// Explains where 'regex' stopped matching 'text': finds the longest prefix of the regex
+/// which still matches and shows the lines around the end of that match with line numbers.
+/// Returns None when not even a prefix matches.
+pub(crate) fn closest_match(text: &str, regex: &str) -> Option<String> {
+    closest_match_by(engine(), text, regex)
+}
+
+/// Implements 'closest_match()' with the prefixes matched by 'engine'.
+fn closest_match_by(engine: &dyn RegexEngine, text: &str, regex: &str) -> Option<String> {
+    if regex.len() > CLOSEST_LIMIT {
+        return None;
+    }
+    let (prefix, end) = regex
+        .char_indices()
+        .skip(1)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .map(|(i, _)| &regex[..i])
+        .filter(|prefix| engine.is_valid(prefix))
+        .find_map(|prefix| {
+            let (_, found) = engine.captures(prefix, text).into_iter().next()?;
+            Some((prefix, found?.end))
+        })?;
+
+    let line = text[..end].matches('\n').count();
+    let mut hint = format!(
+        "matched up to line {} by {:?}, {:?} does not match from there:",
+        line + 1,
+        prefix,
+        &regex[prefix.len()..]
+    );
+    let first = line.saturating_sub(2);
+    for (n, text) in text.lines().enumerate().skip(first).take(line + 3 - first) {
+        hint.push_str(&format!(
+            "\n{} {:4} | {}",
+            if n == line { '>' } else { ' ' },
+            n + 1,
+            text
+        ));
+    }
+    Some(hint)
+}
+
+/// Translates a shell style wildcard pattern into a regex which matches the whole text.
+/// '*' matches any sequence (also across lines), '?' any single character, '[abc]',
+/// '[a-z]' and '[!abc]' a character of/not of a set and '\\' escapes the next character.
+/// Everything else is literal, thus paths and version strings need no escaping. A single
+/// trailing newline of the text is ignored since program output usually ends in one.
+pub fn glob(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let literal = |c: char| ::regex::escape(c.encode_utf8(&mut [0; 4]));
+    let mut regex = String::from(r"(?s)\A");
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                regex.push_str(&literal(chars[i]));
+            }
+            '[' => {
+                let negated = matches!(chars.get(i + 1), Some('!') | Some('^'));
+                let start = i + 1 + usize::from(negated);
+                // a ']' right at the start is part of the set
+                match (start + 1..chars.len()).find(|&j| chars[j] == ']') {
+                    Some(end) => {
+                        regex.push('[');
+                        if negated {
+                            regex.push('^');
+                        }
+                        for (n, &c) in chars[start..end].iter().enumerate() {
+                            if c == '-' && n > 0 && start + n + 1 < end {
+                                regex.push('-');
+                            } else {
+                                regex.push_str(&literal(c));
+                            }
+                        }
+                        regex.push(']');
+                        i = end;
+                    }
+                    None => regex.push_str(r"\["),
+                }
+            }
+            c => regex.push_str(&literal(c)),
+        }
+        i += 1;
+    }
+    regex.push_str(r"\n?\z");
+    regex
+}
+
+/// Matches 'patterns' against successive lines of 'text' in order, skipping lines that
+/// match no pattern. Returns None when all matched, otherwise the number of the line
+/// matched by the last matching pattern (0 for none) and the first pattern which did not
+/// match.
+pub(crate) fn match_lines<IP, P>(text: &str, patterns: IP) -> Option<(usize, String)>
+where
+    IP: IntoIterator<Item = P>,
+    P: AsRef<str>,
+{
+    let engine = engine();
+    let mut lines = text.lines().enumerate();
+    let mut matched = 0;
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        match lines.find(|(_, line)| engine.is_match(pattern, line)) {
+            Some((n, _)) => matched = n + 1,
+            None => return Some((matched, String::from(pattern))),
+        }
+    }
+    None
+}
+
+/// The capture groups of a match in group order, index 0 is the whole match. Each group
+/// carries its name, if any, and its byte range in the text when it participated.
+pub type Groups = Vec<(Option<String>, Option<Range<usize>>)>;
+
+/// The regular expression implementation behind the matching and capturing functions of
+/// this module and thus all 'TestOutput' assertions. Invalid expressions are programming
+/// errors in a test and fail it.
+pub trait RegexEngine: Send + Sync {
+    /// Returns whether 'regex' matches anywhere in 'text'.
+    fn is_match(&self, regex: &str, text: &str) -> bool;
+
+    /// Returns whether 'regex' matches anywhere in the raw 'input'.
+    fn is_match_bytes(&self, regex: &str, input: &[u8]) -> bool;
+
+    /// Returns the groups of the first match of 'regex' in 'text', empty when there is no
+    /// match.
+    fn captures(&self, regex: &str, text: &str) -> Groups;
+
+    /// Returns the groups of all non-overlapping matches of 'regex' in 'text'.
+    fn captures_iter(&self, regex: &str, text: &str) -> Vec<Groups>;
+
+    /// Returns whether 'regex' is a valid expression for this engine. Failure messages try
+    /// prefixes of the expected regex to show how far it matched, engines which do not tell
+    /// valid ones apart give no such hint.
+    fn is_valid(&self, _regex: &str) -> bool {
+        false
+    }
+}
+
+/// Fails the test because 'regex' does not compile.
+#[track_caller]
+fn invalid(regex: &str, err: impl Display) -> ! {
+    Failure::new("regex", format!("invalid regex {:?}: {}", regex, err)).fail()
+}
+
+/// How many compiled regexes a cache keeps before it starts over, bounds the memory taken
+/// by suites which match generated patterns.
+const CACHE_LIMIT: usize = 1024;
+
+/// Compiled regexes by pattern, shared by all threads. Assertions repeat the same patterns
+/// over and over, compiling each only once matters for big suites.
+struct Cache<R> {
+    compiled: Mutex<Option<HashMap<String, Arc<R>>>>,
+}
+
+impl<R> Cache<R> {
+    const fn new() -> Self {
+        Cache {
+            compiled: Mutex::new(None),
+        }
+    }
+
+    /// Returns the compiled 'regex', compiling it with 'compile' when not cached yet.
+    fn get<F: FnOnce(&str) -> R>(&self, regex: &str, compile: F) -> Arc<R> {
+        if let Some(re) = self
+            .compiled
+            .lock()
+            .expect("lock")
+            .as_ref()
+            .and_then(|compiled| compiled.get(regex))
+        {
+            return Arc::clone(re);
+        }
+        // compiled without holding the lock, racing threads may compile the same twice
+        let re = Arc::new(compile(regex));
+        let mut compiled = self.compiled.lock().expect("lock");
+        let compiled = compiled.get_or_insert_with(HashMap::new);
+        if compiled.len() >= CACHE_LIMIT {
+            compiled.clear();
+        }
+        compiled.insert(String::from(regex), Arc::clone(&re));
+        re
+    }
+}
+
+/// The 'regex' crate, fast and safe but without look-around and backreferences.
+pub struct StandardEngine;
+
+static STANDARD: Cache<::regex::Regex> = Cache::new();
+static STANDARD_BYTES: Cache<::regex::bytes::Regex> = Cache::new();
+
+impl StandardEngine {
+    fn compile(regex: &str) -> Arc<::regex::Regex> {
+        STANDARD.get(regex, |regex| {
+            ::regex::Regex::new(regex).unwrap_or_else(|err| invalid(regex, err))
+        })
+    }
+}
+
+impl RegexEngine for StandardEngine {
+    fn is_match(&self, regex: &str, text: &str) -> bool {
+        Self::compile(regex).is_match(text)
+    }
+
+    fn is_match_bytes(&self, regex: &str, input: &[u8]) -> bool {
+        STANDARD_BYTES
+            .get(regex, |regex| {
+                ::regex::bytes::Regex::new(regex).unwrap_or_else(|err| invalid(regex, err))
+            })
+            .is_match(input)
+    }
+
+    fn captures(&self, regex: &str, text: &str) -> Groups {
+        let re = Self::compile(regex);
+        match re.captures(text) {
+            Some(c) => re
+                .capture_names()
+                .enumerate()
+                .map(|(n, name)| (name.map(String::from), c.get(n).map(|m| m.range())))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn captures_iter(&self, regex: &str, text: &str) -> Vec<Groups> {
+        let re = Self::compile(regex);
+        re.captures_iter(text)
+            .map(|c| {
+                re.capture_names()
+                    .enumerate()
+                    .map(|(n, name)| (name.map(String::from), c.get(n).map(|m| m.range())))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn is_valid(&self, regex: &str) -> bool {
+        ::regex::Regex::new(regex).is_ok()
+    }
+}
+
+/// The 'fancy-regex' crate, adds look-around and backreferences to the 'regex' syntax.
+/// It matches text only, raw input is matched as lossy translated utf8.
+#[cfg(feature = "fancy")]
+pub struct FancyEngine;
+
+#[cfg(feature = "fancy")]
+static FANCY: Cache<fancy_regex::Regex> = Cache::new();
+
+#[cfg(feature = "fancy")]
+impl FancyEngine {
+    fn compile(regex: &str) -> Arc<fancy_regex::Regex> {
+        FANCY.get(regex, |regex| {
+            fancy_regex::Regex::new(regex).unwrap_or_else(|err| invalid(regex, err))
+        })
+    }
+}
+
+#[cfg(feature = "fancy")]
+impl RegexEngine for FancyEngine {
+    fn is_match(&self, regex: &str, text: &str) -> bool {
+        Self::compile(regex)
+            .is_match(text)
+            .expect("regex evaluated")
+    }
+
+    fn is_match_bytes(&self, regex: &str, input: &[u8]) -> bool {
+        self.is_match(regex, &String::from_utf8_lossy(input))
+    }
+
+    fn captures(&self, regex: &str, text: &str) -> Groups {
+        let re = Self::compile(regex);
+        match re.captures(text).expect("regex evaluated") {
+            Some(c) => re
+                .capture_names()
+                .enumerate()
+                .map(|(n, name)| (name.map(String::from), c.get(n).map(|m| m.range())))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn captures_iter(&self, regex: &str, text: &str) -> Vec<Groups> {
+        let re = Self::compile(regex);
+        re.captures_iter(text)
+            .map(|c| {
+                let c = c.expect("regex evaluated");
+                re.capture_names()
+                    .enumerate()
+                    .map(|(n, name)| (name.map(String::from), c.get(n).map(|m| m.range())))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn is_valid(&self, regex: &str) -> bool {
+        fancy_regex::Regex::new(regex).is_ok()
+    }
+}
+
+/// The PCRE2 library with perl compatible syntax. Needs a C compiler or the system library.
+#[cfg(feature = "pcre")]
+pub struct Pcre2Engine;
+
+#[cfg(feature = "pcre")]
+static PCRE2: Cache<pcre2::bytes::Regex> = Cache::new();
+#[cfg(feature = "pcre")]
+static PCRE2_UTF: Cache<pcre2::bytes::Regex> = Cache::new();
+
+#[cfg(feature = "pcre")]
+impl Pcre2Engine {
+    fn compile(regex: &str, utf: bool) -> Arc<pcre2::bytes::Regex> {
+        let cache = if utf { &PCRE2_UTF } else { &PCRE2 };
+        cache.get(regex, |regex| {
+            pcre2::bytes::RegexBuilder::new()
+                .utf(utf)
+                .build(regex)
+                .unwrap_or_else(|err| invalid(regex, err))
+        })
+    }
+}
+
+#[cfg(feature = "pcre")]
+impl RegexEngine for Pcre2Engine {
+    fn is_match(&self, regex: &str, text: &str) -> bool {
+        Self::compile(regex, true)
+            .is_match(text.as_bytes())
+            .expect("regex evaluated")
+    }
+
+    fn is_match_bytes(&self, regex: &str, input: &[u8]) -> bool {
+        Self::compile(regex, false)
+            .is_match(input)
+            .expect("regex evaluated")
+    }
+
+    fn captures(&self, regex: &str, text: &str) -> Groups {
+        let re = Self::compile(regex, true);
+        match re.captures(text.as_bytes()).expect("regex evaluated") {
+            Some(c) => re
+                .capture_names()
+                .iter()
+                .enumerate()
+                .map(|(n, name)| (name.clone(), c.get(n).map(|m| m.start()..m.end())))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn captures_iter(&self, regex: &str, text: &str) -> Vec<Groups> {
+        let re = Self::compile(regex, true);
+        re.captures_iter(text.as_bytes())
+            .map(|c| {
+                let c = c.expect("regex evaluated");
+                re.capture_names()
+                    .iter()
+                    .enumerate()
+                    .map(|(n, name)| (name.clone(), c.get(n).map(|m| m.start()..m.end())))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn is_valid(&self, regex: &str) -> bool {
+        pcre2::bytes::RegexBuilder::new()
+            .utf(true)
+            .build(regex)
+            .is_ok()
+    }
+}
+
+static ENGINE: RwLock<&'static dyn RegexEngine> = RwLock::new(&StandardEngine);
+
+/// Selects the engine used by all following matches in this process, the default is
+/// 'StandardEngine'. Since tests run in parallel this should be done once, before any test
+/// matches, and the expressions of the whole suite have to be valid for the chosen engine.
+pub fn set_regex_engine(engine: &'static dyn RegexEngine) {
+    *ENGINE.write().expect("lock") = engine;
+}
+
+fn engine() -> &'static dyn RegexEngine {
+    *ENGINE.read().expect("lock")
+}
+
 #[cfg(test)]
 #[cfg(unix)]
 mod test {
@@ -95,4 +610,148 @@ mod test {
         assert_eq!(&captures["first"], "Hello");
         assert_eq!(&captures["second"], "World!");
     }
+
+    #[test]
+    fn captures_iter() {
+        let captures = captures_iter_utf8(b"a=1 b=2 c=", r"(?P<key>\w)=(\d)?");
+
+        assert_eq!(captures.len(), 3);
+        assert_eq!(&captures[1][0], "b=2");
+        assert_eq!(&captures[1]["key"], "b");
+        assert_eq!(captures[1].get_as::<u32>(2), 2);
+        assert_eq!(captures[2].try_get(2), None);
+        assert!(captures_iter_utf8(b"none", r"\d").is_empty());
+    }
+
+    #[test]
+    fn find_end() {
+        assert_eq!(find_end_bytes(b"a\xffb\nc", "b"), Some(3));
+        assert_eq!(find_end_bytes(b"a\xff\xfeb\nc", "\n"), Some(5));
+        assert_eq!(find_end_bytes(b"a\xffb", "a."), Some(2));
+        assert_eq!(find_end_bytes(b"abc", "x"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid regex \"(unclosed\"")]
+    fn invalid_regex() {
+        regex_match_utf8(b"text", "(unclosed");
+    }
+
+    #[test]
+    fn cache() {
+        let cache = Cache::new();
+        let mut compiled = 0;
+        for _ in 0..3 {
+            cache.get("a+", |regex| {
+                compiled += 1;
+                ::regex::Regex::new(regex).expect("valid regex")
+            });
+        }
+        assert_eq!(compiled, 1);
+
+        for n in 0..=CACHE_LIMIT {
+            cache.get(&n.to_string(), |regex| {
+                ::regex::Regex::new(regex).expect("valid regex")
+            });
+        }
+        assert!(
+            cache
+                .compiled
+                .lock()
+                .expect("lock")
+                .as_ref()
+                .expect("cached")
+                .len()
+                <= CACHE_LIMIT
+        );
+    }
+
+    #[test]
+    fn closest() {
+        assert_eq!(
+            closest_match("one\ntwo\nthree\nfour\n", "^one\ntwo\nthree\nfive").as_deref(),
+            Some(
+                "matched up to line 4 by \"^one\\ntwo\\nthree\\nf\", \"ive\" does not match from there:\n\
+                 \x20    2 | two\n\
+                 \x20    3 | three\n\
+                 >    4 | four"
+            )
+        );
+        assert_eq!(closest_match("abc", "xyz"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "fancy")]
+    fn fancy_closest() {
+        assert_eq!(
+            closest_match_by(&FancyEngine, "one\ntwo\n", "^one\n(?=two)tx")
+                .expect("prefix matches")
+                .lines()
+                .next(),
+            Some("matched up to line 2 by \"^one\\n(?=two)t\", \"x\" does not match from there:")
+        );
+    }
+
+    #[test]
+    fn globs() {
+        let matches =
+            |pattern: &str, text: &str| regex_match_utf8(text.as_bytes(), &glob(pattern)).0;
+
+        assert!(matches("myprogram 0.1.*", "myprogram 0.1.7\n"));
+        assert!(!matches("myprogram 0.1.*", "myprogram 0.10.0\n"));
+        assert!(matches("/usr/*/lib?.so", "/usr/local/libc.so"));
+        assert!(matches("v[0-9].[!a-z]", "v1.2"));
+        assert!(!matches("v[0-9].[!a-z]", "v1.x"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches(r"\*[", "*["));
+        assert!(matches("one*three", "one\ntwo\nthree\n"));
+        assert!(!matches("one", "one\n\n"));
+    }
+
+    #[test]
+    fn typed_captures() {
+        let captures = captures_utf8(b"port 8080 tls true", r"port (?P<port>\d+)(x)? tls (\w+)");
+
+        assert_eq!(captures.get_as::<u16>("port"), 8080);
+        assert!(captures.get_as::<bool>(3));
+        assert_eq!(captures.try_get(2), None);
+        assert_eq!(captures.try_get("port"), Some("8080"));
+        assert_eq!(captures.try_get("missing"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "capture 3 \"true\" does not parse as u8")]
+    fn typed_capture_mismatch() {
+        let captures = captures_utf8(b"port 8080 tls true", r"port (\d+)(x)? tls (\w+)");
+
+        captures.get_as::<u8>(3);
+    }
+
+    #[test]
+    #[cfg(feature = "fancy")]
+    fn fancy_backreference() {
+        let engine = FancyEngine;
+        assert!(engine.is_match(r"^(\w+) \1$", "again again"));
+        assert!(!engine.is_match(r"^(\w+) \1$", "again once"));
+        assert!(engine.is_match_bytes(r"(?<=key=)\d+", b"key=42"));
+
+        let groups = engine.captures(r"(?P<word>\w+) (?P=word)", "say hey hey");
+        assert_eq!(groups[0], (None, Some(4..11)));
+        assert_eq!(groups[1], (Some(String::from("word")), Some(4..7)));
+        assert_eq!(engine.captures_iter(r"(\w)\1", "aab bcc").len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "pcre")]
+    fn pcre2_lookbehind() {
+        let engine = Pcre2Engine;
+        assert!(engine.is_match(r"(?<=key=)\d+", "key=42"));
+        assert!(!engine.is_match(r"(?<!key=)\b\d+", "key=42"));
+        assert!(engine.is_match_bytes(r"^\xff(.)\1$", b"\xffaa"));
+
+        let groups = engine.captures(r"(?<word>\w+) \1", "say hey hey");
+        assert_eq!(groups[1], (Some(String::from("word")), Some(4..7)));
+        assert!(engine.captures("nomatch", "text").is_empty());
+        assert_eq!(engine.captures_iter(r"(?<=k=)\d", "k=1 k=2 3").len(), 2);
+    }
 }
@@ -0,0 +1,271 @@
+//! Recording the outputs of calls and replaying them without executing, see
+//! 'TestCall::replay()'.
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output};
+use std::time::Duration;
+
+use crate::failure::Failure;
+use crate::history;
+use crate::testcall::PreparedCommand;
+use crate::{CallOutput, TestCall};
+
+/// When outputs are recorded and replayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// Always executes and records the output, replacing earlier recordings.
+    Record,
+    /// Never executes, returns the recorded output and panics when there is none.
+    Replay,
+    /// Returns the recorded output when there is one, executes and records otherwise.
+    ReplayOrRecord,
+}
+
+impl TestCall<'_> {
+    /// Runs 'command' according to 'mode', recordings are kept in 'dir'. A recording is
+    /// looked up by the program, its arguments and the data fed to stdin.
+    #[track_caller]
+    pub(crate) fn replay_run(
+        &self,
+        dir: &Path,
+        mode: ReplayMode,
+        command: PreparedCommand,
+        stdin: Option<&mut (dyn Read + Send)>,
+    ) -> CallOutput {
+        let input = stdin.map(|stdin| {
+            let mut input = Vec::new();
+            stdin.read_to_end(&mut input).expect("stdin read");
+            input
+        });
+        let recording = dir.join(key(&command, input.as_deref()));
+
+        if mode != ReplayMode::Record && recording.is_dir() {
+            let output = load(&recording);
+            history::record(&command, output.status, Duration::ZERO, None);
+            return CallOutput::new(output, Duration::ZERO, None);
+        }
+        if mode == ReplayMode::Replay {
+            Failure::new(
+                "replay",
+                format!("no recorded output for {:?} in {:?}", *command, dir),
+            )
+            .fail();
+        }
+
+        let description = format!("{:?}\n", *command);
+        let output = match input {
+            Some(input) => self.execute(command, Some(&mut &input[..])),
+            None => self.execute(command, None),
+        };
+        save(&recording, &description, &output);
+        output
+    }
+}
+
+/// The name of the recording for 'command' fed with 'input'.
+fn key(command: &Command, input: Option<&[u8]>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(os_bytes(command.get_program()));
+    for arg in command.get_args() {
+        hasher.update(b"\0");
+        hasher.update(os_bytes(arg));
+    }
+    if let Some(input) = input {
+        hasher.update(b"\0stdin\0");
+        hasher.update(input);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The raw bytes of 'string', arguments differing only in invalid utf8 get different keys.
+#[cfg(unix)]
+fn os_bytes(string: &OsStr) -> Vec<u8> {
+    std::os::unix::ffi::OsStrExt::as_bytes(string).to_vec()
+}
+
+/// The raw utf16 units of 'string' in little endian order.
+#[cfg(windows)]
+fn os_bytes(string: &OsStr) -> Vec<u8> {
+    std::os::windows::ffi::OsStrExt::encode_wide(string)
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Writes the recording into a temporary directory which is renamed into place, thus an
+/// interrupted write leaves no broken recording behind.
+#[track_caller]
+fn save(recording: &Path, description: &str, output: &Output) {
+    let failed = |err: std::io::Error| -> ! {
+        panic!("recording {:?} failed: {}", recording, err);
+    };
+    let parent = recording.parent().expect("recording in a directory");
+    fs::create_dir_all(parent).unwrap_or_else(|err| failed(err));
+    let partial = tempfile::Builder::new()
+        .prefix(".recording-")
+        .tempdir_in(parent)
+        .unwrap_or_else(|err| failed(err));
+    let write = |name: &str, data: &[u8]| {
+        fs::write(partial.path().join(name), data).unwrap_or_else(|err| failed(err));
+    };
+    write("command", description.as_bytes());
+    write("status", raw_status(output.status).to_string().as_bytes());
+    write("stdout", &output.stdout);
+    write("stderr", &output.stderr);
+    if recording.is_dir() {
+        fs::remove_dir_all(recording).unwrap_or_else(|err| failed(err));
+    }
+    fs::rename(partial.path(), recording).unwrap_or_else(|err| failed(err));
+}
+
+#[track_caller]
+fn load(recording: &Path) -> Output {
+    let read = |name: &str| match fs::read(recording.join(name)) {
+        Ok(data) => data,
+        Err(err) => panic!("broken recording {:?}: {}", recording, err),
+    };
+    let status = String::from_utf8_lossy(&read("status"))
+        .trim()
+        .parse()
+        .unwrap_or_else(|err| panic!("broken recording {:?}: status {}", recording, err));
+    Output {
+        status: from_raw_status(status),
+        stdout: read("stdout"),
+        stderr: read("stderr"),
+    }
+}
+
+#[cfg(unix)]
+fn raw_status(status: ExitStatus) -> i64 {
+    std::os::unix::process::ExitStatusExt::into_raw(status).into()
+}
+
+#[cfg(unix)]
+pub(crate) fn from_raw_status(raw: i64) -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(raw as i32)
+}
+
+#[cfg(windows)]
+fn raw_status(status: ExitStatus) -> i64 {
+    status.code().map_or(-1, |code| code as u32 as i64)
+}
+
+#[cfg(windows)]
+pub(crate) fn from_raw_status(raw: i64) -> ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(raw as u32)
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    #[test]
+    fn record_and_replay() {
+        let cache = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.replay(cache.path(), ReplayMode::ReplayOrRecord);
+
+        let recorded = testcall.call_args(["-c", "echo $$; echo err >&2; exit 3"]);
+        let replayed = testcall.call_args(["-c", "echo $$; echo err >&2; exit 3"]);
+        assert_eq!(recorded.stdout, replayed.stdout);
+        replayed.assert_exitcode(3).assert_stderr_utf8("^err\n$");
+
+        testcall.replay(cache.path(), ReplayMode::Replay);
+        testcall
+            .call_args(["-c", "echo $$; echo err >&2; exit 3"])
+            .assert_stdout_eq(&String::from_utf8_lossy(&recorded.stdout));
+
+        testcall.replay(cache.path(), ReplayMode::Record);
+        assert_ne!(
+            testcall
+                .call_args(["-c", "echo $$; echo err >&2; exit 3"])
+                .stdout,
+            recorded.stdout
+        );
+    }
+
+    #[test]
+    fn stdin_is_part_of_the_key() {
+        let cache = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("cat"));
+        testcall.replay(cache.path(), ReplayMode::ReplayOrRecord);
+
+        testcall
+            .call_with_stdin(NO_ARGS, b"one")
+            .assert_stdout_eq("one");
+        testcall
+            .call_with_stdin(NO_ARGS, b"two")
+            .assert_stdout_eq("two");
+    }
+
+    #[test]
+    fn batch_and_pipe_replayed() {
+        let cache = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.replay(cache.path(), ReplayMode::ReplayOrRecord);
+        let mut cat = TestCall::external_command(Path::new("cat"));
+        cat.replay(cache.path(), ReplayMode::ReplayOrRecord);
+
+        let recorded = testcall.call_batch([["-c", "echo $$"]]);
+        let piped = testcall
+            .clone()
+            .base_args(["-c", "echo $$"])
+            .pipe(&cat)
+            .run();
+        testcall.replay(cache.path(), ReplayMode::Replay);
+        cat.replay(cache.path(), ReplayMode::Replay);
+        assert_eq!(
+            testcall.call_batch([["-c", "echo $$"]])[0].stdout,
+            recorded[0].stdout
+        );
+        assert_eq!(
+            testcall
+                .base_args(["-c", "echo $$"])
+                .pipe(&cat)
+                .run()
+                .stage(1)
+                .stdout,
+            piped.stage(1).stdout
+        );
+    }
+
+    #[test]
+    fn non_utf8_arguments() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let cache = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.replay(cache.path(), ReplayMode::ReplayOrRecord);
+
+        for byte in [0xfe, 0xff] {
+            testcall
+                .call_args([
+                    OsStr::new("-c"),
+                    OsStr::new("printf %s \"$0\" | od -An -tx1"),
+                    OsStr::from_bytes(&[byte]),
+                ])
+                .assert_stdout_utf8(&format!("{:02x}", byte));
+        }
+        // only the two recordings, no leftover partial ones
+        assert_eq!(std::fs::read_dir(cache.path()).expect("listed").count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "no recorded output for")]
+    fn replay_missing() {
+        let cache = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("echo"));
+        testcall.replay(cache.path(), ReplayMode::Replay);
+
+        testcall.call_args(["never recorded"]);
+    }
+}
@@ -0,0 +1,331 @@
+//! Result reports for CI dashboards. Setting 'TESTCALL_REPORT_JUNIT' or 'TESTCALL_REPORT_TAP'
+//! to a file name records every call and every assertion failure of the test process as
+//! test case in a JUnit XML or TAP file. Each test binary writes its own file with the binary
+//! name inserted before the extension, 'report.xml' becomes 'report.NAME.xml', and writes it
+//! once when the process exits. Test cases are named after the test (thread) which made
+//! them.
+use std::env;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::failure::Failure;
+use crate::history::CallRecord;
+
+/// A single reported test case.
+struct Entry {
+    test: String,
+    name: String,
+    duration: Duration,
+    failure: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    JUnit,
+    Tap,
+}
+
+struct Report {
+    targets: Vec<(Format, PathBuf)>,
+    entries: Mutex<Vec<Entry>>,
+}
+
+fn report() -> Option<&'static Report> {
+    static REPORT: OnceLock<Option<Report>> = OnceLock::new();
+    REPORT
+        .get_or_init(|| {
+            let targets: Vec<_> = [
+                (Format::JUnit, "TESTCALL_REPORT_JUNIT"),
+                (Format::Tap, "TESTCALL_REPORT_TAP"),
+            ]
+            .iter()
+            .filter_map(|&(format, var)| {
+                env::var_os(var).map(|path| (format, per_binary(Path::new(&path), &binary())))
+            })
+            .collect();
+            if targets.is_empty() {
+                None
+            } else {
+                // SAFETY: 'write' is a plain function which stays valid until the process ends
+                if unsafe { atexit(write) } != 0 {
+                    eprintln!("report can not be written at exit");
+                }
+                Some(Report {
+                    targets,
+                    entries: Mutex::new(Vec::new()),
+                })
+            }
+        })
+        .as_ref()
+}
+
+extern "C" {
+    // from the C standard library, runs 'callback' on 'exit()' and after 'main()' returned
+    fn atexit(callback: extern "C" fn()) -> std::os::raw::c_int;
+}
+
+/// The file name of the running executable without extension, cargo names test binaries
+/// after their target and a hash.
+fn binary() -> OsString {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_stem().map(OsString::from))
+        .unwrap_or_else(|| OsString::from(std::process::id().to_string()))
+}
+
+/// Inserts 'binary' into 'path' before the extension.
+fn per_binary(path: &Path, binary: &OsString) -> PathBuf {
+    let mut name = path.file_stem().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(binary);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Writes the reports, registered to run when the process exits.
+extern "C" fn write() {
+    if let Some(report) = report() {
+        let entries = report
+            .entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (format, path) in &report.targets {
+            let rendered = match format {
+                Format::JUnit => render_junit(&entries),
+                Format::Tap => render_tap(&entries),
+            };
+            if let Err(err) = fs::write(path, rendered) {
+                eprintln!("writing report {:?} failed: {}", path, err);
+            }
+        }
+    }
+}
+
+/// Reports a finished call.
+pub(crate) fn call(record: &CallRecord) {
+    add(|| Entry {
+        test: current_test(),
+        name: record.command().to_string(),
+        duration: record.duration(),
+        failure: None,
+    });
+}
+
+/// Reports a failed assertion.
+pub(crate) fn failure(failure: &Failure) {
+    add(|| {
+        let mut details = String::from(failure.message());
+        if let Some(expected) = failure.expected() {
+            let _ = write!(details, "\nexpected:\n{}", expected);
+        }
+        if let Some(actual) = failure.actual() {
+            let _ = write!(details, "\n{} was:\n{}", failure.subject(), actual);
+        }
+        Entry {
+            test: current_test(),
+            name: failure.kind().to_string(),
+            duration: Duration::ZERO,
+            failure: Some(details),
+        }
+    });
+}
+
+fn add<F: FnOnce() -> Entry>(entry: F) {
+    if let Some(report) = report() {
+        // a panicking test must not poison the report for the others
+        let mut entries = report
+            .entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        entries.push(entry());
+    }
+}
+
+/// The name of the running test, the test harness names each test thread after it.
+fn current_test() -> String {
+    thread::current().name().unwrap_or("main").to_string()
+}
+
+fn render_junit(entries: &[Entry]) -> String {
+    let failures = entries
+        .iter()
+        .filter(|entry| entry.failure.is_some())
+        .count();
+    let total: Duration = entries.iter().map(|entry| entry.duration).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites>\n<testsuite name=\"testcall\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+        entries.len(),
+        failures,
+        total.as_secs_f64()
+    );
+    for entry in entries {
+        let _ = write!(
+            xml,
+            "<testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            xml_escape(&entry.test),
+            xml_escape(&entry.name),
+            entry.duration.as_secs_f64()
+        );
+        match &entry.failure {
+            None => xml.push_str("/>\n"),
+            Some(details) => {
+                let _ = writeln!(
+                    xml,
+                    "><failure message=\"{}\">{}</failure></testcase>",
+                    xml_escape(details.lines().next().unwrap_or_default()),
+                    xml_escape(details)
+                );
+            }
+        }
+    }
+    xml.push_str("</testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn render_tap(entries: &[Entry]) -> String {
+    let mut tap = String::from("TAP version 13\n");
+    for (n, entry) in entries.iter().enumerate() {
+        // '#' starts a directive in TAP, keep it out of descriptions
+        let description = format!("{}: {}", entry.test, entry.name).replace('#', "\\#");
+        match &entry.failure {
+            None => {
+                let _ = writeln!(tap, "ok {} - {}", n + 1, description);
+            }
+            Some(details) => {
+                let _ = writeln!(tap, "not ok {} - {}", n + 1, description);
+                tap.push_str("  ---\n  message: |\n");
+                for line in details.lines() {
+                    let _ = writeln!(tap, "    {}", line);
+                }
+                tap.push_str("  ...\n");
+            }
+        }
+    }
+    let _ = writeln!(tap, "1..{}", entries.len());
+    tap
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#10;"),
+            // XML 1.0 can not carry other control characters at all
+            c if c.is_control() && c != '\t' && c != '\r' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(unix)]
+    use crate::TestOutput;
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry {
+                test: String::from("tests::echo"),
+                name: String::from("\"echo\" \"<hi>\""),
+                duration: Duration::from_millis(12),
+                failure: None,
+            },
+            Entry {
+                test: String::from("tests::echo"),
+                name: String::from("assert_stdout_utf8"),
+                duration: Duration::ZERO,
+                failure: Some(String::from("stdout does not match\nexpected:\n^bye")),
+            },
+        ]
+    }
+
+    #[test]
+    fn junit() {
+        assert_eq!(
+            render_junit(&entries()),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <testsuites>\n\
+             <testsuite name=\"testcall\" tests=\"2\" failures=\"1\" time=\"0.012\">\n\
+             <testcase classname=\"tests::echo\" name=\"&quot;echo&quot; &quot;&lt;hi&gt;&quot;\" time=\"0.012\"/>\n\
+             <testcase classname=\"tests::echo\" name=\"assert_stdout_utf8\" time=\"0.000\">\
+             <failure message=\"stdout does not match\">stdout does not match&#10;expected:&#10;^bye</failure>\
+             </testcase>\n\
+             </testsuite>\n\
+             </testsuites>\n"
+        );
+    }
+
+    #[test]
+    fn tap() {
+        assert_eq!(
+            render_tap(&entries()),
+            "TAP version 13\n\
+             ok 1 - tests::echo: \"echo\" \"<hi>\"\n\
+             not ok 2 - tests::echo: assert_stdout_utf8\n  \
+             ---\n  \
+             message: |\n    \
+             stdout does not match\n    \
+             expected:\n    \
+             ^bye\n  \
+             ...\n\
+             1..2\n"
+        );
+    }
+
+    #[test]
+    fn file_names() {
+        let binary = OsString::from("testcall-0123");
+        assert_eq!(
+            per_binary(Path::new("target/report.xml"), &binary),
+            Path::new("target/report.testcall-0123.xml")
+        );
+        assert_eq!(
+            per_binary(Path::new("report"), &binary),
+            Path::new("report.testcall-0123")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reported_call() {
+        crate::TestCall::external_command(Path::new("true")).call();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn written_at_exit() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let exe = env::current_exe().expect("test executable");
+        let mut testcall = crate::TestCall::external_command(&exe);
+        testcall.inherit_env();
+
+        testcall
+            .call_args_envs(
+                ["--exact", "report::test::reported_call"],
+                [("TESTCALL_REPORT_TAP", tmpdir.path().join("report.tap"))],
+            )
+            .assert_success();
+        let report = tmpdir
+            .path()
+            .join(format!("report.{}.tap", binary().to_string_lossy()));
+        let tap = fs::read_to_string(&report).expect("report written");
+        assert!(tap.starts_with("TAP version 13\nok 1 - report::test::reported_call: "));
+        assert!(tap.ends_with("\n1..1\n"));
+    }
+}
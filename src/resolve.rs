@@ -0,0 +1,119 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+#[cfg(windows)]
+use std::{env, ffi::OsString, path::PathBuf};
+
+/// Resolves the path given to 'TestCall::external_command()' to the executable that will be
+/// called. On unix this is left to the operating system.
+#[cfg(not(windows))]
+pub(crate) fn external(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+/// Resolves the path given to 'TestCall::external_command()' to the executable that will be
+/// called. Applies the extensions from 'PATHEXT', searches 'PATH' for bare names and finally
+/// looks into the 'App Paths' registry keys. Panics with the list of all searched locations
+/// when nothing was found.
+#[cfg(windows)]
+#[track_caller]
+pub(crate) fn external(path: &Path) -> Cow<'_, Path> {
+    let searched = candidates(path, env::var_os("PATH"), env::var_os("PATHEXT"));
+    if let Some(found) = searched.iter().find(|candidate| candidate.is_file()) {
+        return Cow::Owned(found.clone());
+    }
+    if path.components().count() == 1 {
+        if let Some(found) = app_path(path) {
+            return Cow::Owned(found);
+        }
+    }
+
+    let mut message = format!("executable not found: {:?}\nsearched:", path);
+    for candidate in &searched {
+        message.push_str(&format!("\n  {}", candidate.display()));
+    }
+    message.push_str("\n  registry: App Paths (HKCU, HKLM)");
+    panic!("{}", message);
+}
+
+/// Lists the paths to try for 'path' in order. Paths with more than one component are only
+/// tried with the 'PATHEXT' extensions, bare names are searched in every 'PATH' directory.
+#[cfg(windows)]
+fn candidates(path: &Path, path_var: Option<OsString>, pathext: Option<OsString>) -> Vec<PathBuf> {
+    let pathext = pathext
+        .map(|pathext| pathext.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from(".COM;.EXE;.BAT;.CMD"));
+    let names = |base: &Path| {
+        let mut names = Vec::new();
+        if base.extension().is_some() {
+            names.push(base.to_path_buf());
+        }
+        for ext in pathext.split(';').filter(|ext| !ext.is_empty()) {
+            let mut name = base.as_os_str().to_os_string();
+            name.push(ext);
+            names.push(PathBuf::from(name));
+        }
+        names
+    };
+
+    if path.components().count() > 1 || path.is_absolute() {
+        return names(path);
+    }
+
+    let mut dirs = vec![env::current_dir().expect("current dir")];
+    if let Some(path_var) = path_var {
+        dirs.extend(env::split_paths(&path_var));
+    }
+    dirs.iter().flat_map(|dir| names(&dir.join(path))).collect()
+}
+
+/// Looks up 'name' under the 'App Paths' registry keys of the current user and the machine.
+#[cfg(windows)]
+fn app_path(name: &Path) -> Option<PathBuf> {
+    use winreg::enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+    use winreg::RegKey;
+
+    let mut name = name.as_os_str().to_os_string();
+    if Path::new(&name).extension().is_none() {
+        name.push(".exe");
+    }
+    let key = format!(
+        "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\App Paths\\{}",
+        name.to_string_lossy()
+    );
+
+    [HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE]
+        .iter()
+        .filter_map(|root| RegKey::predef(*root).open_subkey(&key).ok())
+        .filter_map(|key| key.get_value::<String, _>("").ok())
+        .map(|value| PathBuf::from(value.trim_matches('"')))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+#[cfg(windows)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn candidates_pathext() {
+        let found = candidates(
+            Path::new("git"),
+            Some(OsString::from("C:\\bin")),
+            Some(OsString::from(".EXE;.CMD")),
+        );
+        assert!(found.contains(&PathBuf::from("C:\\bin\\git.EXE")));
+        assert!(found.contains(&PathBuf::from("C:\\bin\\git.CMD")));
+    }
+
+    #[test]
+    fn resolves_cmd() {
+        assert!(external(Path::new("cmd")).is_file());
+    }
+
+    #[test]
+    #[should_panic(expected = "searched")]
+    fn not_found() {
+        external(Path::new("testcall-no-such-executable"));
+    }
+}
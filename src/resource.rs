@@ -0,0 +1,125 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+
+/// Serializes tests which must not run concurrently, for example because they use a fixed
+/// port or a shared device. Acquiring blocks while any other holder of the same name exists,
+/// within this process and across processes (like separate 'cargo test' binaries) through a
+/// lock file in the temporary directory. Released when dropped.
+///
+/// ```rust,no_run
+/// # use testcall::SharedResource;
+/// let _port = SharedResource::acquire("port-8080");
+/// // start the server on port 8080
+/// ```
+pub struct SharedResource {
+    #[cfg_attr(unix, allow(dead_code))]
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl SharedResource {
+    /// Blocks until the resource 'name' is acquired. Characters which are not valid in file
+    /// names on all platforms are replaced.
+    #[track_caller]
+    pub fn acquire(name: &str) -> SharedResource {
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path = env::temp_dir().join(format!("testcall-resource-{}.lock", name));
+        let file = lock(&path);
+        SharedResource {
+            path,
+            file: Some(file),
+        }
+    }
+}
+
+/// flock() locks belong to the open file, thus threads of the same process exclude each
+/// other as well. The lock is released by the kernel when the holder dies.
+#[cfg(unix)]
+fn lock(path: &std::path::Path) -> File {
+    use std::os::unix::io::AsRawFd;
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .expect("lock file opened");
+    loop {
+        // SAFETY: the descriptor is owned by 'file' which is alive
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return file;
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            panic!("locking {:?} failed: {}", path, err);
+        }
+    }
+}
+
+/// Without flock() the lock file itself is the lock, it is created exclusively and removed
+/// on release. A holder that crashed leaves a stale lock file behind which must be removed
+/// manually.
+#[cfg(not(unix))]
+fn lock(path: &std::path::Path) -> File {
+    use std::io::ErrorKind;
+    use std::thread;
+    use std::time::Duration;
+
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(file) => return file,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(err) => panic!("creating lock file {:?} failed: {}", path, err),
+        }
+    }
+}
+
+impl Drop for SharedResource {
+    fn drop(&mut self) {
+        // closing releases a flock(), the lock file stays, removing it would race
+        drop(self.file.take());
+        #[cfg(not(unix))]
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn exclusive() {
+        static HELD: AtomicBool = AtomicBool::new(false);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..5 {
+                        let _guard = SharedResource::acquire("testcall/selftest");
+                        assert!(!HELD.swap(true, Ordering::SeqCst), "held twice");
+                        thread::sleep(Duration::from_millis(2));
+                        HELD.store(false, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().expect("no concurrent holders");
+        }
+    }
+}
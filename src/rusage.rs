@@ -0,0 +1,208 @@
+//! Waiting for children while collecting the resources they used. On unix this uses
+//! 'wait4()' which reports the rusage of exactly the reaped child, on other platforms the
+//! plain 'std' waiting functions are used and no measurements are available. Resource
+//! limits for children are applied with 'setrlimit()' on unix.
+use std::process::{Child, ExitStatus};
+use std::time::Duration;
+
+/// The CPU time a finished child spent in user and kernel mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuTime {
+    /// Time spent executing in user mode.
+    pub user: Duration,
+    /// Time spent in the kernel on behalf of the child.
+    pub system: Duration,
+}
+
+impl CpuTime {
+    /// User and system time together.
+    pub fn total(&self) -> Duration {
+        self.user + self.system
+    }
+}
+
+/// What a finished child consumed.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Usage {
+    pub(crate) cpu_time: CpuTime,
+    /// The peak resident set size in bytes as reported by the kernel.
+    pub(crate) max_rss: u64,
+    /// The peak resident set size of the test process when the child was reaped. On linux
+    /// the kernel charges the memory of the spawning process to the child until it
+    /// executes, a 'max_rss' up to this size can be that instead of the child's own.
+    pub(crate) spawner_rss: u64,
+}
+
+impl Usage {
+    /// The peak resident set size of the executed program itself, None when it stayed
+    /// within 'spawner_rss' and can not be told apart.
+    pub(crate) fn peak_rss(&self) -> Option<u64> {
+        if self.max_rss > self.spawner_rss {
+            Some(self.max_rss)
+        } else {
+            None
+        }
+    }
+}
+
+/// The peak resident set size of the test process itself which the kernel charged to a
+/// child before it executed, 0 where children start with their own accounting.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn spawner_rss() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmHWM:"))
+                .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        })
+        .map_or(u64::MAX, |kb| kb * 1024)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn spawner_rss() -> u64 {
+    0
+}
+
+/// Waits for 'child' to exit.
+#[cfg(unix)]
+pub(crate) fn wait(child: &mut Child) -> (ExitStatus, Option<Usage>) {
+    wait4(child, 0).expect("child exited")
+}
+
+/// Returns the exit status when 'child' exited already, does not block.
+#[cfg(unix)]
+pub(crate) fn try_wait(child: &mut Child) -> Option<(ExitStatus, Option<Usage>)> {
+    wait4(child, libc::WNOHANG)
+}
+
+#[cfg(unix)]
+fn wait4(child: &mut Child, options: libc::c_int) -> Option<(ExitStatus, Option<Usage>)> {
+    use std::io;
+    use std::os::unix::process::ExitStatusExt;
+
+    let timeval = |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+
+    let mut status = 0;
+    // SAFETY: rusage is plain old data for which all zeroes is a valid value
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: both pointers refer to live locals, the pid is our own unreaped child
+        let pid =
+            unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, options, &mut usage) };
+        match pid {
+            0 => return None,
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    panic!("wait4 failed: {}", err);
+                }
+            }
+            _ => {
+                return Some((
+                    ExitStatus::from_raw(status),
+                    Some(Usage {
+                        cpu_time: CpuTime {
+                            user: timeval(usage.ru_utime),
+                            system: timeval(usage.ru_stime),
+                        },
+                        // macOS reports bytes, the others kilobytes
+                        max_rss: if cfg!(target_os = "macos") {
+                            usage.ru_maxrss as u64
+                        } else {
+                            usage.ru_maxrss as u64 * 1024
+                        },
+                        // sampled after reaping, the peak of the test process only grows
+                        spawner_rss: spawner_rss(),
+                    }),
+                ));
+            }
+        }
+    }
+}
+
+/// Checks whether 'child' exited without reaping it, thus its pid stays reserved and a
+/// later wait still gets the status. Always false on platforms where this can not be
+/// checked.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn has_exited(child: &Child) -> bool {
+    // SAFETY: siginfo_t is plain old data for which all zeroes is a valid value
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    // SAFETY: 'info' is a live local, WNOWAIT leaves the child unreaped
+    let ret = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            child.id() as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    // SAFETY: waitid filled 'info' in
+    ret == 0 && unsafe { info.si_pid() } != 0
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+pub(crate) fn has_exited(_child: &Child) -> bool {
+    false
+}
+
+/// Waits for 'child' to exit.
+#[cfg(not(unix))]
+pub(crate) fn wait(child: &mut Child) -> (ExitStatus, Option<Usage>) {
+    (child.wait().expect("child exited"), None)
+}
+
+/// Returns the exit status when 'child' exited already, does not block.
+#[cfg(not(unix))]
+pub(crate) fn try_wait(child: &mut Child) -> Option<(ExitStatus, Option<Usage>)> {
+    child
+        .try_wait()
+        .expect("wait success")
+        .map(|status| (status, None))
+}
+
+/// Resource limits applied to a child right before it executes.
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Limits {
+    pub(crate) memory: Option<u64>,
+    pub(crate) cpu_time: Option<u64>,
+    pub(crate) open_files: Option<u64>,
+}
+
+#[cfg(unix)]
+impl Limits {
+    /// Sets the limits in the child of 'command', nothing happens when no limit is
+    /// configured.
+    pub(crate) fn apply(self, command: &mut std::process::Command) {
+        use std::os::unix::process::CommandExt;
+
+        if self.memory.is_none() && self.cpu_time.is_none() && self.open_files.is_none() {
+            return;
+        }
+        // SAFETY: the closure only makes async signal safe syscalls
+        unsafe {
+            command.pre_exec(move || {
+                // the hard cpu limit kills with SIGKILL, one second of grace gives a SIGXCPU
+                // first
+                for (resource, limit, grace) in [
+                    (libc::RLIMIT_AS, self.memory, 0),
+                    (libc::RLIMIT_CPU, self.cpu_time, 1),
+                    (libc::RLIMIT_NOFILE, self.open_files, 0),
+                ] {
+                    if let Some(limit) = limit {
+                        let limit = libc::rlimit {
+                            rlim_cur: limit as libc::rlim_t,
+                            rlim_max: limit.saturating_add(grace) as libc::rlim_t,
+                        };
+                        if libc::setrlimit(resource, &limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
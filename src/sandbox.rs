@@ -0,0 +1,418 @@
+//! Running children in fresh mount, PID and network namespaces with a pristine filesystem
+//! view ('sandbox' feature, linux only).
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use crate::netns::write_file;
+use crate::TestCall;
+
+/// The directories of the host which are visible read-only in the sandbox when they exist.
+const SYSTEM_DIRS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc"];
+
+/// The signals the sandbox supervisor forwards to the sandboxed program.
+const FORWARDED_SIGNALS: &[libc::c_int] = &[
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTERM,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+];
+
+/// The pid of the sandboxed program as seen by the supervisor.
+static SANDBOXED: AtomicI32 = AtomicI32::new(0);
+
+/// A step building the root of the sandbox, all paths are nul terminated and prepared
+/// before forking since allocating between fork and exec is not allowed.
+enum Step {
+    Mkdir(CString),
+    Symlink {
+        target: CString,
+        link: CString,
+    },
+    Bind {
+        source: CString,
+        target: CString,
+        readonly: bool,
+    },
+    Tmpfs(CString),
+    Touch(CString),
+}
+
+impl TestCall<'_> {
+    /// Runs calls in new mount, PID and network namespaces. The call sees a fresh root
+    /// with the system directories ('/usr', '/etc', ...) read-only, '/dev', an empty '/tmp',
+    /// its own '/proc', the executable and the test directory (writable, at the same path
+    /// and as working directory), but nothing else of the host filesystem. Only a loopback
+    /// network device exists and the executable runs as PID 1 under a supervisor which
+    /// forwards signals to it. Needs a test directory set with 'current_dir()' or a
+    /// directory backend, and root or unprivileged user namespaces.
+    pub fn sandbox(&mut self, sandbox: bool) -> &mut Self {
+        self.sandbox = sandbox;
+        self
+    }
+}
+
+/// Sets up 'command' to enter a sandbox right before it executes, see 'TestCall::sandbox()'.
+#[track_caller]
+pub(crate) fn enter(command: &mut Command) {
+    let dir = match command.get_current_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => panic!("the sandbox needs a test directory, set one with 'current_dir()'"),
+    };
+    let dir = fs::canonicalize(&dir).unwrap_or(dir);
+    let stage = tempfile::Builder::new()
+        .prefix(".testcall-sandbox")
+        .tempdir()
+        .expect("sandbox directory created");
+    let root = stage.path().to_path_buf();
+
+    let mut steps = Vec::new();
+    steps.push(Step::Tmpfs(cstring(&root)));
+    for dir in SYSTEM_DIRS {
+        let path = Path::new(dir);
+        let inside = root.join(path.strip_prefix("/").expect("absolute path"));
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_symlink() => steps.push(Step::Symlink {
+                target: cstring(&fs::read_link(path).expect("symlink read")),
+                link: cstring(&inside),
+            }),
+            Ok(meta) if meta.is_dir() => {
+                steps.push(Step::Mkdir(cstring(&inside)));
+                steps.push(Step::Bind {
+                    source: cstring(path),
+                    target: cstring(&inside),
+                    readonly: true,
+                });
+            }
+            _ => {}
+        }
+    }
+    steps.push(Step::Mkdir(cstring(&root.join("dev"))));
+    steps.push(Step::Bind {
+        source: cstring(Path::new("/dev")),
+        target: cstring(&root.join("dev")),
+        readonly: false,
+    });
+    steps.push(Step::Mkdir(cstring(&root.join("tmp"))));
+    steps.push(Step::Tmpfs(cstring(&root.join("tmp"))));
+    steps.push(Step::Mkdir(cstring(&root.join("proc"))));
+
+    let program = PathBuf::from(command.get_program());
+    if program.is_absolute() && !SYSTEM_DIRS.iter().any(|dir| program.starts_with(dir)) {
+        let program = fs::canonicalize(&program).unwrap_or(program);
+        mkdirs(
+            &mut steps,
+            &root,
+            program.parent().expect("program in a directory"),
+        );
+        steps.push(Step::Touch(cstring(&inside(&root, &program))));
+        steps.push(Step::Bind {
+            source: cstring(&program),
+            target: cstring(&inside(&root, &program)),
+            readonly: true,
+        });
+    }
+    mkdirs(&mut steps, &root, &dir);
+    steps.push(Step::Bind {
+        source: cstring(&dir),
+        target: cstring(&inside(&root, &dir)),
+        readonly: false,
+    });
+
+    let proc_dir = cstring(&root.join("proc"));
+    let root_dir = cstring(&root);
+    let work_dir = cstring(&dir);
+    // SAFETY: these calls can not fail
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let uid_map = format!("{} {} 1", uid, uid);
+    let gid_map = format!("{} {} 1", gid, gid);
+
+    // SAFETY: the closure only makes async signal safe syscalls, the supervisor never
+    // returns from it
+    unsafe {
+        command.pre_exec(move || {
+            // the stage directory is removed when the command is dropped
+            let _ = &stage;
+            let namespaces = libc::CLONE_NEWNS | libc::CLONE_NEWPID | libc::CLONE_NEWNET;
+            if libc::unshare(namespaces) != 0 {
+                if libc::unshare(libc::CLONE_NEWUSER | namespaces) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                write_file(b"/proc/self/setgroups\0", b"deny")?;
+                write_file(b"/proc/self/uid_map\0", uid_map.as_bytes())?;
+                write_file(b"/proc/self/gid_map\0", gid_map.as_bytes())?;
+            }
+            check(libc::mount(
+                ptr::null(),
+                b"/\0".as_ptr() as *const libc::c_char,
+                ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                ptr::null(),
+            ))?;
+            for step in &steps {
+                step.run()?;
+            }
+
+            // only children of the caller enter the new PID namespace
+            match libc::fork() {
+                -1 => Err(io::Error::last_os_error()),
+                0 => {
+                    libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+                    check(libc::mount(
+                        b"proc\0".as_ptr() as *const libc::c_char,
+                        proc_dir.as_ptr(),
+                        b"proc\0".as_ptr() as *const libc::c_char,
+                        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                        ptr::null(),
+                    ))?;
+                    check(libc::chroot(root_dir.as_ptr()))?;
+                    check(libc::chdir(work_dir.as_ptr()))
+                }
+                pid => supervise(pid),
+            }
+        });
+    }
+}
+
+impl Step {
+    /// Runs the step in the child, without allocating.
+    unsafe fn run(&self) -> io::Result<()> {
+        match self {
+            Step::Mkdir(path) => {
+                if libc::mkdir(path.as_ptr(), 0o755) != 0
+                    && io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST)
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            }
+            Step::Symlink { target, link } => check(libc::symlink(target.as_ptr(), link.as_ptr())),
+            Step::Tmpfs(path) => check(libc::mount(
+                b"tmpfs\0".as_ptr() as *const libc::c_char,
+                path.as_ptr(),
+                b"tmpfs\0".as_ptr() as *const libc::c_char,
+                libc::MS_NOSUID | libc::MS_NODEV,
+                b"mode=0755\0".as_ptr() as *const libc::c_void,
+            )),
+            Step::Touch(path) => {
+                let fd = libc::open(
+                    path.as_ptr(),
+                    libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC,
+                    0o644,
+                );
+                if fd == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                libc::close(fd);
+                Ok(())
+            }
+            Step::Bind {
+                source,
+                target,
+                readonly,
+            } => {
+                check(libc::mount(
+                    source.as_ptr(),
+                    target.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    ptr::null(),
+                ))?;
+                if !readonly {
+                    return Ok(());
+                }
+                // flags locked by a user namespace have to be kept when remounting
+                let mut stat: libc::statvfs = std::mem::zeroed();
+                check(libc::statvfs(source.as_ptr(), &mut stat))?;
+                let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+                for (st, ms) in [
+                    (libc::ST_NOSUID, libc::MS_NOSUID),
+                    (libc::ST_NODEV, libc::MS_NODEV),
+                    (libc::ST_NOEXEC, libc::MS_NOEXEC),
+                ] {
+                    if stat.f_flag & st != 0 {
+                        flags |= ms;
+                    }
+                }
+                check(libc::mount(
+                    ptr::null(),
+                    target.as_ptr(),
+                    ptr::null(),
+                    flags,
+                    ptr::null(),
+                ))
+            }
+        }
+    }
+}
+
+/// Waits in the parent PID namespace for the sandboxed program 'pid' while forwarding
+/// signals to it, then exits the same way it did.
+unsafe fn supervise(pid: libc::pid_t) -> ! {
+    extern "C" fn forward(signal: libc::c_int) {
+        // SAFETY: kill is async signal safe
+        unsafe {
+            libc::kill(SANDBOXED.load(Ordering::Relaxed), signal);
+        }
+    }
+
+    // holding the exec error pipe of 'Command::spawn()' open would block it
+    if libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, 0) != 0 {
+        for fd in 3..1024 {
+            libc::close(fd);
+        }
+    }
+    SANDBOXED.store(pid, Ordering::Relaxed);
+    for &signal in FORWARDED_SIGNALS {
+        libc::signal(signal, forward as *const () as libc::sighandler_t);
+    }
+    let mut status = 0;
+    while libc::waitpid(pid, &mut status, 0) == -1 {
+        if io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+            libc::_exit(127);
+        }
+    }
+    if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        libc::signal(signal, libc::SIG_DFL);
+        let mut mask: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        libc::sigaddset(&mut mask, signal);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &mask, ptr::null_mut());
+        libc::kill(libc::getpid(), signal);
+        libc::_exit(128 + signal);
+    }
+    libc::_exit(libc::WEXITSTATUS(status))
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn cstring(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).expect("path without nul")
+}
+
+/// The location of the absolute 'path' within 'root'.
+fn inside(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").expect("absolute path"))
+}
+
+/// Adds the steps creating 'dir' and all its parents within 'root'.
+fn mkdirs(steps: &mut Vec<Step>, root: &Path, dir: &Path) {
+    let mut ancestors: Vec<&Path> = dir
+        .ancestors()
+        .filter(|dir| dir.parent().is_some())
+        .collect();
+    ancestors.reverse();
+    for dir in ancestors {
+        steps.push(Step::Mkdir(cstring(&inside(root, dir))));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn pristine_view() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&tmpdir).sandbox(true);
+
+        testcall
+            .call_args([
+                "-c",
+                "echo $$; pwd; ls /tmp | wc -l; test -e /root || echo no root; \
+                 tail -n +3 /proc/net/dev | cut -d: -f1; echo sandboxed >out",
+            ])
+            .assert_success()
+            .assert_stdout_utf8(&format!(
+                "^1\n{}\n *0\nno root\n *lo\n$",
+                ::regex::escape(&tmpdir.path().display().to_string())
+            ));
+        assert_eq!(
+            std::fs::read_to_string(tmpdir.path().join("out")).expect("written"),
+            "sandboxed\n"
+        );
+        testcall
+            .call_args(["-c", "touch /usr/sandboxed"])
+            .assert_failure()
+            .assert_stderr_utf8("Read-only file system");
+    }
+
+    #[test]
+    fn executable_visible() {
+        let executables = bintest::BinTest::new();
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut probe = TestCall::new(&executables, "testcall-probe");
+        probe.current_dir(&tmpdir).sandbox(true);
+
+        probe
+            .call_args(["out:inside", "cwd"])
+            .assert_success()
+            .assert_stdout_utf8("^inside\n");
+    }
+
+    #[test]
+    fn signals_forwarded() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&tmpdir).sandbox(true);
+
+        let mut child = testcall.spawn_args([
+            "-c",
+            "trap 'echo term; exit 7' TERM; echo ready; sleep 30 & wait",
+        ]);
+        child.read_until("ready", Duration::from_secs(5));
+        child.signal(signal::SIGTERM);
+        child
+            .wait()
+            .assert_exitcode(7)
+            .assert_stdout_utf8("^ready\nterm\n$");
+    }
+
+    #[test]
+    fn run_as() {
+        if !can_run_as() {
+            return;
+        }
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        std::fs::set_permissions(tmpdir.path(), std::fs::Permissions::from_mode(0o777))
+            .expect("permissions set");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall
+            .current_dir(&tmpdir)
+            .sandbox(true)
+            .run_as(65534, 65534);
+
+        testcall
+            .call_args(["-c", "echo $$; id -u"])
+            .assert_success()
+            .assert_stdout_utf8("^1\n65534\n$");
+    }
+
+    #[test]
+    #[should_panic(expected = "the sandbox needs a test directory")]
+    fn no_dir() {
+        let mut testcall = TestCall::external_command(Path::new("true"));
+        testcall.sandbox(true);
+
+        testcall.call();
+    }
+}
@@ -0,0 +1,260 @@
+//! Fake executables placed in front of 'PATH' to test how a program invokes external tools
+//! (unix only, the shims are shell scripts).
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use testpath::TestPath;
+
+use crate::failure::Failure;
+use crate::TestCall;
+
+/// The scripted behavior of a fake executable. By default it prints nothing and exits
+/// successfully.
+#[derive(Clone, Debug)]
+pub struct Shim {
+    name: String,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    exit_code: i32,
+    record_stdin: bool,
+}
+
+impl Shim {
+    /// Creates a shim which stands in for the executable 'name', like "git" or "ssh".
+    pub fn new(name: &str) -> Self {
+        Shim {
+            name: String::from(name),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            exit_code: 0,
+            record_stdin: false,
+        }
+    }
+
+    /// Sets what the shim writes to stdout.
+    pub fn stdout(mut self, output: &str) -> Self {
+        self.stdout = output.as_bytes().to_vec();
+        self
+    }
+
+    /// Sets what the shim writes to stderr.
+    pub fn stderr(mut self, output: &str) -> Self {
+        self.stderr = output.as_bytes().to_vec();
+        self
+    }
+
+    /// Sets the exit code of the shim.
+    pub fn exit_code(mut self, code: i32) -> Self {
+        self.exit_code = code;
+        self
+    }
+
+    /// Records everything the shim gets on stdin. Off by default because the shim then
+    /// blocks until stdin is closed.
+    pub fn record_stdin(mut self, record: bool) -> Self {
+        self.record_stdin = record;
+        self
+    }
+}
+
+/// A recorded invocation of a shim.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShimCall {
+    /// The arguments without the program name.
+    pub args: Vec<String>,
+    /// The data read from stdin, empty unless recording stdin was enabled.
+    pub stdin: Vec<u8>,
+}
+
+impl TestCall<'_> {
+    /// Installs 'shim' into the 'shims' directory of the test directory 'dir' and prepends
+    /// that to the 'PATH' of every following call. Each invocation of the shim is recorded
+    /// and can be inspected with 'shim_calls()'. All shims of a TestCall must be installed
+    /// into the same test directory.
+    #[track_caller]
+    pub fn install_shim<D: TestPath>(&mut self, dir: &D, shim: Shim) -> &mut Self {
+        let shim_dir = dir.path().join("shims");
+        if let Some(previous) = &self.shim_dir {
+            assert_eq!(
+                previous, &shim_dir,
+                "all shims installed in one test directory"
+            );
+        }
+        fs::create_dir_all(&shim_dir).expect("shim directory created");
+
+        let data = shim_dir.join(format!("{}.data", shim.name));
+        fs::create_dir_all(data.join("calls")).expect("shim data directory created");
+        fs::write(data.join("stdout"), &shim.stdout).expect("shim stdout written");
+        fs::write(data.join("stderr"), &shim.stderr).expect("shim stderr written");
+
+        let quote = |text: &str| text.replace('\'', r"'\''");
+        let data = quote(data.to_str().expect("utf8 test directory"));
+        // the original PATH, shims may stand in for the tools used by the script itself. The
+        // arguments are recorded nul terminated after their count, printf prints the format
+        // once even without arguments
+        let path = quote(&env::var("PATH").unwrap_or_default());
+        let script = format!(
+            "#!/bin/sh\n\
+             PATH='{path}'\n\
+             data='{data}'\n\
+             n=0\n\
+             while ! mkdir \"$data/calls/$n\" 2>/dev/null; do n=$((n+1)); done\n\
+             printf '%s\\0' \"$#\" \"$@\" > \"$data/calls/$n/args\"\n\
+             {stdin}\
+             cat \"$data/stdout\"\n\
+             cat \"$data/stderr\" >&2\n\
+             exit {code}\n",
+            path = path,
+            data = data,
+            stdin = if shim.record_stdin {
+                "cat > \"$data/calls/$n/stdin\"\n"
+            } else {
+                ""
+            },
+            code = shim.exit_code,
+        );
+        let path = shim_dir.join(&shim.name);
+        fs::write(&path, script).expect("shim written");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).expect("shim executable");
+
+        self.shim_dir = Some(shim_dir);
+        self
+    }
+
+    /// Returns the recorded invocations of the shim 'name', oldest first.
+    #[track_caller]
+    pub fn shim_calls(&self, name: &str) -> Vec<ShimCall> {
+        let calls = self
+            .shim_dir
+            .as_ref()
+            .expect("shims installed")
+            .join(format!("{}.data", name))
+            .join("calls");
+        let count = fs::read_dir(&calls)
+            .unwrap_or_else(|_| panic!("shim '{}' installed", name))
+            .count();
+        (0..count)
+            .map(|n| {
+                let call = calls.join(n.to_string());
+                let args = fs::read(call.join("args")).unwrap_or_default();
+                let mut args = args
+                    .split(|&b| b == 0)
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned());
+                let count = args
+                    .next()
+                    .and_then(|count| count.parse().ok())
+                    .unwrap_or_default();
+                ShimCall {
+                    args: args.take(count).collect(),
+                    stdin: fs::read(call.join("stdin")).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Expects that the shim 'name' was invoked exactly 'times'.
+    #[track_caller]
+    pub fn assert_shim_called(&self, name: &str, times: usize) -> &Self {
+        let calls = self.shim_calls(name);
+        if calls.len() != times {
+            Failure::new(
+                "assert_shim_called",
+                format!(
+                    "expected {} called {} times, was called {} times:\n{:#?}",
+                    name,
+                    times,
+                    calls.len(),
+                    calls
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+}
+
+/// Returns the 'PATH' of this process with 'dir' prepended.
+pub(crate) fn path_with(dir: &Path) -> OsString {
+    let mut paths = vec![PathBuf::from(dir)];
+    if let Some(path) = env::var_os("PATH") {
+        paths.extend(env::split_paths(&path));
+    }
+    env::join_paths(paths).expect("valid PATH")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn shims() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall
+            .install_shim(&tmpdir, Shim::new("git").stdout("main\n"))
+            .install_shim(&tmpdir, Shim::new("cat").stdout("meow\n"))
+            .install_shim(
+                &tmpdir,
+                Shim::new("ssh")
+                    .stderr("connection refused\n")
+                    .exit_code(255)
+                    .record_stdin(true),
+            );
+
+        testcall
+            .call_args([
+                "-c",
+                "git branch --show-current; echo payload | ssh 'host name' ls; echo $?; cat",
+            ])
+            .assert_success()
+            .assert_stdout_utf8("^main\n255\nmeow\n$")
+            .assert_stderr_utf8("^connection refused\n$");
+
+        testcall.assert_shim_called("git", 1);
+        assert_eq!(
+            testcall.shim_calls("ssh"),
+            [ShimCall {
+                args: vec![String::from("host name"), String::from("ls")],
+                stdin: b"payload\n".to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn shim_args() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.install_shim(&tmpdir, Shim::new("git"));
+
+        testcall
+            .call_args(["-c", "git; git ''; git a ''"])
+            .assert_success();
+        let args: Vec<Vec<String>> = testcall
+            .shim_calls("git")
+            .into_iter()
+            .map(|call| call.args)
+            .collect();
+        assert_eq!(
+            args,
+            [
+                vec![],
+                vec![String::new()],
+                vec![String::from("a"), String::new()]
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "expected git called 2 times, was called 0 times")]
+    fn shim_not_called() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("true"));
+        testcall.install_shim(&tmpdir, Shim::new("git"));
+
+        testcall.call().assert_success();
+        testcall.assert_shim_called("git", 2);
+    }
+}
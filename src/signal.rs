@@ -0,0 +1,234 @@
+//! Sending signals to spawned children (unix only).
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::failure::Failure;
+use crate::rusage;
+use crate::{CallOutput, TestChild};
+
+pub use libc::{
+    SIGABRT, SIGHUP, SIGINT, SIGKILL, SIGPIPE, SIGQUIT, SIGSEGV, SIGTERM, SIGUSR1, SIGUSR2,
+};
+
+impl TestChild {
+    /// Sends 'signal' to the child. Panics when the child exited already.
+    #[track_caller]
+    pub fn signal(&self, signal: i32) -> &Self {
+        if self.exited.is_some() || rusage::has_exited(&self.child) {
+            Failure::new(
+                "signal",
+                format!("child exited before it could be sent signal {}", signal),
+            )
+            .fail();
+        }
+        // SAFETY: the child is not reaped yet, thus its pid can not be reused
+        let ret = unsafe { libc::kill(self.child.id() as libc::pid_t, signal) };
+        assert!(
+            ret == 0,
+            "sending signal {} failed: {}",
+            signal,
+            std::io::Error::last_os_error()
+        );
+        self
+    }
+
+    /// Sends 'signal' (usually 'SIGTERM' or 'SIGINT') and expects that the child exits with
+    /// 'expected_code' within 'timeout'. Otherwise the child is killed with 'SIGKILL' and
+    /// the test fails with the output collected so far. Returns the output for further
+    /// assertions.
+    #[track_caller]
+    pub fn assert_graceful_shutdown(
+        mut self,
+        signal: i32,
+        expected_code: i32,
+        timeout: Duration,
+    ) -> CallOutput {
+        self.signal(signal);
+        let output = match self.wait_until(Instant::now() + timeout) {
+            Some(output) => output,
+            None => {
+                self.signal(SIGKILL);
+                let output = self.wait();
+                Failure::new(
+                    "assert_graceful_shutdown",
+                    format!(
+                        "no shutdown within {:?} after signal {}, killed\nstdout was:\n{}\nstderr was:\n{}",
+                        timeout,
+                        signal,
+                        String::from_utf8_lossy(&output.stdout),
+                        String::from_utf8_lossy(&output.stderr)
+                    ),
+                )
+                .fail();
+            }
+        };
+        if output.status.code() != Some(expected_code) {
+            Failure::new(
+                "assert_graceful_shutdown",
+                format!(
+                    "unclean shutdown after signal {}, expected exit code {}, {}\nstdout was:\n{}\nstderr was:\n{}",
+                    signal,
+                    expected_code,
+                    output.status,
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            )
+            .fail();
+        }
+        output
+    }
+
+    /// Sends each signal in 'steps' when the given time since spawning the child passed, like
+    /// '&[(secs(1), SIGHUP), (secs(2), SIGHUP), (secs(3), SIGTERM)]'. Panics when the child
+    /// exits before all signals were sent.
+    #[track_caller]
+    pub fn signal_script(&self, steps: &[(Duration, i32)]) -> &Self {
+        self.signal_script_with(steps, |_, _| {})
+    }
+
+    /// Like 'signal_script()' but calls 'check' with the index of the step and the child
+    /// after each signal was sent, to put assertions between the steps.
+    #[track_caller]
+    pub fn signal_script_with<F>(&self, steps: &[(Duration, i32)], mut check: F) -> &Self
+    where
+        F: FnMut(usize, &TestChild),
+    {
+        for (index, &(after, signal)) in steps.iter().enumerate() {
+            let at = self.started + after;
+            let now = Instant::now();
+            if at > now {
+                thread::sleep(at - now);
+            }
+            self.signal(signal);
+            check(index, self);
+        }
+        self
+    }
+}
+
+/// Returns the conventional name of 'signal', like "SIGSEGV", or the number for others.
+pub fn name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGCHLD => "SIGCHLD",
+        libc::SIGCONT => "SIGCONT",
+        libc::SIGSTOP => "SIGSTOP",
+        libc::SIGTSTP => "SIGTSTP",
+        libc::SIGTTIN => "SIGTTIN",
+        libc::SIGTTOU => "SIGTTOU",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGSYS => "SIGSYS",
+        _ => return format!("signal {}", signal),
+    };
+    String::from(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn signal_script() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args([
+            "-c",
+            "trap 'echo reloaded' HUP; echo ready; while :; do sleep 0.05; done",
+        ]);
+        child.assert_first_output_within(Duration::from_secs(5));
+        let mut steps = Vec::new();
+        child.signal_script_with(
+            &[
+                (Duration::from_millis(200), SIGHUP),
+                (Duration::from_millis(400), SIGHUP),
+                (Duration::from_millis(600), SIGTERM),
+            ],
+            |step, _| steps.push(step),
+        );
+        assert_eq!(steps, [0, 1, 2]);
+
+        let output = child.wait_timeout(Duration::from_secs(5));
+        output.assert_stdout_utf8("^ready\nreloaded\nreloaded\n$");
+        assert_eq!(
+            std::os::unix::process::ExitStatusExt::signal(&output.status),
+            Some(SIGTERM)
+        );
+    }
+
+    #[test]
+    fn graceful_shutdown() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args([
+            "-c",
+            "trap 'echo bye; exit 0' TERM; echo ready; while :; do sleep 0.05; done",
+        ]);
+        child.assert_first_output_within(Duration::from_secs(5));
+        child
+            .assert_graceful_shutdown(SIGTERM, 0, Duration::from_secs(5))
+            .assert_stdout_utf8("bye\n$");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "unclean shutdown after signal 2, expected exit code 130, exit status: 1"
+    )]
+    fn graceful_shutdown_code() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args([
+            "-c",
+            "trap 'exit 1' INT; echo ready; while :; do sleep 0.05; done",
+        ]);
+        child.assert_first_output_within(Duration::from_secs(5));
+        child.assert_graceful_shutdown(SIGINT, 130, Duration::from_secs(5));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "no shutdown within 200ms after signal 15, killed\nstdout was:\nstubborn"
+    )]
+    fn graceful_shutdown_ignored() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args([
+            "-c",
+            "trap '' TERM; echo stubborn; while :; do sleep 0.05; done",
+        ]);
+        child.assert_first_output_within(Duration::from_secs(5));
+        child.assert_graceful_shutdown(SIGTERM, 0, Duration::from_millis(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "child exited before it could be sent signal 1")]
+    fn signal_exited() {
+        let testcall = TestCall::external_command(Path::new("true"));
+
+        let child = testcall.spawn();
+        child.signal_script(&[(Duration::from_millis(200), SIGHUP)]);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(name(SIGSEGV), "SIGSEGV");
+        assert_eq!(name(1000), "signal 1000");
+    }
+}
@@ -0,0 +1,192 @@
+//! Golden file testing. Snapshots are stored as 'tests/snapshots/NAME.STREAM' below the
+//! package directory, 'NAME' may contain '/' to group snapshots in subdirectories. Setting
+//! 'TESTCALL_UPDATE_SNAPSHOTS' to anything but "0" (re)writes the snapshots from the actual
+//! output instead of comparing. On a mismatch the actual output is written next to the
+//! snapshot as 'NAME.STREAM.new' for review, accepting it is renaming it.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::failure::Failure;
+use crate::testcall::env_flag;
+
+/// The directory snapshots are stored in. Cargo sets 'CARGO_MANIFEST_DIR' when running
+/// tests, otherwise the current directory is the package directory.
+fn dir() -> PathBuf {
+    env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join("tests")
+        .join("snapshots")
+}
+
+/// Compares 'actual' with the snapshot 'name' of 'stream' or updates the snapshot.
+#[track_caller]
+pub(crate) fn assert_snapshot(kind: &'static str, name: &str, stream: &'static str, actual: &[u8]) {
+    check(
+        &dir(),
+        env_flag("TESTCALL_UPDATE_SNAPSHOTS"),
+        kind,
+        name,
+        stream,
+        actual,
+    );
+}
+
+/// Compares 'actual' with the snapshot 'name' of 'stream' stored in 'dir', or updates the
+/// snapshot when 'update' is set.
+#[track_caller]
+fn check(
+    dir: &Path,
+    update: bool,
+    kind: &'static str,
+    name: &str,
+    stream: &'static str,
+    actual: &[u8],
+) {
+    assert!(
+        !name.is_empty() && !name.split('/').any(|part| part.is_empty() || part == ".."),
+        "invalid snapshot name {:?}",
+        name
+    );
+    let path = dir.join(format!("{}.{}", name, stream));
+    let new = path.with_file_name(format!(
+        "{}.{}.new",
+        name.rsplit('/').next().unwrap_or(name),
+        stream
+    ));
+
+    if update {
+        fs::create_dir_all(path.parent().expect("snapshot directory"))
+            .expect("snapshot directory created");
+        fs::write(&path, actual).expect("snapshot written");
+        let _ = fs::remove_file(&new);
+        return;
+    }
+
+    let actual_text = String::from_utf8_lossy(actual);
+    let expected = match fs::read(&path) {
+        Ok(expected) => expected,
+        Err(_) => Failure::new(
+            kind,
+            format!(
+                "snapshot {} missing, create it by running with TESTCALL_UPDATE_SNAPSHOTS=1",
+                path.display()
+            ),
+        )
+        .with_actual(stream, &actual_text)
+        .fail(),
+    };
+    if expected != actual {
+        let _ = fs::write(&new, actual);
+        let expected = String::from_utf8_lossy(&expected);
+        Failure::new(
+            kind,
+            format!(
+                "{} differs from snapshot {} (-snapshot +actual), actual written to {}",
+                stream,
+                path.display(),
+                new.display()
+            ),
+        )
+        .with_expected(&expected)
+        .with_actual(stream, &actual_text)
+        .with_diff(crate::diff::unified(&expected, &actual_text))
+        .fail();
+    }
+    let _ = fs::remove_file(&new);
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use super::check;
+    use crate::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    // the self-tests use their own snapshot directory, the package has no snapshots
+
+    #[test]
+    fn snapshot() {
+        let snapshots = TempDir::new().expect("TempDir created");
+        let testcall = TestCall::external_command(Path::new("echo"));
+        let output = testcall.call_args(["hello", "snapshot"]);
+
+        check(
+            snapshots.path(),
+            true,
+            "test",
+            "group/echo",
+            "stdout",
+            &output.stdout,
+        );
+        assert_eq!(
+            fs::read(snapshots.path().join("group/echo.stdout")).expect("snapshot written"),
+            b"hello snapshot\n"
+        );
+        check(
+            snapshots.path(),
+            false,
+            "test",
+            "group/echo",
+            "stdout",
+            &output.stdout,
+        );
+    }
+
+    #[test]
+    fn snapshot_differs() {
+        let snapshots = TempDir::new().expect("TempDir created");
+        fs::write(snapshots.path().join("echo.stdout"), "hello snapshot\n")
+            .expect("snapshot written");
+        let testcall = TestCall::external_command(Path::new("echo"));
+        let output = testcall.call_args(["goodbye", "snapshot"]);
+
+        let failed = std::panic::catch_unwind(|| {
+            check(
+                snapshots.path(),
+                false,
+                "test",
+                "echo",
+                "stdout",
+                &output.stdout,
+            )
+        });
+        let cause = failed.expect_err("snapshot differs");
+        let message = crate::frames::panic_message(&cause);
+        assert!(message.contains("stdout differs from snapshot"));
+        assert!(message.contains("-hello snapshot\n+goodbye snapshot\n"));
+        assert_eq!(
+            fs::read(snapshots.path().join("echo.stdout.new")).expect("actual written"),
+            b"goodbye snapshot\n"
+        );
+
+        check(
+            snapshots.path(),
+            true,
+            "test",
+            "echo",
+            "stdout",
+            &output.stdout,
+        );
+        assert!(!snapshots.path().join("echo.stdout.new").exists());
+    }
+
+    #[test]
+    #[should_panic(expected = "missing, create it by running with TESTCALL_UPDATE_SNAPSHOTS=1")]
+    fn snapshot_missing() {
+        let snapshots = TempDir::new().expect("TempDir created");
+
+        check(snapshots.path(), false, "test", "missing", "stdout", b"");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid snapshot name \"../escape\"")]
+    fn snapshot_name() {
+        let testcall = TestCall::external_command(Path::new("true"));
+
+        testcall.call().assert_stdout_snapshot("../escape");
+    }
+}
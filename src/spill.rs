@@ -0,0 +1,203 @@
+//! Output spilled to files for calls which produce more than should be kept in memory.
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use crate::failure::Failure;
+use crate::rusage::{CpuTime, Usage};
+
+/// The result of 'TestCall::capture_to_dir()', stdout and stderr are stored in the files
+/// 'stdout' and 'stderr' of the given directory. All assertions read the files streaming
+/// with bounded memory.
+#[derive(Debug)]
+pub struct TestOutputFiles {
+    status: ExitStatus,
+    duration: Duration,
+    usage: Option<Usage>,
+    stdout: PathBuf,
+    stderr: PathBuf,
+}
+
+impl TestOutputFiles {
+    pub(crate) fn new(
+        status: ExitStatus,
+        duration: Duration,
+        usage: Option<Usage>,
+        dir: &Path,
+    ) -> Self {
+        TestOutputFiles {
+            status,
+            duration,
+            usage,
+            stdout: dir.join("stdout"),
+            stderr: dir.join("stderr"),
+        }
+    }
+
+    /// The exit status of the call.
+    pub fn status(&self) -> ExitStatus {
+        self.status
+    }
+
+    /// Wall clock time from starting the executable until it was waited for.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The cpu time consumed by the call, None where this can not be measured.
+    pub fn cpu_time(&self) -> Option<CpuTime> {
+        self.usage.map(|usage| usage.cpu_time)
+    }
+
+    /// The peak resident set size of the call in bytes, None where this can not be measured,
+    /// see 'CallOutput::max_rss()'.
+    pub fn max_rss(&self) -> Option<u64> {
+        self.usage.and_then(|usage| usage.peak_rss())
+    }
+
+    /// The file holding stdout.
+    pub fn stdout_path(&self) -> &Path {
+        &self.stdout
+    }
+
+    /// The file holding stderr.
+    pub fn stderr_path(&self) -> &Path {
+        &self.stderr
+    }
+
+    /// Will panic when the program did not exited successful.
+    #[track_caller]
+    pub fn assert_success(&self) -> &Self {
+        if !self.status.success() {
+            Failure::new(
+                "assert_success",
+                format!("expected success at exit, got {}", self.status),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that the program exited with a failure.
+    #[track_caller]
+    pub fn assert_failure(&self) -> &Self {
+        if self.status.success() {
+            Failure::new("assert_failure", String::from("expected failure at exit")).fail();
+        }
+        self
+    }
+
+    /// Expects that the program exited with the provided code.
+    #[track_caller]
+    pub fn assert_exitcode(&self, code: i32) -> &Self {
+        if self.status.code() != Some(code) {
+            Failure::new("assert_exitcode", String::from("unexpected exitcode"))
+                .with_expected(&code.to_string())
+                .with_actual("exitcode", &format!("{:?}", self.status.code()))
+                .fail();
+        }
+        self
+    }
+
+    /// Expects that the program wrote at most 'bytes' bytes to stdout.
+    #[track_caller]
+    pub fn assert_stdout_at_most(&self, bytes: u64) -> &Self {
+        assert_at_most("assert_stdout_at_most", "stdout", &self.stdout, bytes);
+        self
+    }
+
+    /// Expects that the program wrote at most 'bytes' bytes to stderr.
+    #[track_caller]
+    pub fn assert_stderr_at_most(&self, bytes: u64) -> &Self {
+        assert_at_most("assert_stderr_at_most", "stderr", &self.stderr, bytes);
+        self
+    }
+
+    /// Expects that at least one line of stdout matches 'regex'. Lines are lossy converted
+    /// to utf8 and passed without their newline.
+    #[track_caller]
+    pub fn assert_stdout_any_line(&self, regex: &str) -> &Self {
+        assert_any_line("assert_stdout_any_line", "stdout", &self.stdout, regex);
+        self
+    }
+
+    /// Expects that at least one line of stderr matches 'regex'.
+    #[track_caller]
+    pub fn assert_stderr_any_line(&self, regex: &str) -> &Self {
+        assert_any_line("assert_stderr_any_line", "stderr", &self.stderr, regex);
+        self
+    }
+}
+
+#[track_caller]
+fn assert_at_most(kind: &'static str, stream: &'static str, path: &Path, bytes: u64) {
+    let len = path.metadata().expect("output file").len();
+    if len > bytes {
+        Failure::new(
+            kind,
+            format!(
+                "expected at most {} bytes on {}, got {} in {:?}",
+                bytes, stream, len, path
+            ),
+        )
+        .fail();
+    }
+}
+
+#[track_caller]
+fn assert_any_line(kind: &'static str, stream: &'static str, path: &Path, regex: &str) {
+    let mut reader = BufReader::new(File::open(path).expect("output file"));
+    let mut line = Vec::new();
+    while reader
+        .read_until(b'\n', &mut line)
+        .expect("readable output")
+        > 0
+    {
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if crate::regex::regex_match_utf8(&line, regex).0 {
+            return;
+        }
+        line.clear();
+    }
+    Failure::new(kind, format!("no line of {} in {:?} matches", stream, path))
+        .with_expected(regex)
+        .fail();
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    #[test]
+    fn capture_to_dir() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let output =
+            testcall.capture_to_dir(&tmpdir, ["-c", "seq 1 100000; echo done >&2; exit 3"]);
+        output
+            .assert_exitcode(3)
+            .assert_stdout_any_line("^99999$")
+            .assert_stderr_any_line("^done$")
+            .assert_stderr_at_most(5);
+        assert_eq!(tmpdir.read_utf8("stdout").lines().count(), 100000);
+    }
+
+    #[test]
+    #[should_panic(expected = "no line of stdout")]
+    fn capture_to_dir_no_line() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .capture_to_dir(&tmpdir, ["hello"])
+            .assert_stdout_any_line("^bye$");
+    }
+}
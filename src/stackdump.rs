@@ -0,0 +1,49 @@
+#[cfg(target_os = "linux")]
+use std::process::{Command, Stdio};
+
+/// Tools tried in order to obtain the backtraces of all threads of a running process.
+#[cfg(target_os = "linux")]
+const DUMPERS: &[(&str, &[&str])] = &[
+    ("eu-stack", &["-p"]),
+    (
+        "gdb",
+        &["-batch", "-nx", "-ex", "thread apply all bt", "-p"],
+    ),
+];
+
+/// Attaches a debugger to the process 'pid' and returns the backtraces of all its threads.
+/// Returns an explanation instead when no tool is installed or attaching failed (missing
+/// ptrace permissions are common in containers).
+#[cfg(target_os = "linux")]
+pub(crate) fn stack_dump(pid: u32) -> String {
+    let mut tried = Vec::new();
+    for (tool, args) in DUMPERS {
+        let output = Command::new(tool)
+            .args(*args)
+            .arg(pid.to_string())
+            .stdin(Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() && !output.stdout.is_empty() => {
+                return format!(
+                    "stack dump by {}:\n{}",
+                    tool,
+                    String::from_utf8_lossy(&output.stdout)
+                );
+            }
+            Ok(output) => tried.push(format!(
+                "{} failed: {}",
+                tool,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(err) => tried.push(format!("{} not available: {}", tool, err)),
+        }
+    }
+    format!("no stack dump available:\n  {}", tried.join("\n  "))
+}
+
+/// Stack dumps are only supported on linux.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn stack_dump(_pid: u32) -> String {
+    String::from("no stack dump available: unsupported platform")
+}
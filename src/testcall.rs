@@ -1,9 +1,37 @@
 use bintest::BinTest;
-use std::ffi::OsStr;
-use std::path::Path;
-use std::process::{Child, Command, Output, Stdio};
-use testpath::TestPath;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, ExitStatus, Output, Stdio};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use testpath::{Fixtures, TestPath};
+
+use crate::capture::{Capture, OnExceeded, OutputPolicy};
+use crate::coredump;
+use crate::failure::Failure;
+use crate::frames::panic_message;
+use crate::history;
+use crate::normalize;
+use crate::resolve;
+use crate::rusage::{self, Usage};
+use crate::stackdump;
+use crate::{CallOutput, Captured, DirBackend, ReplayMode, TestOutputFiles};
+
+/// How often a child is checked for completion while waiting with a timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the output of a child killed on timeout is still collected.
+const KILL_GRACE: Duration = Duration::from_millis(100);
 
+#[derive(Clone, Copy)]
 enum ExeLocation<'a> {
     BinTest {
         executables: &'a BinTest,
@@ -12,28 +40,178 @@ enum ExeLocation<'a> {
     External(&'a Path),
 }
 
+/// How a configuration file is passed to the executable.
+#[derive(Clone, Copy, Debug)]
+pub enum ConfigVia<'a> {
+    /// As a command line flag followed by the path, like '--config <path>'. When the flag ends
+    /// in '=' the path is appended to it in a single argument, like '--config=<path>'.
+    Flag(&'a str),
+    /// In the given environment variable.
+    Env(&'a str),
+}
+
+/// How calls get their environment.
+#[derive(Clone)]
+enum EnvPolicy {
+    Inherit,
+    Clear,
+    Allowlist(Vec<OsString>),
+}
+
+/// A hook run on the prepared command before each call, see 'TestCall::on_before_call()'.
+type BeforeCall = Arc<dyn Fn(&mut Command) + Send + Sync>;
+
+/// A hook run on the output after each call, see 'TestCall::on_after_call()'.
+type AfterCall = Arc<dyn Fn(&CallOutput) + Send + Sync>;
+
+/// A directory backend attached by 'TestCall::dir_backend()'. Shared by the clones of the
+/// TestCall and its spawned children, the last one dropping it calls 'cleanup()', thus this
+/// also happens when a test panics.
+struct AttachedBackend(Arc<dyn DirBackend>);
+
+impl std::ops::Deref for AttachedBackend {
+    type Target = dyn DirBackend;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl Drop for AttachedBackend {
+    fn drop(&mut self) {
+        self.0.cleanup();
+    }
+}
+
 /// A TestCall object binds a BinTest::Command to a single executable and environment and
-/// provides functions to call this multiple times.
+/// provides functions to call this multiple times. A fully configured TestCall can be cloned
+/// as template and tweaked for individual tests.
+#[derive(Clone)]
 pub struct TestCall<'a> {
     executable: ExeLocation<'a>,
-    dir: Option<&'a dyn TestPath>,
+    pub(crate) dir: Option<&'a dyn TestPath>,
+    tee: Option<bool>,
+    normalize_newlines: bool,
+    #[cfg(all(unix, feature = "pty"))]
+    pty: Option<(u16, u16)>,
+    max_output: Option<usize>,
+    output_policy: OutputPolicy,
+    pub(crate) timeout: Option<Duration>,
+    stack_dump: Option<bool>,
+    crash_diagnostics: Option<bool>,
+    #[cfg(all(target_os = "linux", feature = "netns"))]
+    pub(crate) isolate_network: bool,
+    #[cfg(all(target_os = "linux", feature = "sandbox"))]
+    pub(crate) sandbox: bool,
+    extra_args: Vec<OsString>,
+    wrapper: Vec<OsString>,
+    // None removes the variable
+    extra_envs: Vec<(OsString, Option<OsString>)>,
+    env_policy: Option<EnvPolicy>,
+    coverage_env: bool,
+    pub(crate) fake_time: Option<u64>,
+    pub(crate) fake_time_env: Option<OsString>,
+    replay: Option<(PathBuf, ReplayMode)>,
+    dry_run: bool,
+    #[cfg(unix)]
+    pub(crate) shim_dir: Option<std::path::PathBuf>,
+    #[cfg(unix)]
+    limits: rusage::Limits,
+    #[cfg(unix)]
+    process_group: bool,
+    #[cfg(unix)]
+    pub(crate) run_as: Option<(u32, u32)>,
+    #[cfg(unix)]
+    pub(crate) sudo: bool,
+    backend: Option<Arc<AttachedBackend>>,
+    before_call: Vec<BeforeCall>,
+    after_call: Vec<AfterCall>,
 }
 
 impl<'a> TestCall<'a> {
     /// Creates a new testcall object for 'name' from the current crates executables.
     pub fn new(executables: &'a BinTest, name: &'a str) -> TestCall<'a> {
-        TestCall {
-            executable: ExeLocation::BinTest { executables, name },
-            dir: None,
-        }
+        TestCall::with_executable(ExeLocation::BinTest { executables, name })
     }
 
     /// Creates a new testcall object for an external command given by path.
+    /// On windows, the extensions from 'PATHEXT' are applied, bare names are searched in
+    /// 'PATH' and the 'App Paths' registry keys. When nothing is found the call panics with
+    /// a list of the searched locations.
     pub fn external_command(path: &'a Path) -> TestCall<'a> {
+        TestCall::with_executable(ExeLocation::External(path))
+    }
+
+    fn with_executable(executable: ExeLocation<'a>) -> TestCall<'a> {
         TestCall {
-            executable: ExeLocation::External(path),
+            executable,
             dir: None,
+            tee: None,
+            normalize_newlines: false,
+            #[cfg(all(unix, feature = "pty"))]
+            pty: None,
+            max_output: None,
+            output_policy: OutputPolicy::Panic,
+            timeout: None,
+            stack_dump: None,
+            crash_diagnostics: None,
+            #[cfg(all(target_os = "linux", feature = "netns"))]
+            isolate_network: false,
+            #[cfg(all(target_os = "linux", feature = "sandbox"))]
+            sandbox: false,
+            extra_args: Vec::new(),
+            wrapper: Vec::new(),
+            extra_envs: Vec::new(),
+            env_policy: None,
+            coverage_env: true,
+            fake_time: None,
+            fake_time_env: None,
+            replay: None,
+            dry_run: false,
+            #[cfg(unix)]
+            shim_dir: None,
+            #[cfg(unix)]
+            limits: rusage::Limits::default(),
+            #[cfg(unix)]
+            process_group: false,
+            #[cfg(unix)]
+            run_as: None,
+            #[cfg(unix)]
+            sudo: false,
+            backend: None,
+            before_call: Vec::new(),
+            after_call: Vec::new(),
+        }
+    }
+
+    /// Calls 'check' with the name and a fresh TestCall for every executable 'executables'
+    /// built, to apply shared smoke tests (like '--help' exits successfully) to all of them.
+    /// New binaries are covered automatically. All executables are checked even when some
+    /// fail, then the test panics listing every failing one. Returns the number of
+    /// executables checked.
+    #[track_caller]
+    pub fn for_each_bin<F>(executables: &'a BinTest, mut check: F) -> usize
+    where
+        F: FnMut(&str, TestCall<'a>),
+    {
+        let mut failures = String::new();
+        let mut count = 0;
+        for (name, _) in executables.list_executables() {
+            count += 1;
+            let testcall = TestCall::new(executables, name);
+            if let Err(cause) = panic::catch_unwind(AssertUnwindSafe(|| check(name, testcall))) {
+                let message = panic_message(&cause);
+                failures.push_str(&format!(
+                    "\n  {}: {}",
+                    name,
+                    message.lines().next().unwrap_or_default()
+                ));
+            }
+        }
+        if !failures.is_empty() {
+            Failure::new("for_each_bin", format!("executables failed:{}", failures)).fail();
         }
+        count
     }
 
     /// Sets the current dir in which the next call shall execute
@@ -42,93 +220,515 @@ impl<'a> TestCall<'a> {
         self
     }
 
-    /// Calls the executable with the given arguments and environment.
-    /// `args` can be `NO_ARGS` or something iterateable that yields the arguments.
-    /// `envs` can be `NO_ENVS` or something iterateable that yields the key/value pairs.
-    /// When any envs are given then the environment is cleared first.
-    /// Returns a Output object for further investigation.
+    /// Attaches a test directory backend and calls its 'setup()' hook. Following calls run
+    /// within the backend's directory unless 'current_dir()' is set and invoke its
+    /// 'before_call()' and 'after_call()' hooks. An already attached backend is detached
+    /// first. Its 'cleanup()' hook is called once the backend is detached or the TestCall
+    /// dropped, also when the test fails, and no clone or spawned child uses it anymore.
+    pub fn dir_backend(&mut self, backend: Arc<dyn DirBackend>) -> &mut Self {
+        self.detach_dir_backend();
+        backend.setup();
+        self.backend = Some(Arc::new(AttachedBackend(backend)));
+        self
+    }
+
+    /// Detaches the backend attached with 'dir_backend()', which is cleaned up unless clones
+    /// of this TestCall or spawned children still use it.
+    pub fn detach_dir_backend(&mut self) -> &mut Self {
+        self.backend = None;
+        self
+    }
+
+    /// Adds 'hook' to be run on the prepared command before each call and spawn, after the
+    /// 'before_call()' of a directory backend. For cross-cutting setup, like resetting a
+    /// database, once per TestCall instead of at every call site. Hooks are shared with
+    /// clones and run in the order they were added. Like 'on_after_call()' hooks they do not
+    /// run for calls replayed by 'replay()', skipped by 'dry_run()' or written by
+    /// 'capture_to_dir()' and not for 'command_line()'.
+    pub fn on_before_call<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&mut Command) + Send + Sync + 'static,
+    {
+        self.before_call.push(Arc::new(hook));
+        self
+    }
+
+    /// Adds 'hook' to be run on the output after each finished call and spawn, after the
+    /// 'after_call()' of a directory backend. For cross-cutting checks and collection, like
+    /// gathering timings or dumping logs. Hooks are shared with clones and run in the order
+    /// they were added. Calls replayed by 'replay()', skipped by 'dry_run()' or written by
+    /// 'capture_to_dir()' do not run them.
+    pub fn on_after_call<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&CallOutput) + Send + Sync + 'static,
+    {
+        self.after_call.push(Arc::new(hook));
+        self
+    }
+
+    /// Writes a configuration file 'name' with 'content' into the test directory 'dir' and
+    /// registers it on this TestCall, so that every following call passes it as configured
+    /// by 'via'. Keeps the fixture and its wiring in one place. Flags are placed before the
+    /// arguments of each call, the environment variable is set even when a call clears the
+    /// environment.
     #[track_caller]
-    pub fn call_args_envs<IA, S, IE, K, V>(&self, args: IA, envs: IE) -> Output
+    pub fn config_file<D, P>(
+        &mut self,
+        dir: &D,
+        name: P,
+        content: &str,
+        via: ConfigVia,
+    ) -> &mut Self
+    where
+        D: Fixtures,
+        P: AsRef<Path>,
+    {
+        dir.create_file(name.as_ref(), content.as_bytes());
+        let path = dir.path().join(name.as_ref());
+        match via {
+            ConfigVia::Flag(flag) if flag.ends_with('=') => {
+                let mut arg = OsString::from(flag);
+                arg.push(&path);
+                self.extra_args.push(arg);
+            }
+            ConfigVia::Flag(flag) => {
+                self.extra_args.push(OsString::from(flag));
+                self.extra_args.push(path.into_os_string());
+            }
+            ConfigVia::Env(name) => {
+                self.env(name, path);
+            }
+        }
+        self
+    }
+
+    /// Prepends 'args' to the arguments of all following calls. Allows one TestCall per
+    /// subcommand, like '["--config", "test.toml", "sub"]'. Appends to the prefix when
+    /// called again.
+    pub fn base_args<IA, S>(&mut self, args: IA) -> &mut Self
     where
         IA: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
+    {
+        self.extra_args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_os_string()));
+        self
+    }
+
+    /// Sets the environment variable 'key' for all following calls, also for those which clear
+    /// the environment. Variables given to a single call take precedence.
+    pub fn env<K, V>(&mut self, key: K, value: V) -> &mut Self
+    where
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.extra_envs.push((
+            key.as_ref().to_os_string(),
+            Some(value.as_ref().to_os_string()),
+        ));
+        self
+    }
+
+    /// Sets multiple environment variables for all following calls.
+    pub fn envs<IE, K, V>(&mut self, envs: IE) -> &mut Self
+    where
         IE: IntoIterator<Item = (K, V)>,
         K: AsRef<OsStr>,
         V: AsRef<OsStr>,
     {
-        let mut command = match self.executable {
-            ExeLocation::BinTest { executables, name } => executables.command(name),
-            ExeLocation::External(path) => Command::new(path),
-        };
-        if let Some(dir) = &self.dir {
-            command.current_dir(dir.path());
+        for (key, value) in envs {
+            self.env(key, value);
         }
+        self
+    }
 
-        let mut envs = envs.into_iter().fuse().peekable();
-        if envs.peek().is_some() {
-            command.env_clear();
-            command.envs(envs);
+    /// Removes the environment variable 'key' for all following calls, whether inherited or
+    /// set before.
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.extra_envs.push((key.as_ref().to_os_string(), None));
+        self
+    }
+
+    /// Starts all following calls with an empty environment instead of inheriting the one of
+    /// the test, only the variables set on the TestCall and the call are passed. 'false' is
+    /// 'inherit_env()'.
+    pub fn env_clear(&mut self, clear: bool) -> &mut Self {
+        self.env_policy = Some(if clear {
+            EnvPolicy::Clear
+        } else {
+            EnvPolicy::Inherit
+        });
+        self
+    }
+
+    /// All following calls inherit the environment of the test, even when a call gives its
+    /// own variables (which are then added on top).
+    pub fn inherit_env(&mut self) -> &mut Self {
+        self.env_policy = Some(EnvPolicy::Inherit);
+        self
+    }
+
+    /// All following calls start with an environment holding only the variables named in
+    /// 'keys' which are passed through from the test's environment, like '["PATH", "HOME"]'.
+    pub fn env_allowlist<IK, K>(&mut self, keys: IK) -> &mut Self
+    where
+        IK: IntoIterator<Item = K>,
+        K: AsRef<OsStr>,
+    {
+        self.env_policy = Some(EnvPolicy::Allowlist(
+            keys.into_iter()
+                .map(|key| key.as_ref().to_os_string())
+                .collect(),
+        ));
+        self
+    }
+
+    /// Echoes the stdout/stderr of the called executable live to the test's stdout/stderr
+    /// while still capturing it. Useful with 'cargo test -- --nocapture' to watch the
+    /// progress of long running calls. When not set explicitly, teeing is enabled by setting
+    /// the 'TESTCALL_TEE' environment variable to anything but "0".
+    pub fn tee_output(&mut self, tee: bool) -> &mut Self {
+        self.tee = Some(tee);
+        self
+    }
+
+    fn tee_enabled(&self) -> bool {
+        self.tee.unwrap_or_else(|| env_flag("TESTCALL_TEE"))
+    }
+
+    /// Runs calls and spawned children on a pseudo terminal of 80x24 cells, so programs
+    /// which check 'isatty()' show their colors, prompts and progress bars. Stdin, stdout
+    /// and stderr are all connected to the terminal, everything the child writes is
+    /// captured as stdout in the raw terminal byte stream (with "\r\n" line endings),
+    /// stderr stays empty. Input given to a call or by 'TestChild::write_stdin()' is typed
+    /// into the terminal. Unix only, needs the 'pty' feature.
+    #[cfg(all(unix, feature = "pty"))]
+    pub fn pty(&mut self, pty: bool) -> &mut Self {
+        self.pty = if pty { Some((80, 24)) } else { None };
+        self
+    }
+
+    /// Like 'pty(true)' with a terminal of 'cols' x 'rows' cells.
+    #[cfg(all(unix, feature = "pty"))]
+    pub fn pty_size(&mut self, cols: u16, rows: u16) -> &mut Self {
+        self.pty = Some((cols, rows));
+        self
+    }
+
+    /// Converts "\r\n" line endings in the captured stdout and stderr to "\n" when a call
+    /// finishes, so regex and equality assertions written for unix hold for windows programs
+    /// as well. Incremental reads of a running 'TestChild' are not converted.
+    pub fn normalize_newlines(&mut self, normalize: bool) -> &mut Self {
+        self.normalize_newlines = normalize;
+        self
+    }
+
+    /// Limits how long calls may run. A call which did not finish within 'timeout' is
+    /// killed and the test panics with a "timed out after" message and the output collected
+    /// so far instead of hanging the test suite. Spawned children are not affected, use
+    /// 'TestChild::wait_timeout()' for them.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Limits how many bytes of stdout and of stderr each call may capture. A call writing
+    /// more fails with an "output exceeded" panic instead of filling the memory of the test
+    /// runner, unless another 'output_policy()' is set. When not set explicitly, the limit
+    /// is taken from the 'TESTCALL_MAX_OUTPUT' environment variable (in bytes), by default
+    /// the output is unlimited.
+    pub fn max_output(&mut self, bytes: usize) -> &mut Self {
+        self.max_output = Some(bytes);
+        self
+    }
+
+    /// Selects what happens when a call exceeds the output limit, the default is
+    /// 'OutputPolicy::Panic'.
+    pub fn output_policy(&mut self, policy: OutputPolicy) -> &mut Self {
+        self.output_policy = policy;
+        self
+    }
+
+    fn max_output_limit(&self) -> Option<usize> {
+        self.max_output.or_else(|| {
+            env::var("TESTCALL_MAX_OUTPUT")
+                .ok()
+                .map(|limit| limit.parse().expect("TESTCALL_MAX_OUTPUT in bytes"))
+        })
+    }
+
+    /// Limits the address space of calls to 'bytes', allocations beyond fail. The limits are
+    /// set as rlimits and are unix only, windows Job Objects are not supported.
+    #[cfg(unix)]
+    pub fn limit_memory(&mut self, bytes: u64) -> &mut Self {
+        self.limits.memory = Some(bytes);
+        self
+    }
+
+    /// Limits the CPU time of calls to 'secs' seconds, a child exceeding it is terminated
+    /// by SIGXCPU, or by SIGKILL one second later when it handles that. Unix only (rlimit).
+    #[cfg(unix)]
+    pub fn limit_cpu_time(&mut self, secs: u64) -> &mut Self {
+        self.limits.cpu_time = Some(secs);
+        self
+    }
+
+    /// Limits the number of file descriptors calls can open to 'n'. Unix only (rlimit).
+    #[cfg(unix)]
+    pub fn limit_open_files(&mut self, n: u64) -> &mut Self {
+        self.limits.open_files = Some(n);
+        self
+    }
+
+    /// Runs calls in a process group of their own, thus 'TestChild::kill_tree()', timeouts
+    /// and dropping a 'TestChild' kill the helpers it forked as well. Children in their own
+    /// group do not receive the Ctrl-C of the terminal and are stopped when reading from
+    /// it, therefore this is off by default. Unix only, on other platforms only the child
+    /// itself is killed.
+    #[cfg(unix)]
+    pub fn process_group(&mut self, enable: bool) -> &mut Self {
+        self.process_group = enable;
+        self
+    }
+
+    /// When a timeout expires, attach a debugger ('eu-stack' or 'gdb', linux only) to the
+    /// hanging child before killing it and include the backtraces of all threads in the
+    /// failure message. When not set explicitly, this is enabled by setting the
+    /// 'TESTCALL_STACK_DUMP' environment variable to anything but "0".
+    pub fn stack_dump_on_timeout(&mut self, enable: bool) -> &mut Self {
+        self.stack_dump = Some(enable);
+        self
+    }
+
+    fn stack_dump_enabled(&self) -> bool {
+        self.stack_dump
+            .unwrap_or_else(|| env_flag("TESTCALL_STACK_DUMP"))
+    }
+
+    /// Calls which crash (SIGSEGV, SIGABRT and the like on unix, NTSTATUS errors on windows)
+    /// leave their diagnostics in 'crash-<pid>' of the directory set with 'current_dir()'
+    /// (the temporary directory otherwise): the stderr with the backtrace ('RUST_BACKTRACE'
+    /// is set for the child unless given), the core dump (enabled up to the hard limit)
+    /// when the system writes it to the working directory and a report. Failing
+    /// 'assert_success()' and 'assert_not_crashed()' point to it. When not set explicitly,
+    /// this is enabled by setting the 'TESTCALL_CRASH_DIAGNOSTICS' environment variable to
+    /// anything but "0".
+    pub fn crash_diagnostics(&mut self, enable: bool) -> &mut Self {
+        self.crash_diagnostics = Some(enable);
+        self
+    }
+
+    /// Where crash diagnostics are collected, None when disabled.
+    fn crash_dir(&self) -> Option<PathBuf> {
+        if !self
+            .crash_diagnostics
+            .unwrap_or_else(|| env_flag("TESTCALL_CRASH_DIAGNOSTICS"))
+        {
+            return None;
         }
+        Some(match (&self.dir, &self.backend) {
+            (Some(dir), _) => dir.path().to_path_buf(),
+            (None, Some(backend)) => backend.path().to_path_buf(),
+            (None, None) => env::temp_dir(),
+        })
+    }
 
-        let output = command.args(args).output().expect("called command");
-        output
+    /// Runs the executable in a new network namespace in which only an unconfigured loopback
+    /// device exists, thus any network access fails. Uses a user namespace when not running
+    /// as root. Linux only, needs the 'netns' feature.
+    #[cfg(all(target_os = "linux", feature = "netns"))]
+    pub fn isolate_network(&mut self, isolate: bool) -> &mut Self {
+        self.isolate_network = isolate;
+        self
     }
 
-    /// Calls the executable with the given arguments.
-    /// `args` can be `NO_ARGS` or something iterateable that yields the arguments.
-    /// Returns a Output object for further investigation.
-    #[inline]
-    #[track_caller]
-    pub fn call_args<IA, S>(&self, args: IA) -> Output
+    /// Coverage variables like 'LLVM_PROFILE_FILE' of the test process are passed to calls
+    /// even when the environment is cleared, thus 'cargo llvm-cov' covers the tested
+    /// executables. A 'LLVM_PROFILE_FILE' without '%p' or '%m' gets a '%p' appended to keep
+    /// the profiles of different calls apart. Enabled by default.
+    pub fn coverage_env(&mut self, propagate: bool) -> &mut Self {
+        self.coverage_env = propagate;
+        self
+    }
+
+    /// Records the outputs of calls into 'dir' and replays them from there instead of
+    /// executing again, depending on 'mode'. Recordings are looked up by the program, its
+    /// arguments and the data fed to stdin, the environment and working directory are not
+    /// considered. Lets tests iterate quickly on assertions about expensive external
+    /// commands. Spawned children are always executed.
+    pub fn replay<P: Into<PathBuf>>(&mut self, dir: P, mode: ReplayMode) -> &mut Self {
+        self.replay = Some((dir.into(), mode));
+        self
+    }
+
+    /// Calls are not executed but succeed immediately with the shell quoted command line
+    /// they would execute on stdout (see 'CommandLine'), for asserting on argument
+    /// construction with the usual output assertions. Spawned children are always executed.
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Runs every call through 'wrapper' with its leading 'args', like 'valgrind
+    /// --error-exitcode=99' or a sanitizer runner. The executable (still resolved through
+    /// 'BinTest' or 'PATH') and its arguments are appended. How the wrapper reports errors
+    /// is checked with 'TestOutput::assert_no_wrapper_error()'.
+    pub fn wrap_with<P, IA, S>(&mut self, wrapper: P, args: IA) -> &mut Self
     where
+        P: AsRef<OsStr>,
         IA: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        self.call_args_envs(args, NO_ENVS)
+        self.wrapper = std::iter::once(wrapper.as_ref().to_os_string())
+            .chain(args.into_iter().map(|arg| arg.as_ref().to_os_string()))
+            .collect();
+        self
     }
 
-    /// Convinience method to call the executable with the given arguments.
-    /// `args` is a single '&str' split at ascii_whitespace. It is important to note that this
-    /// only works when the arguments themself do not contain whitespace characters (like
-    /// quoted strings "Hello World"). Returns a Output object for further investigation.
-    #[inline]
-    #[track_caller]
-    pub fn call_argstr(&self, args: &str) -> Output {
-        self.call_args_envs(args.split_ascii_whitespace(), NO_ENVS)
+    /// Creates the 'Command' for the executable with the given arguments and environment.
+    pub(crate) fn command<IA, S, IE, K, V>(&self, args: IA, envs: IE) -> PreparedCommand
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+        IE: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.command_wrapped(&[], args, envs)
     }
 
-    /// Calls the executable without arguments.
-    /// `envs` can be `NO_ENVS` or something iterateable that yields the key/value pairs.
-    /// When any envs are given then the environment is cleared first.
-    /// Returns a Output object for further investigation.
-    #[inline]
-    #[track_caller]
-    pub fn call_envs<IE, K, V>(&self, envs: IE) -> Output
+    /// Like 'command()' but the executable is run by 'wrapper', a program with its leading
+    /// arguments (like 'strace -f'), which gets the executable and its arguments appended.
+    /// A wrapper configured with 'wrap_with()' runs inside of it.
+    pub(crate) fn command_wrapped<IA, S, IE, K, V>(
+        &self,
+        wrapper: &[OsString],
+        args: IA,
+        envs: IE,
+    ) -> PreparedCommand
     where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
         IE: IntoIterator<Item = (K, V)>,
         K: AsRef<OsStr>,
         V: AsRef<OsStr>,
     {
-        self.call_args_envs(NO_ARGS, envs)
+        #[cfg(unix)]
+        let sudo: Vec<OsString> = if self.sudo {
+            crate::privilege::SUDO.iter().map(OsString::from).collect()
+        } else {
+            Vec::new()
+        };
+        #[cfg(not(unix))]
+        let sudo: Vec<OsString> = Vec::new();
+        let wrapper: Vec<&OsString> = sudo.iter().chain(wrapper).chain(&self.wrapper).collect();
+        let mut command = match (self.executable, wrapper.split_first()) {
+            (ExeLocation::BinTest { executables, name }, None) => executables.command(name),
+            (ExeLocation::External(path), None) => Command::new(&*resolve::external(path)),
+            (executable, Some((program, wrapper_args))) => {
+                let mut command = Command::new(program);
+                command.args(wrapper_args);
+                match executable {
+                    ExeLocation::BinTest { executables, name } => {
+                        command.arg(executables.command(name).get_program())
+                    }
+                    ExeLocation::External(path) => command.arg(&*resolve::external(path)),
+                };
+                command
+            }
+        };
+        match (&self.dir, &self.backend) {
+            (Some(dir), _) => {
+                command.current_dir(dir.path());
+            }
+            (None, Some(backend)) => {
+                command.current_dir(backend.path());
+            }
+            (None, None) => {}
+        }
+
+        let mut envs = envs.into_iter().fuse().peekable();
+        let env_cleared = self.clears_env(envs.peek().is_some());
+        if env_cleared {
+            command.env_clear();
+        }
+        if let Some(EnvPolicy::Allowlist(keys)) = &self.env_policy {
+            for key in keys {
+                if let Some(value) = env::var_os(key) {
+                    command.env(key, value);
+                }
+            }
+        }
+        for (key, value) in &self.extra_envs {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        command.envs(envs);
+        if self.coverage_env {
+            for (key, value) in coverage_envs(|key| env::var_os(key)) {
+                if !command
+                    .get_envs()
+                    .any(|(given, _)| given == OsStr::new(key))
+                {
+                    command.env(key, value);
+                }
+            }
+        }
+        command.envs(self.fake_time_envs());
+        #[cfg(unix)]
+        if let Some(shim_dir) = &self.shim_dir {
+            command.env("PATH", crate::shims::path_with(shim_dir));
+        }
+        command.args(&self.extra_args);
+
+        #[cfg(all(target_os = "linux", feature = "netns"))]
+        if self.isolate_network {
+            crate::netns::isolate(&mut command);
+        }
+        #[cfg(all(target_os = "linux", feature = "sandbox"))]
+        if self.sandbox {
+            crate::sandbox::enter(&mut command);
+        }
+        #[cfg(unix)]
+        self.limits.apply(&mut command);
+        #[cfg(unix)]
+        if let Some((uid, gid)) = self.run_as {
+            crate::privilege::switch_user(&mut command, uid, gid);
+        }
+
+        command.args(args);
+        PreparedCommand {
+            command,
+            env_cleared,
+        }
     }
 
-    /// Calls the executable without arguments.
-    /// Returns a Output object for further investigation.
-    #[inline]
-    #[track_caller]
-    pub fn call(&self) -> Output {
-        self.call_args_envs(NO_ARGS, NO_ENVS)
+    /// Returns true when a call starts with an empty environment, 'envs_given' tells
+    /// whether the call gives its own variables.
+    fn clears_env(&self, envs_given: bool) -> bool {
+        match &self.env_policy {
+            None => envs_given,
+            Some(EnvPolicy::Inherit) => false,
+            Some(EnvPolicy::Clear) | Some(EnvPolicy::Allowlist(_)) => true,
+        }
     }
 
-    /// Spawns executable with the given arguments and environment in the background.
+    /// Returns true when calls are not executed but their output is made up by 'dry_run()'
+    /// or taken from a recording by 'replay()'.
+    pub(crate) fn simulated(&self) -> bool {
+        self.dry_run || self.replay.is_some()
+    }
+
+    /// Calls the executable with the given arguments and environment.
     /// `args` can be `NO_ARGS` or something iterateable that yields the arguments.
     /// `envs` can be `NO_ENVS` or something iterateable that yields the key/value pairs.
-    /// When any envs are given then the environment is cleared first.
-    /// Stdout and stderr are rigged to be piped back to the caller to be collected by
-    /// The TestChild::wait().
-    /// Returns a TestChild object for later investigation.
+    /// When any envs are given then the environment is cleared first, unless another
+    /// environment policy was set with 'inherit_env()' or 'env_allowlist()'.
+    /// Returns a CallOutput object for further investigation.
     #[track_caller]
-    pub fn spawn_args_envs<IA, S, IE, K, V>(&self, args: IA, envs: IE) -> TestChild
+    pub fn call_args_envs<IA, S, IE, K, V>(&self, args: IA, envs: IE) -> CallOutput
     where
         IA: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
@@ -136,86 +736,1295 @@ impl<'a> TestCall<'a> {
         K: AsRef<OsStr>,
         V: AsRef<OsStr>,
     {
-        let mut command = match self.executable {
-            ExeLocation::BinTest { executables, name } => executables.command(name),
-            ExeLocation::External(path) => Command::new(path),
-        };
-        if let Some(dir) = &self.dir {
-            command.current_dir(dir.path());
+        self.run(self.command(args, envs), None)
+    }
+
+    /// Calls the executable with stdout and stderr written directly to the files 'stdout'
+    /// and 'stderr' in 'dir' (replacing earlier ones) instead of collecting them in memory.
+    /// Meant for calls producing large outputs, the returned 'TestOutputFiles' asserts on
+    /// the files with bounded memory. The output limit and teeing do not apply.
+    #[track_caller]
+    pub fn capture_to_dir<T, IA, S>(&self, dir: &T, args: IA) -> TestOutputFiles
+    where
+        T: TestPath + ?Sized,
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let create = |name| File::create(dir.path().join(name)).expect("output file created");
+        let mut command = self.command(args, NO_ENVS);
+        if self.simulated() {
+            let output = self.run(command, None);
+            for (name, content) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+                create(name)
+                    .write_all(content)
+                    .expect("output file written");
+            }
+            return TestOutputFiles::new(output.status, output.duration(), None, dir.path());
         }
+        command
+            .stdin(Stdio::null())
+            .stdout(create("stdout"))
+            .stderr(create("stderr"));
 
-        let mut envs = envs.into_iter().fuse().peekable();
-        if envs.peek().is_some() {
-            command.env_clear();
-            command.envs(envs);
+        let started = Instant::now();
+        let mut child = command.spawn().expect("spawned command");
+        let (status, usage) = match self.timeout {
+            None => rusage::wait(&mut child),
+            Some(timeout) => loop {
+                if let Some(exited) = rusage::try_wait(&mut child) {
+                    break exited;
+                }
+                if started.elapsed() >= timeout {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    Failure::new(
+                        "capture_to_dir",
+                        format!(
+                            "timed out after {:?}, output is in {:?}",
+                            timeout,
+                            dir.path()
+                        ),
+                    )
+                    .fail();
+                }
+                thread::sleep(POLL_INTERVAL);
+            },
+        };
+        let duration = started.elapsed();
+        history::record(&command, status, duration, None);
+        TestOutputFiles::new(status, duration, usage, dir.path())
+    }
+
+    /// Calls the executable concurrently once for each argument list in 'args_list', as many
+    /// at a time as there are cpus. Returns the outputs in the order of 'args_list', to
+    /// exercise concurrent behavior and assert the results collectively.
+    #[track_caller]
+    pub fn call_batch<IL, IA, S>(&self, args_list: IL) -> Vec<CallOutput>
+    where
+        IL: IntoIterator<Item = IA>,
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        self.batch(args_list, workers)
+    }
+
+    /// Implements 'call_batch()' with at most 'workers' children running at a time. A new
+    /// child is started as soon as any running one exited.
+    #[track_caller]
+    fn batch<IL, IA, S>(&self, args_list: IL, workers: usize) -> Vec<CallOutput>
+    where
+        IL: IntoIterator<Item = IA>,
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        if self.simulated() {
+            return args_list
+                .into_iter()
+                .map(|args| self.run(self.command(args, NO_ENVS), None))
+                .collect();
         }
+        let mut args_list = args_list.into_iter();
+        // (index in 'outputs', child)
+        let mut running: Vec<(usize, TestChild)> = Vec::with_capacity(workers);
+        let mut outputs = Vec::new();
+        loop {
+            while running.len() < workers.max(1) {
+                match args_list.next() {
+                    Some(args) => {
+                        let child = self.start(self.command(args, NO_ENVS), Some(Stdio::null()));
+                        running.push((outputs.len(), child));
+                        outputs.push(None);
+                    }
+                    None => break,
+                }
+            }
+            if running.is_empty() {
+                break;
+            }
 
-        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+            let done = running.iter_mut().position(|(_, child)| {
+                !child.is_running()
+                    || self
+                        .timeout
+                        .is_some_and(|timeout| child.started.elapsed() >= timeout)
+            });
+            match done {
+                Some(done) => {
+                    let (index, child) = running.swap_remove(done);
+                    outputs[index] = Some(match self.timeout {
+                        Some(timeout) => child.wait_timeout(timeout),
+                        None => child.wait(),
+                    });
+                }
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+        outputs
+            .into_iter()
+            .map(|output| output.expect("child waited for"))
+            .collect()
+    }
 
-        TestChild(command.args(args).spawn().expect("spawned command"))
+    /// Calls the executable with the given arguments until 'done' accepts the output, at most
+    /// 'attempts' times. Waits 'backoff' before the first retry and doubles the wait for each
+    /// further one. Returns the outputs of all attempts, the last one passed. Panics with the
+    /// last output when no attempt passed. Meant for services which need a moment to become
+    /// ready.
+    #[track_caller]
+    pub fn call_with_retry<IA, S, F>(
+        &self,
+        args: IA,
+        attempts: usize,
+        backoff: Duration,
+        mut done: F,
+    ) -> Vec<CallOutput>
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+        F: FnMut(&CallOutput) -> bool,
+    {
+        assert!(attempts > 0, "call_with_retry needs at least one attempt");
+        let args: Vec<OsString> = args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_os_string())
+            .collect();
+        let mut outputs = Vec::with_capacity(attempts);
+        let mut wait = backoff;
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                thread::sleep(wait);
+                wait *= 2;
+            }
+            let output = self.call_args(&args);
+            let passed = done(&output);
+            outputs.push(output);
+            if passed {
+                return outputs;
+            }
+        }
+        let last = outputs.last().expect("one attempt");
+        Failure::new(
+            "call_with_retry",
+            format!(
+                "no attempt of {} passed, last {}\nstdout was:\n{}\nstderr was:\n{}",
+                attempts,
+                last.status,
+                String::from_utf8_lossy(&last.stdout),
+                String::from_utf8_lossy(&last.stderr)
+            ),
+        )
+        .fail();
     }
 
-    /// Spawns the executable with the given arguments into background.
-    /// `args` can be `NO_ARGS` or something iterateable that yields the arguments.
-    /// Returns a TestChild object for later investigation.
+    /// Calls the executable with the given arguments and writes 'input' to its stdin, which
+    /// is closed afterwards. The output is collected concurrently, thus programs which
+    /// produce output before consuming all input do not deadlock.
+    /// Returns a CallOutput object for further investigation.
     #[inline]
     #[track_caller]
-    pub fn spawn_args<IA, S>(&self, args: IA) -> TestChild
+    pub fn call_with_stdin<IA, S>(&self, args: IA, input: &[u8]) -> CallOutput
     where
         IA: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        self.spawn_args_envs(args, NO_ENVS)
+        self.call_with_stdin_envs(args, NO_ENVS, input)
     }
 
-    /// Spawns the executable without arguments into background.
-    /// `envs` can be `NO_ENVS` or something iterateable that yields the key/value pairs.
-    /// When any envs are given then the environment is cleared first.
-    /// Returns a TestChild object for later investigation.
-    #[inline]
+    /// Calls the executable with the given arguments and environment and writes 'input' to
+    /// its stdin. When any envs are given then the environment is cleared first, unless another
+    /// environment policy was set with 'inherit_env()' or 'env_allowlist()'.
+    /// Returns a CallOutput object for further investigation.
     #[track_caller]
-    pub fn spawn_envs<IE, K, V>(&self, envs: IE) -> TestChild
+    pub fn call_with_stdin_envs<IA, S, IE, K, V>(
+        &self,
+        args: IA,
+        envs: IE,
+        mut input: &[u8],
+    ) -> CallOutput
     where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
         IE: IntoIterator<Item = (K, V)>,
         K: AsRef<OsStr>,
         V: AsRef<OsStr>,
     {
-        self.spawn_args_envs(NO_ARGS, envs)
+        self.run(self.command(args, envs), Some(&mut input))
     }
 
-    /// Spawns the executable without arguments into background.
-    /// Returns a TestChild object for later investigation.
-    #[inline]
+    /// Calls the executable with the given arguments and pumps its stdin from 'stdin' in
+    /// chunks while the output is collected. This allows streaming huge inputs without
+    /// materializing them in memory or on disk first. When the executable exits before
+    /// consuming all input, the rest is discarded.
+    /// Returns a CallOutput object for further investigation.
     #[track_caller]
-    pub fn spawn(&self) -> TestChild {
-        self.spawn_args_envs(NO_ARGS, NO_ENVS)
+    pub fn call_with_stdin_reader<IA, S, R>(&self, args: IA, mut stdin: R) -> CallOutput
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+        R: Read + Send,
+    {
+        self.run(self.command(args, NO_ENVS), Some(&mut stdin))
     }
-}
 
-pub const NO_ARGS: [&OsStr; 0] = [];
+    /// Calls the executable with the given arguments and the file at 'path' connected
+    /// directly to its stdin, without loading it into memory or copying it through a pipe.
+    /// A relative 'path' is taken within the directory of the call, the one set with
+    /// 'current_dir()' or the one of the directory backend, when there is one.
+    /// Returns a CallOutput object for further investigation.
+    #[track_caller]
+    pub fn call_stdin_file<IA, S, P>(&self, args: IA, path: P) -> CallOutput
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+        P: AsRef<Path>,
+    {
+        let path = match (&self.dir, &self.backend) {
+            (Some(dir), _) => dir.path().join(path),
+            (None, Some(backend)) => backend.path().join(path),
+            (None, None) => path.as_ref().to_path_buf(),
+        };
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) => Failure::new(
+                "call_stdin_file",
+                format!("opening stdin file failed: {}", err),
+            )
+            .with_actual("path", &path.display().to_string())
+            .fail(),
+        };
+        let command = self.command(args, NO_ENVS);
+        if self.simulated() {
+            return self.run(command, Some(&mut file));
+        }
+        let child = self.start(command, Some(Stdio::from(file)));
+        match self.timeout {
+            Some(timeout) => child.wait_timeout(timeout),
+            None => child.wait(),
+        }
+    }
+
+    /// Runs a prepared command to completion, feeding 'stdin' when given.
+    #[track_caller]
+    pub(crate) fn run(
+        &self,
+        command: PreparedCommand,
+        stdin: Option<&mut (dyn Read + Send)>,
+    ) -> CallOutput {
+        if self.dry_run {
+            return self.dry_run_output(&command);
+        }
+        match &self.replay {
+            Some((dir, mode)) => self.replay_run(dir, *mode, command, stdin),
+            None => self.execute(command, stdin),
+        }
+    }
+
+    /// Runs 'command' to completion, feeding it 'stdin'.
+    #[track_caller]
+    pub(crate) fn execute(
+        &self,
+        command: PreparedCommand,
+        stdin: Option<&mut (dyn Read + Send)>,
+    ) -> CallOutput {
+        let mut child = self.start(
+            command,
+            Some(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            }),
+        );
+        let pipe = child.child.stdin.take();
+
+        thread::scope(|scope| {
+            let feeder = match (stdin, pipe) {
+                (Some(reader), Some(mut pipe)) => {
+                    Some(scope.spawn(move || io::copy(reader, &mut pipe)))
+                }
+                _ => None,
+            };
+            let output = match self.timeout {
+                Some(timeout) => child.wait_timeout(timeout),
+                None => child.wait(),
+            };
+            match feeder.map(|feeder| feeder.join().expect("stdin fed")) {
+                Some(Err(err)) if err.kind() != ErrorKind::BrokenPipe => {
+                    panic!("feeding stdin failed: {}", err)
+                }
+                _ => output,
+            }
+        })
+    }
+
+    /// Spawns a prepared command with its output collected in the background. Leaves stdin
+    /// at the 'Command' default when 'stdin' is None.
+    #[track_caller]
+    fn start(&self, command: PreparedCommand, stdin: Option<Stdio>) -> TestChild {
+        self.start_piped(command, stdin, false).0
+    }
+
+    /// Like 'start()' but with 'pipe_stdout' the stdout of the child is returned for
+    /// connecting it to another process instead of being collected.
+    #[track_caller]
+    pub(crate) fn start_piped(
+        &self,
+        command: PreparedCommand,
+        stdin: Option<Stdio>,
+        pipe_stdout: bool,
+    ) -> (TestChild, Option<ChildStdout>) {
+        let mut command = command.command;
+        // only here, thus exactly the calls which run the after call hooks run these
+        if let Some(backend) = &self.backend {
+            backend.before_call(&mut command);
+        }
+        for hook in &self.before_call {
+            hook(&mut command);
+        }
+        if let Some(stdin) = stdin {
+            command.stdin(stdin);
+        }
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let crash_dir = self.crash_dir();
+        if crash_dir.is_some() {
+            coredump::prepare(&mut command);
+        }
+        // a pty session is a process group of its own already
+        #[cfg(all(unix, feature = "pty"))]
+        let own_session = self.pty.is_some();
+        #[cfg(all(unix, not(feature = "pty")))]
+        let own_session = false;
+        #[cfg(unix)]
+        if self.process_group && !own_session {
+            std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        }
+        #[cfg(all(unix, feature = "pty"))]
+        let terminal = self
+            .pty
+            .map(|(cols, rows)| crate::pty::attach(&mut command, cols, rows));
+        let started = Instant::now();
+        let mut child = command.spawn().expect("spawned command");
+        // the child keeps the command, which must not hold on to the read end of a pipe from
+        // an earlier pipeline stage, that stage would never get SIGPIPE then
+        command.stdin(Stdio::null());
+        #[cfg(all(unix, feature = "pty"))]
+        if let Some(terminal) = terminal {
+            crate::pty::detach(&mut command, &mut child, terminal);
+        }
+        let stdout = if pipe_stdout {
+            child.stdout.take()
+        } else {
+            None
+        };
+
+        #[cfg(unix)]
+        let reaped = Arc::new(AtomicBool::new(false));
+        #[cfg(unix)]
+        let on_exceeded: Option<OnExceeded> = {
+            let pid = child.id() as libc::pid_t;
+            let group = self.process_group || own_session;
+            let reaped = Arc::clone(&reaped);
+            Some(Arc::new(move || {
+                // the pid is not reused before the child was reaped
+                if !reaped.load(Ordering::SeqCst) {
+                    // SAFETY: plain syscalls on our own unreaped child
+                    unsafe {
+                        if group {
+                            libc::killpg(pid, libc::SIGKILL);
+                        } else {
+                            libc::kill(pid, libc::SIGKILL);
+                        }
+                    }
+                }
+            }))
+        };
+        #[cfg(not(unix))]
+        let on_exceeded: Option<OnExceeded> = None;
+        let capture = Capture::start(
+            &mut child,
+            self.tee_enabled(),
+            self.max_output_limit(),
+            self.output_policy.clone(),
+            on_exceeded,
+        );
+        let child = TestChild {
+            child,
+            capture,
+            command,
+            started,
+            #[cfg(unix)]
+            group: self.process_group || own_session,
+            #[cfg(unix)]
+            reaped,
+            stack_dump: self.stack_dump_enabled(),
+            normalize_newlines: self.normalize_newlines,
+            crash_dir,
+            backend: self.backend.clone(),
+            after_call: self.after_call.clone(),
+            stdout_read: 0,
+            stderr_read: 0,
+            exited: None,
+            finished: false,
+        };
+        (child, stdout)
+    }
+
+    /// Calls the executable with the given arguments.
+    /// `args` can be `NO_ARGS` or something iterateable that yields the arguments.
+    /// Returns a CallOutput object for further investigation.
+    #[inline]
+    #[track_caller]
+    pub fn call_args<IA, S>(&self, args: IA) -> CallOutput
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.call_args_envs(args, NO_ENVS)
+    }
+
+    /// Convinience method to call the executable with the given arguments.
+    /// `args` is a single '&str' split at ascii_whitespace. It is important to note that this
+    /// only works when the arguments themself do not contain whitespace characters (like
+    /// quoted strings "Hello World"). Returns a CallOutput object for further investigation.
+    #[inline]
+    #[track_caller]
+    pub fn call_argstr(&self, args: &str) -> CallOutput {
+        self.call_args_envs(args.split_ascii_whitespace(), NO_ENVS)
+    }
+
+    /// Calls the executable with 'cmdline' split into arguments by shell word rules, so
+    /// command lines from documentation or bug reports can be used verbatim. Single and double
+    /// quotes and backslash escapes are honored, no expansions are done.
+    #[track_caller]
+    pub fn call_cmdline(&self, cmdline: &str) -> CallOutput {
+        self.call_args_envs(shell_words(cmdline), NO_ENVS)
+    }
+
+    /// Calls the executable without arguments.
+    /// `envs` can be `NO_ENVS` or something iterateable that yields the key/value pairs.
+    /// When any envs are given then the environment is cleared first, unless another
+    /// environment policy was set with 'inherit_env()' or 'env_allowlist()'.
+    /// Returns a CallOutput object for further investigation.
+    #[inline]
+    #[track_caller]
+    pub fn call_envs<IE, K, V>(&self, envs: IE) -> CallOutput
+    where
+        IE: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.call_args_envs(NO_ARGS, envs)
+    }
+
+    /// Calls the executable without arguments.
+    /// Returns a CallOutput object for further investigation.
+    #[inline]
+    #[track_caller]
+    pub fn call(&self) -> CallOutput {
+        self.call_args_envs(NO_ARGS, NO_ENVS)
+    }
+
+    /// Spawns executable with the given arguments and environment in the background.
+    /// `args` can be `NO_ARGS` or something iterateable that yields the arguments.
+    /// `envs` can be `NO_ENVS` or something iterateable that yields the key/value pairs.
+    /// When any envs are given then the environment is cleared first, unless another
+    /// environment policy was set with 'inherit_env()' or 'env_allowlist()'.
+    /// Stdout and stderr are rigged to be piped back to the caller and collected in the
+    /// background until TestChild::wait() returns them.
+    /// Returns a TestChild object for later investigation.
+    #[track_caller]
+    pub fn spawn_args_envs<IA, S, IE, K, V>(&self, args: IA, envs: IE) -> TestChild
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+        IE: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.start(self.command(args, envs), None)
+    }
+
+    /// Spawns the executable with the given arguments into background.
+    /// `args` can be `NO_ARGS` or something iterateable that yields the arguments.
+    /// Returns a TestChild object for later investigation.
+    #[inline]
+    #[track_caller]
+    pub fn spawn_args<IA, S>(&self, args: IA) -> TestChild
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.spawn_args_envs(args, NO_ENVS)
+    }
+
+    /// Spawns the executable without arguments into background.
+    /// `envs` can be `NO_ENVS` or something iterateable that yields the key/value pairs.
+    /// When any envs are given then the environment is cleared first, unless another
+    /// environment policy was set with 'inherit_env()' or 'env_allowlist()'.
+    /// Returns a TestChild object for later investigation.
+    #[inline]
+    #[track_caller]
+    pub fn spawn_envs<IE, K, V>(&self, envs: IE) -> TestChild
+    where
+        IE: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.spawn_args_envs(NO_ARGS, envs)
+    }
+
+    /// Spawns the executable without arguments into background.
+    /// Returns a TestChild object for later investigation.
+    #[inline]
+    #[track_caller]
+    pub fn spawn(&self) -> TestChild {
+        self.spawn_args_envs(NO_ARGS, NO_ENVS)
+    }
+
+    /// Spawns the executable with the given arguments into background with stdin piped,
+    /// to be fed step by step with 'TestChild::write_stdin()'. Stdin stays open until
+    /// 'TestChild::close_stdin()' or a wait.
+    #[track_caller]
+    pub fn spawn_interactive<IA, S>(&self, args: IA) -> TestChild
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.start(self.command(args, NO_ENVS), Some(Stdio::piped()))
+    }
+}
+
+/// The variables of coverage tools which are passed to children regardless of the
+/// environment policy.
+const COVERAGE_ENVS: &[&str] = &[
+    "LLVM_PROFILE_FILE",
+    "CARGO_LLVM_COV",
+    "CARGO_LLVM_COV_TARGET_DIR",
+    "GCOV_PREFIX",
+    "GCOV_PREFIX_STRIP",
+];
+
+/// Returns the coverage variables set according to 'lookup'. A 'LLVM_PROFILE_FILE' without
+/// '%p' or '%m' gets a '%p' so that every call writes its own profile instead of
+/// overwriting the one of the previous call.
+fn coverage_envs<F>(lookup: F) -> Vec<(&'static str, OsString)>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    COVERAGE_ENVS
+        .iter()
+        .filter_map(|&key| {
+            let mut value = lookup(key)?;
+            if key == "LLVM_PROFILE_FILE" {
+                let pattern = value.to_string_lossy().into_owned();
+                if !pattern.contains("%p") && !pattern.contains("%m") {
+                    value = match pattern.strip_suffix(".profraw") {
+                        Some(stem) => format!("{}-%p.profraw", stem),
+                        None => format!("{}-%p", pattern),
+                    }
+                    .into();
+                }
+            }
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Splits 'line' into words like a POSIX shell does without any expansions. Panics on
+/// unterminated quotes.
+#[track_caller]
+pub(crate) fn shell_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(escaped) => word.get_or_insert_with(String::new).push(escaped),
+                None => word.get_or_insert_with(String::new).push('\\'),
+            },
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => panic!("unterminated single quote in {:?}", line),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => panic!("unterminated double quote in {:?}", line),
+                        },
+                        Some(c) => word.push(c),
+                        None => panic!("unterminated double quote in {:?}", line),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Returns true when the environment variable 'name' is set to anything but "0".
+pub(crate) fn env_flag(name: &str) -> bool {
+    env::var_os(name).is_some_and(|value| value != "0")
+}
+
+pub const NO_ARGS: [&OsStr; 0] = [];
 pub const NO_ENVS: [(&OsStr, &OsStr); 0] = [];
 
-/// The handle to background processes
-pub struct TestChild(Child);
+/// A 'Command' as prepared by 'TestCall::command()', dereferences to it.
+pub(crate) struct PreparedCommand {
+    command: Command,
+    env_cleared: bool,
+}
+
+impl PreparedCommand {
+    /// Returns true when the command starts with an empty environment.
+    pub(crate) fn env_cleared(&self) -> bool {
+        self.env_cleared
+    }
+}
+
+impl Deref for PreparedCommand {
+    type Target = Command;
+
+    fn deref(&self) -> &Command {
+        &self.command
+    }
+}
+
+impl DerefMut for PreparedCommand {
+    fn deref_mut(&mut self) -> &mut Command {
+        &mut self.command
+    }
+}
+
+/// The handle to background processes. Dropping it kills a still running process.
+pub struct TestChild {
+    pub(crate) child: Child,
+    pub(crate) capture: Capture,
+    command: Command,
+    pub(crate) started: Instant,
+    // whether the child leads a process group of its own
+    #[cfg(unix)]
+    group: bool,
+    // set once the child was reaped, it must not be killed by its pid anymore then
+    #[cfg(unix)]
+    reaped: Arc<AtomicBool>,
+    stack_dump: bool,
+    normalize_newlines: bool,
+    crash_dir: Option<PathBuf>,
+    backend: Option<Arc<AttachedBackend>>,
+    after_call: Vec<AfterCall>,
+    // how much output was consumed by the incremental readers
+    stdout_read: usize,
+    stderr_read: usize,
+    // the status of a child reaped by 'is_running()' which was not waited for yet
+    pub(crate) exited: Option<(ExitStatus, Option<Usage>)>,
+    finished: bool,
+}
+
+impl TestChild {
+    /// Returns the OS assigned process identifier of the child.
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Waits for the completion of a child process and returns
+    /// a CallOutput object for further investigation.
+    pub fn wait(mut self) -> CallOutput {
+        self.close_stdin();
+        let (status, usage) = match self.exited.take() {
+            Some(exited) => exited,
+            None => self.reap(),
+        };
+        self.finish(status, usage, None)
+    }
+
+    /// Checks whether the child is still running. An exited child is reaped, its status is
+    /// kept for 'wait()'.
+    pub fn is_running(&mut self) -> bool {
+        if self.exited.is_none() {
+            self.exited = rusage::try_wait(&mut self.child);
+            if self.exited.is_some() {
+                self.mark_reaped();
+            }
+        }
+        self.exited.is_none()
+    }
+
+    /// Waits until the child exited and reaps it.
+    fn reap(&mut self) -> (ExitStatus, Option<Usage>) {
+        let exited = rusage::wait(&mut self.child);
+        self.mark_reaped();
+        exited
+    }
+
+    fn mark_reaped(&self) {
+        #[cfg(unix)]
+        self.reaped.store(true, Ordering::SeqCst);
+    }
+
+    /// Waits for the completion of the child until 'deadline', returns None when it is still
+    /// running then.
+    pub(crate) fn wait_until(&mut self, deadline: Instant) -> Option<CallOutput> {
+        self.close_stdin();
+        loop {
+            if !self.is_running() {
+                let (status, usage) = self.exited.take().expect("child exited");
+                return Some(self.finish(status, usage, Some(deadline)));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Collects the output of the exited child, waits at most until 'deadline' for its
+    /// output streams to be closed.
+    fn finish(
+        &mut self,
+        status: ExitStatus,
+        usage: Option<Usage>,
+        deadline: Option<Instant>,
+    ) -> CallOutput {
+        self.finished = true;
+        let (mut stdout, mut stderr) = self.capture.finish(deadline);
+        if self.normalize_newlines {
+            stdout = normalize::unix_newlines(&stdout);
+            stderr = normalize::unix_newlines(&stderr);
+        }
+        let duration = self.started.elapsed();
+        let exceeded = self.capture.limit_failure();
+        history::record(
+            &self.command,
+            status,
+            duration,
+            exceeded.as_ref().map(Failure::message),
+        );
+        if let Some(failure) = exceeded {
+            failure.fail();
+        }
+        let crash_report = self.crash_dir.as_ref().and_then(|dir| {
+            let cwd = match self.command.get_current_dir() {
+                Some(cwd) => cwd.to_path_buf(),
+                None => env::current_dir().expect("current dir"),
+            };
+            coredump::collect(status, self.child.id(), &cwd, &stderr, dir)
+        });
+        let output = CallOutput::new(
+            Output {
+                status,
+                stdout,
+                stderr,
+            },
+            duration,
+            usage,
+        )
+        .with_crash_report(crash_report);
+        if let Some(backend) = &self.backend {
+            backend.after_call(&output);
+        }
+        for hook in &self.after_call {
+            hook(&output);
+        }
+        output
+    }
+
+    /// Waits for the completion of a child process, but at most 'timeout' after it was
+    /// spawned. When the timeout expires the child is killed and the test panics with the
+    /// output collected so far and, when enabled by 'TestCall::stack_dump_on_timeout()', the
+    /// backtraces of the hanging process.
+    #[track_caller]
+    pub fn wait_timeout(mut self, timeout: Duration) -> CallOutput {
+        match self.wait_until(self.started + timeout) {
+            Some(output) => output,
+            None => self.timed_out(timeout),
+        }
+    }
+
+    /// Kills the child which is still running after 'timeout' and fails with the output
+    /// collected so far.
+    #[track_caller]
+    pub(crate) fn timed_out(mut self, timeout: Duration) -> ! {
+        let stack_dump = if self.stack_dump {
+            format!("\n{}", stackdump::stack_dump(self.child.id()))
+        } else {
+            String::new()
+        };
+        self.kill_group();
+        let _ = self.child.kill();
+        let (status, usage) = self.reap();
+        // the deadline passed already, grandchildren outside of the process group may keep
+        // the output open
+        let output = self.finish(status, usage, Some(Instant::now() + KILL_GRACE));
+        Failure::new(
+            "wait_timeout",
+            format!(
+                "timed out after {:?}{}\nstdout was:\n{}\nstderr was:\n{}",
+                timeout,
+                stack_dump,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        )
+        .fail();
+    }
+
+    /// Returns the time from spawning until the first byte arrived on stdout, None when
+    /// nothing was written yet.
+    pub fn first_output_latency(&self) -> Option<Duration> {
+        self.capture
+            .stdout()
+            .first()
+            .map(|at| at.duration_since(self.started))
+    }
+
+    /// Waits until the child writes its first byte to stdout and asserts that this happened
+    /// within 'duration' after spawning. Catches slow startups before any feedback is given.
+    /// Returns the measured latency.
+    #[track_caller]
+    pub fn assert_first_output_within(&self, duration: Duration) -> Duration {
+        let stdout = self.capture.stdout();
+        stdout.wait_until(self.started + duration, |data| !data.is_empty());
+        match stdout.first().map(|at| at.duration_since(self.started)) {
+            Some(latency) if latency <= duration => latency,
+            Some(latency) => Failure::new(
+                "assert_first_output_within",
+                format!(
+                    "first output after {:?}, expected within {:?}",
+                    latency, duration
+                ),
+            )
+            .fail(),
+            None => Failure::new(
+                "assert_first_output_within",
+                format!("no output within {:?}", duration),
+            )
+            .fail(),
+        }
+    }
+
+    /// Returns the number of bytes the child wrote to stdout so far.
+    pub fn stdout_bytes(&self) -> usize {
+        self.capture.stdout().len()
+    }
+
+    /// Returns the number of bytes the child wrote to stderr so far.
+    pub fn stderr_bytes(&self) -> usize {
+        self.capture.stderr().len()
+    }
+
+    /// Returns the stdout written since the last read without waiting. The reads only move
+    /// a cursor, the final 'CallOutput' still contains the whole output. A character which
+    /// was not completely written yet stays unread until the rest of it arrives.
+    pub fn read_available_stdout(&mut self) -> String {
+        let data = self.capture.stdout().since_utf8(self.stdout_read);
+        self.stdout_read += data.len();
+        String::from_utf8_lossy(&data).into_owned()
+    }
+
+    /// Returns the stderr written since the last read without waiting, like
+    /// 'read_available_stdout()'.
+    pub fn read_available_stderr(&mut self) -> String {
+        let data = self.capture.stderr().since_utf8(self.stderr_read);
+        self.stderr_read += data.len();
+        String::from_utf8_lossy(&data).into_owned()
+    }
+
+    /// Waits at most 'timeout' until the unread stdout matches 'regex' and returns it up to
+    /// the end of the match. Anything after the match stays unread for the next read. Panics
+    /// with the unread output when there is no match in time or the child closed stdout.
+    #[track_caller]
+    pub fn read_until(&mut self, regex: &str, timeout: Duration) -> String {
+        let start = self.stdout_read;
+        let mut end = None;
+        self.capture
+            .stdout()
+            .wait_until(Instant::now() + timeout, |data| {
+                end = crate::regex::find_end_bytes(&data[start..], regex).map(|end| start + end);
+                end.is_some()
+            });
+        let data = self.capture.stdout().since(start);
+        match end {
+            Some(end) => {
+                self.stdout_read = end;
+                String::from_utf8_lossy(&data[..end - start]).into_owned()
+            }
+            None => Failure::new(
+                "read_until",
+                format!(
+                    "stdout did not match {:?} within {:?}, unread stdout was:\n{}",
+                    regex,
+                    timeout,
+                    String::from_utf8_lossy(&data)
+                ),
+            )
+            .fail(),
+        }
+    }
+
+    /// Writes 'bytes' to the stdin of a child spawned by 'TestCall::spawn_interactive()'.
+    /// Panics when stdin is not piped, was closed or the child stopped reading.
+    #[track_caller]
+    pub fn write_stdin(&mut self, bytes: impl AsRef<[u8]>) -> &mut Self {
+        let stdin = match self.child.stdin.as_mut() {
+            Some(stdin) => stdin,
+            None => Failure::new(
+                "write_stdin",
+                String::from("stdin is not open, spawn with 'spawn_interactive()'"),
+            )
+            .fail(),
+        };
+        if let Err(err) = stdin.write_all(bytes.as_ref()).and_then(|_| stdin.flush()) {
+            Failure::new("write_stdin", format!("writing stdin failed: {}", err)).fail();
+        }
+        self
+    }
+
+    /// Closes stdin, the child reads end of file then. Closing a closed stdin does nothing.
+    pub fn close_stdin(&mut self) -> &mut Self {
+        drop(self.child.stdin.take());
+        self
+    }
+
+    /// Waits at most 'timeout' until a line of the unread stdout matches 'regex' and returns
+    /// its captures, to synchronize with readiness messages like "Listening on port 8080".
+    /// Only complete lines are matched, lossy converted to utf8 and without their line
+    /// ending. The output up to and including the matching line is consumed. Panics with the
+    /// unread output when no line matched in time or the child closed stdout.
+    #[track_caller]
+    pub fn wait_for_stdout(&mut self, regex: &str, timeout: Duration) -> Captured {
+        let start = self.stdout_read;
+        let deadline = Instant::now() + timeout;
+        let mut scanned = start;
+        let mut found = None;
+        self.capture.stdout().wait_until(deadline, |data| {
+            while let Some(len) = data[scanned..].iter().position(|&byte| byte == b'\n') {
+                let mut line = &data[scanned..scanned + len];
+                scanned += len + 1;
+                if line.last() == Some(&b'\r') {
+                    line = &line[..line.len() - 1];
+                }
+                if crate::regex::regex_match_utf8(line, regex).0 {
+                    found = Some(crate::regex::captures_utf8(line, regex));
+                    return true;
+                }
+            }
+            false
+        });
+        match found {
+            Some(captured) => {
+                self.stdout_read = scanned;
+                captured
+            }
+            None => Failure::new(
+                "wait_for_stdout",
+                format!(
+                    "no stdout line matched {:?} {}, unread stdout was:\n{}",
+                    regex,
+                    if Instant::now() >= deadline {
+                        format!("within {:?}", timeout)
+                    } else {
+                        String::from("before stdout was closed")
+                    },
+                    String::from_utf8_lossy(&self.capture.stdout().since(start))
+                ),
+            )
+            .fail(),
+        }
+    }
+
+    /// Kills a child process unconditionally.
+    pub fn kill(mut self) {
+        self.terminate();
+    }
+
+    /// Kills the child together with all processes it started which did not leave its
+    /// process group, like forked helpers or daemons, thus they can not keep the test
+    /// directory busy. Requires 'TestCall::process_group()' (or a pty) on unix, otherwise
+    /// and on other platforms only the child itself is killed.
+    pub fn kill_tree(mut self) {
+        self.kill_group();
+        self.terminate();
+    }
+
+    /// Sends SIGKILL to the process group of the child when it leads one.
+    fn kill_group(&mut self) {
+        #[cfg(unix)]
+        if self.group {
+            // SAFETY: the group id is the pid of the child, which is not reused while the
+            // group has members
+            unsafe {
+                libc::killpg(self.child.id() as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+
+    /// Kills and reaps the child unless it was reaped already.
+    fn terminate(&mut self) {
+        self.finished = true;
+        // a child reaped by 'is_running()' may have its pid reused already
+        if self.exited.is_none() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            self.mark_reaped();
+        }
+    }
+}
+
+/// Children which are neither waited for nor killed are killed when dropped, together with
+/// their process group when they lead one, thus a failing test does not leave servers
+/// running.
+impl Drop for TestChild {
+    fn drop(&mut self) {
+        if !self.finished {
+            // a child reaped by 'is_running()' may have its pid reused already
+            if self.exited.is_none() {
+                self.kill_group();
+            }
+            self.terminate();
+            // a child killed for exceeding the output limit fails even when not waited for
+            if !thread::panicking() {
+                if let Some(failure) = self.capture.limit_failure() {
+                    failure.fail();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod probe_test {
+    use crate::*;
+    use bintest::BinTest;
+    use std::sync::OnceLock;
+
+    /// The executables of this crate, built once.
+    fn executables() -> &'static BinTest {
+        static EXECUTABLES: OnceLock<BinTest> = OnceLock::new();
+        EXECUTABLES.get_or_init(BinTest::new)
+    }
+
+    /// A TestCall for the 'testcall-probe' binary of this crate.
+    pub(crate) fn probe() -> TestCall<'static> {
+        TestCall::new(executables(), "testcall-probe")
+    }
+
+    #[test]
+    fn for_each_bin() {
+        let mut names = Vec::new();
+        let count = TestCall::for_each_bin(executables(), |name, testcall| {
+            names.push(String::from(name));
+            testcall.call_args(["out:smoke"]).assert_success();
+        });
+        assert_eq!(count, names.len());
+        assert!(names.iter().any(|name| name == "testcall-probe"));
+    }
+
+    #[test]
+    #[should_panic(expected = "executables failed:\n  testcall-probe: ")]
+    fn for_each_bin_failure() {
+        TestCall::for_each_bin(executables(), |_, testcall| {
+            testcall.call_args(["exit:1"]).assert_success();
+        });
+    }
+
+    #[test]
+    fn probe_out_err() {
+        probe()
+            .call_args(["out:Hello World!", "err:oops"])
+            .assert_success()
+            .assert_stdout_utf8("^Hello World!\n$")
+            .assert_stderr_utf8("^oops\n$");
+    }
+
+    #[test]
+    fn probe_exit() {
+        probe()
+            .call_args(["out:before", "exit:3", "out:after"])
+            .assert_exitcode(3)
+            .assert_stdout_utf8("^before\n$");
+    }
+
+    #[test]
+    fn probe_env() {
+        probe()
+            .call_args_envs(["env:PROBE", "env:UNSET"], [("PROBE", "value")])
+            .assert_success()
+            .assert_stdout_utf8("^value\n<unset>\n$");
+    }
+
+    #[test]
+    fn probe_config_env() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut probe = probe();
+        probe.config_file(&tmpdir, "probe.conf", "", ConfigVia::Env("PROBE_CONFIG"));
+
+        // also set when the environment is cleared
+        probe
+            .call_args_envs(["env:PROBE_CONFIG"], [("OTHER", "")])
+            .assert_success()
+            .assert_stdout_utf8("probe.conf\n$");
+    }
+
+    #[test]
+    fn probe_template() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut template = probe();
+        template.config_file(&tmpdir, "probe.conf", "", ConfigVia::Env("PROBE_CONFIG"));
+
+        let mut tweaked = template.clone();
+        tweaked.config_file(&tmpdir, "other.conf", "", ConfigVia::Env("OTHER_CONFIG"));
+
+        template
+            .call_args(["env:PROBE_CONFIG", "env:OTHER_CONFIG"])
+            .assert_stdout_utf8("probe.conf\n<unset>\n$");
+        tweaked
+            .call_args(["env:PROBE_CONFIG", "env:OTHER_CONFIG"])
+            .assert_stdout_utf8("probe.conf\n.*other.conf\n$");
+    }
+
+    #[test]
+    fn probe_dir_backend() {
+        use std::path::Path;
+        use std::process::Command;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use testpath::TestPath;
+
+        struct Counting {
+            dir: tempfile::TempDir,
+            hooks: [AtomicUsize; 4],
+        }
+
+        impl TestPath for Counting {
+            fn path(&self) -> &Path {
+                self.dir.path()
+            }
+        }
 
-impl TestChild {
-    /// Waits for the completion of a child process and returns
-    /// a Output object for further investigation.
-    pub fn wait(self) -> Output {
-        self.0.wait_with_output().expect("wait success")
+        impl DirBackend for Counting {
+            fn setup(&self) {
+                self.hooks[0].fetch_add(1, Ordering::Relaxed);
+            }
+            fn before_call(&self, command: &mut Command) {
+                self.hooks[1].fetch_add(1, Ordering::Relaxed);
+                command.env("BACKEND", "counting");
+            }
+            fn after_call(&self, _output: &CallOutput) {
+                self.hooks[2].fetch_add(1, Ordering::Relaxed);
+            }
+            fn cleanup(&self) {
+                self.hooks[3].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let backend = Arc::new(Counting {
+            dir: tempfile::TempDir::new().expect("TempDir created"),
+            hooks: Default::default(),
+        });
+        let mut probe = probe();
+        probe.dir_backend(backend.clone());
+
+        probe
+            .call_args(["env:BACKEND", "cwd"])
+            .assert_stdout_utf8(&format!(
+                "^counting\n{}\n$",
+                ::regex::escape(&backend.path().display().to_string())
+            ));
+        probe.spawn_args(["out:spawned"]).wait();
+        probe.detach_dir_backend().call();
+
+        let counts: Vec<usize> = backend
+            .hooks
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        assert_eq!(counts, [1, 2, 2, 1]);
+
+        let failed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut failing = probe.clone();
+            failing.dir_backend(backend.clone());
+            failing.call_args(["exit:1"]).assert_success();
+        }));
+        assert!(failed.is_err());
+        assert_eq!(backend.hooks[3].load(Ordering::Relaxed), 2);
     }
 
-    /// Kills a child process unconditionally.
-    pub fn kill(mut self) {
-        let _ = self.0.kill();
+    #[test]
+    fn probe_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut probe = probe();
+        let before = events.clone();
+        let after = events.clone();
+        probe
+            .on_before_call(move |command| {
+                before.lock().unwrap().push(String::from("before"));
+                command.env("HOOKED", "yes");
+            })
+            .on_after_call(move |output| {
+                after
+                    .lock()
+                    .unwrap()
+                    .push(format!("after {}", output.status.code().unwrap_or(-1)));
+            });
+
+        probe
+            .call_args(["env:HOOKED"])
+            .assert_stdout_utf8("^yes\n$");
+        probe.clone().spawn_args(["exit:3"]).wait();
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["before", "after 0", "before", "after 3"]
+        );
+
+        // nothing is executed, neither kind of hook runs
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        probe.command_line(["env:HOOKED"], NO_ENVS);
+        probe.capture_to_dir(&tmpdir, ["env:HOOKED"]);
+        probe.dry_run(true).call_args(["env:HOOKED"]);
+        assert_eq!(events.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn probe_unknown_command() {
+        probe()
+            .call_args(["bogus"])
+            .assert_exitcode(2)
+            .assert_stderr_utf8("unknown command: bogus");
     }
 }
 
 #[cfg(test)]
 #[cfg(unix)]
 mod test {
+    use super::{coverage_envs, shell_words};
     use crate::*;
+    use std::ffi::OsString;
     use std::path::Path;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn echo_no_args() {
@@ -255,6 +2064,95 @@ mod test {
             .assert_stdout_utf8("^[^ ]* .*Cargo.toml\n$");
     }
 
+    #[test]
+    fn cmdline() {
+        assert_eq!(
+            shell_words(r#"--foo 'bar baz' "a \"quoted\" \$word" back\ slash '' x"y"z"#),
+            [
+                "--foo",
+                "bar baz",
+                "a \"quoted\" $word",
+                "back slash",
+                "",
+                "xyz"
+            ]
+        );
+
+        let testcall = TestCall::external_command(Path::new("printf"));
+        testcall
+            .call_cmdline("'%s|' --foo 'bar baz'")
+            .assert_stdout_utf8("^--foo\\|bar baz\\|$");
+    }
+
+    #[test]
+    #[should_panic(expected = "unterminated single quote")]
+    fn cmdline_unterminated() {
+        shell_words("--foo 'bar");
+    }
+
+    #[test]
+    fn batch() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let outputs = testcall.call_batch(
+            (0..4).map(|n| ["-c".to_string(), format!("sleep 0.{}; echo {}", 4 - n, n)]),
+        );
+        assert_eq!(outputs.len(), 4);
+        for (n, output) in outputs.iter().enumerate() {
+            output
+                .assert_success()
+                .assert_stdout_utf8(&format!("^{}\n$", n));
+        }
+    }
+
+    #[test]
+    fn batch_refills() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall
+            .current_dir(&tmpdir)
+            .timeout(Duration::from_secs(10));
+
+        // the first call only finishes after the third one ran, which requires that the
+        // second call's slot gets refilled while the first one is still running
+        let outputs = testcall.batch(
+            [
+                ["-c", "while [ ! -e go ]; do sleep 0.01; done; echo first"],
+                ["-c", "echo second"],
+                ["-c", "touch go; echo third"],
+            ],
+            2,
+        );
+        outputs[0].assert_stdout_utf8("^first\n$");
+        outputs[1].assert_stdout_utf8("^second\n$");
+        outputs[2].assert_stdout_utf8("^third\n$");
+    }
+
+    #[test]
+    fn retry() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&tmpdir);
+
+        let outputs = testcall.call_with_retry(
+            ["-c", "echo x >>tries; test $(wc -l <tries) -ge 3"],
+            5,
+            Duration::from_millis(10),
+            |output| output.status.success(),
+        );
+        assert_eq!(outputs.len(), 3);
+        outputs[0].assert_failure();
+    }
+
+    #[test]
+    #[should_panic(expected = "no attempt of 2 passed")]
+    fn retry_exhausted() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+        testcall.call_with_retry(["not", "ready"], 2, Duration::ZERO, |output| {
+            crate::regex::regex_match_utf8(&output.stdout, "^ready").0
+        });
+    }
+
     #[test]
     fn spawn() {
         let testcall = TestCall::external_command(Path::new("echo"));
@@ -268,4 +2166,581 @@ mod test {
             .assert_success()
             .assert_stdout_utf8("Hello World!");
     }
+
+    #[test]
+    fn is_running() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let mut child = testcall.spawn_args(["-c", "sleep 0.2"]);
+        assert!(child.is_running());
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while child.is_running() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!child.is_running());
+        child.wait().assert_success();
+    }
+
+    #[test]
+    fn kill_on_drop() {
+        let testcall = TestCall::external_command(Path::new("sleep"));
+
+        let child = testcall.spawn_args(["5"]);
+        let pid = child.id() as libc::pid_t;
+        drop(child);
+        // SAFETY: signal 0 only checks for existence
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+    }
+
+    /// Waits until the orphaned 'pid' was killed, a zombie waiting for init counts as dead.
+    fn assert_gone(pid: libc::pid_t) {
+        let zombie = || {
+            std::fs::read_to_string(format!("/proc/{}/stat", pid))
+                .is_ok_and(|stat| stat.rsplit(')').next().unwrap_or("").starts_with(" Z"))
+        };
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        // SAFETY: signal 0 only checks for existence
+        while unsafe { libc::kill(pid, 0) } == 0 && !zombie() {
+            assert!(std::time::Instant::now() < deadline, "{} survived", pid);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn kill_tree() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.process_group(true);
+
+        let mut child = testcall.spawn_args(["-c", "sleep 30 & echo $!; wait"]);
+        let helper: libc::pid_t = child
+            .read_until("\n", Duration::from_secs(5))
+            .trim()
+            .parse()
+            .expect("pid");
+        child.kill_tree();
+        assert_gone(helper);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn process_group_opt_in() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        let pgid = |testcall: &TestCall| {
+            let output = testcall
+                .call_args(["-c", "cut -d' ' -f5 /proc/$$/stat"])
+                .into_output()
+                .stdout;
+            String::from_utf8_lossy(&output)
+                .trim()
+                .parse::<libc::pid_t>()
+                .expect("pgid")
+        };
+
+        // SAFETY: no preconditions
+        assert_eq!(pgid(&testcall), unsafe { libc::getpgrp() });
+        testcall.process_group(true);
+        assert_ne!(pgid(&testcall), unsafe { libc::getpgrp() });
+    }
+
+    #[test]
+    fn drop_kills_group() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.process_group(true);
+
+        let mut child = testcall.spawn_args(["-c", "sleep 30 & echo $!; wait"]);
+        let helper: libc::pid_t = child
+            .read_until("\n", Duration::from_secs(5))
+            .trim()
+            .parse()
+            .expect("pid");
+        drop(child);
+        assert_gone(helper);
+    }
+
+    #[test]
+    fn wrapped() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.wrap_with("env", ["WRAPPED=yes"]);
+
+        testcall
+            .call_args(["-c", "echo $WRAPPED"])
+            .assert_success()
+            .assert_no_wrapper_error(99)
+            .assert_stdout_utf8("^yes\n$");
+    }
+
+    #[test]
+    fn resource_limits() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall
+            .limit_memory(256 << 20)
+            .limit_open_files(64)
+            .limit_cpu_time(1);
+
+        testcall
+            .call_args(["-c", "ulimit -v; ulimit -n; ulimit -t"])
+            .assert_success()
+            .assert_stdout_utf8("^262144\n64\n1\n$");
+    }
+
+    #[test]
+    fn cpu_time_exceeded() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.limit_cpu_time(1);
+
+        testcall
+            .call_args(["-c", "while :; do :; done"])
+            .assert_signaled(libc::SIGXCPU);
+    }
+
+    #[test]
+    fn first_output_within() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args(["-c", "echo ready; sleep 0.2"]);
+        let latency = child.assert_first_output_within(Duration::from_secs(5));
+        assert_eq!(child.first_output_latency(), Some(latency));
+        child.wait().assert_success();
+    }
+
+    #[test]
+    #[should_panic(expected = "no output within")]
+    fn first_output_too_late() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args(["-c", "sleep 1; echo late"]);
+        child.assert_first_output_within(Duration::from_millis(100));
+    }
+
+    #[test]
+    fn wait_timeout() {
+        let testcall = TestCall::external_command(Path::new("echo"));
+
+        testcall
+            .spawn_args(["in time"])
+            .wait_timeout(Duration::from_secs(5))
+            .assert_success();
+    }
+
+    #[test]
+    #[should_panic(expected = "timed out after 100ms")]
+    fn wait_timeout_stack_dump() {
+        let mut testcall = TestCall::external_command(Path::new("sleep"));
+        testcall.stack_dump_on_timeout(true);
+
+        testcall
+            .spawn_args(["5"])
+            .wait_timeout(Duration::from_millis(100));
+    }
+
+    #[test]
+    fn wait_timeout_inherited_output() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.timeout(Duration::from_millis(300));
+        let started = std::time::Instant::now();
+
+        // the grandchild leaves the process group and keeps stdout open
+        testcall
+            .call_args(["-c", "setsid sleep 3 & echo done"])
+            .assert_success()
+            .assert_stdout_utf8("done\n");
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            testcall.call_args(["-c", "setsid sleep 3 & sleep 3"]);
+        }));
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn output_bytes() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let child = testcall.spawn_args(["-c", "printf 12345; printf 123 >&2; sleep 5"]);
+        child.assert_first_output_within(Duration::from_secs(5));
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while child.stderr_bytes() < 3 && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(child.stdout_bytes(), 5);
+        assert_eq!(child.stderr_bytes(), 3);
+        child.kill();
+    }
+
+    #[test]
+    fn output_policies() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.max_output(4).output_policy(OutputPolicy::Truncate);
+        testcall
+            .call_args(["-c", "echo 123456789; echo still running"])
+            .assert_success()
+            .assert_stdout_utf8("^1234$");
+
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        testcall.output_policy(OutputPolicy::SpillToFile(tmpdir.path().to_path_buf()));
+        testcall
+            .call_args(["-c", "echo 123456789"])
+            .assert_stdout_utf8("^1234$");
+        assert_eq!(
+            std::fs::read_to_string(tmpdir.path().join("stdout")).expect("spilled"),
+            "123456789\n"
+        );
+    }
+
+    #[test]
+    fn max_output() {
+        let mut testcall = TestCall::external_command(Path::new("echo"));
+        testcall.max_output(6);
+
+        testcall.call_args(["12345"]).assert_success();
+    }
+
+    #[test]
+    #[should_panic(expected = "stdout output exceeded 1 MiB")]
+    fn max_output_exceeded() {
+        let mut testcall = TestCall::external_command(Path::new("yes"));
+        testcall.max_output(1 << 20);
+
+        testcall.call();
+    }
+
+    #[test]
+    #[should_panic(expected = "stdout output exceeded 4 KiB")]
+    fn max_output_kills_child() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall
+            .max_output(4 << 10)
+            .timeout(Duration::from_secs(10));
+
+        // ignores SIGPIPE, only being killed ends it before the timeout
+        testcall.call_args([
+            "-c",
+            "trap '' PIPE; while :; do echo spam; done 2>/dev/null",
+        ]);
+    }
+
+    #[test]
+    fn max_output_spawned() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.max_output(4 << 10);
+
+        let mut child = testcall.spawn_args([
+            "-c",
+            "trap '' PIPE; while :; do echo spam; done 2>/dev/null",
+        ]);
+        let started = Instant::now();
+        while child.is_running() {
+            assert!(started.elapsed() < Duration::from_secs(10), "child killed");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let failure = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(child)))
+            .expect_err("drop failed");
+        assert!(failure
+            .downcast_ref::<String>()
+            .is_some_and(|msg| msg.contains("stdout output exceeded")));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut testcall = TestCall::external_command(Path::new("sleep"));
+        testcall.timeout(Duration::from_secs(5));
+
+        testcall.call_args(["0"]).assert_success();
+    }
+
+    #[test]
+    #[should_panic(expected = "timed out after 100ms")]
+    fn timeout_expired() {
+        let mut testcall = TestCall::external_command(Path::new("sleep"));
+        testcall.timeout(Duration::from_millis(100));
+
+        // feeding stdin blocks since sleep never reads it
+        testcall.call_with_stdin_reader(["5"], std::io::repeat(b'x'));
+    }
+
+    #[test]
+    fn read_until() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let mut child = testcall.spawn_args(["-c", "echo ready; sleep 0.2; echo one two; sleep 5"]);
+        assert_eq!(
+            child.read_until("ready\n", Duration::from_secs(5)),
+            "ready\n"
+        );
+        assert_eq!(child.read_until("one", Duration::from_secs(5)), "one");
+        child.read_until("two", Duration::from_secs(5));
+        assert_eq!(child.read_available_stdout(), "\n");
+        assert_eq!(child.read_available_stdout(), "");
+        assert_eq!(child.read_available_stderr(), "");
+        child.kill();
+    }
+
+    #[test]
+    fn read_available_split_char() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let mut child = testcall.spawn_args(["-c", "printf 'caf\\303'; sleep 0.3; printf '\\251'"]);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        child
+            .capture
+            .stdout()
+            .wait_until(deadline, |data| data.len() == 4);
+        assert_eq!(child.read_available_stdout(), "caf");
+        child
+            .capture
+            .stdout()
+            .wait_until(deadline, |data| data.len() == 5);
+        assert_eq!(child.read_available_stdout(), "\u{e9}");
+        child.wait().assert_success();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "stdout did not match \"done\" within 100ms, unread stdout was:\nrest"
+    )]
+    fn read_until_timeout() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let mut child = testcall.spawn_args(["-c", "printf 'first rest'; sleep 5"]);
+        child.read_until("first ", Duration::from_secs(5));
+        child.read_until("done", Duration::from_millis(100));
+    }
+
+    #[test]
+    fn wait_for_stdout() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let mut child = testcall.spawn_args([
+            "-c",
+            "echo starting; sleep 0.2; echo 'listening on port 8080'; echo ready; sleep 5",
+        ]);
+        let captured = child.wait_for_stdout(r"port (?P<port>\d+)$", Duration::from_secs(5));
+        assert_eq!(&captured["port"], "8080");
+        assert_eq!(
+            &child.wait_for_stdout("^ready", Duration::from_secs(5))[0],
+            "ready"
+        );
+        assert_eq!(child.read_available_stdout(), "");
+        child.kill();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "no stdout line matched \"^ready\" before stdout was closed, unread stdout was:\nstarting\nfailed"
+    )]
+    fn wait_for_stdout_exited() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let mut child = testcall.spawn_args(["-c", "echo starting; echo failed; exit 1"]);
+        child.wait_for_stdout("^ready", Duration::from_secs(5));
+    }
+
+    #[test]
+    fn interactive_stdin() {
+        let testcall = TestCall::external_command(Path::new("sh"));
+
+        let mut child = testcall
+            .spawn_interactive(["-c", "while read cmd; do echo \"got $cmd\"; done; echo bye"]);
+        child.write_stdin("one\n");
+        assert_eq!(
+            child.read_until("got one\n", Duration::from_secs(5)),
+            "got one\n"
+        );
+        child.write_stdin(b"two\n").close_stdin();
+        child
+            .wait()
+            .assert_success()
+            .assert_stdout_utf8("^got one\ngot two\nbye\n$");
+    }
+
+    #[test]
+    #[should_panic(expected = "stdin is not open")]
+    fn write_stdin_closed() {
+        let testcall = TestCall::external_command(Path::new("cat"));
+
+        let mut child = testcall.spawn_interactive(NO_ARGS);
+        child.close_stdin().write_stdin("late");
+    }
+
+    #[test]
+    fn base_args() {
+        let mut testcall = TestCall::external_command(Path::new("echo"));
+        testcall.base_args(["sub", "--flag"]).base_args(["more"]);
+
+        testcall
+            .call_args(["arg"])
+            .assert_stdout_utf8("^sub --flag more arg\n$");
+        testcall.call().assert_stdout_utf8("^sub --flag more\n$");
+    }
+
+    #[test]
+    fn envs() {
+        let mut testcall = TestCall::external_command(Path::new("/bin/sh"));
+        testcall
+            .env("FIRST", "1")
+            .envs([("SECOND", "2"), ("THIRD", "3")])
+            .env_remove("THIRD");
+
+        testcall
+            .call_args(["-c", "echo $FIRST$SECOND$THIRD"])
+            .assert_stdout_utf8("^12\n$");
+        testcall
+            .call_args_envs(["-c", "echo $FIRST$SECOND"], [("SECOND", "two")])
+            .assert_stdout_utf8("^1two\n$");
+
+        // set by cargo for the test process, no need to modify the environment
+        let inherited = std::env::var("CARGO_MANIFEST_DIR").expect("run by cargo");
+        testcall
+            .call_args(["-c", "echo $CARGO_MANIFEST_DIR"])
+            .assert_stdout_eq(&format!("{}\n", inherited));
+        testcall
+            .env_clear(true)
+            .call_args(["-c", "echo $CARGO_MANIFEST_DIR$FIRST"])
+            .assert_stdout_utf8("^1\n$");
+    }
+
+    #[test]
+    fn coverage_profiles() {
+        let lookup = |profile: &'static str| {
+            move |key: &str| match key {
+                "LLVM_PROFILE_FILE" => Some(OsString::from(profile)),
+                "CARGO_LLVM_COV" => Some(OsString::from("1")),
+                _ => None,
+            }
+        };
+        assert_eq!(
+            coverage_envs(lookup("/target/cov.profraw")),
+            [
+                (
+                    "LLVM_PROFILE_FILE",
+                    OsString::from("/target/cov-%p.profraw")
+                ),
+                ("CARGO_LLVM_COV", OsString::from("1"))
+            ]
+        );
+        assert_eq!(
+            coverage_envs(lookup("/target/cov-%m.profraw"))[0].1,
+            "/target/cov-%m.profraw"
+        );
+        assert_eq!(coverage_envs(lookup("default"))[0].1, "default-%p");
+        assert!(coverage_envs(|_| None).is_empty());
+    }
+
+    #[test]
+    fn env_policies() {
+        // set by cargo for the test process, no need to modify the environment
+        let inherited = std::env::var("CARGO_MANIFEST_DIR").expect("run by cargo");
+        let mut testcall = TestCall::external_command(Path::new("/bin/sh"));
+
+        testcall
+            .inherit_env()
+            .call_args_envs(["-c", "echo $CARGO_MANIFEST_DIR $CALL"], [("CALL", "call")])
+            .assert_stdout_eq(&format!("{} call\n", inherited));
+        testcall
+            .env_clear(true)
+            .call_args(["-c", "echo $CARGO_MANIFEST_DIR"])
+            .assert_stdout_eq("\n");
+        testcall
+            .env_clear(false)
+            .call_args_envs(["-c", "echo $CARGO_MANIFEST_DIR $CALL"], [("CALL", "call")])
+            .assert_stdout_eq(&format!("{} call\n", inherited));
+        testcall
+            .env_allowlist(["CARGO_MANIFEST_DIR", "TESTCALL_TEST_UNSET"])
+            .call_args([
+                "-c",
+                "echo $CARGO_MANIFEST_DIR ${TESTCALL_TEST_UNSET-unset} ${CARGO_PKG_NAME-unset}",
+            ])
+            .assert_stdout_eq(&format!("{} unset unset\n", inherited));
+    }
+
+    #[test]
+    fn stdin() {
+        let testcall = TestCall::external_command(Path::new("tr"));
+
+        testcall
+            .call_with_stdin(["a-z", "A-Z"], b"hello\n")
+            .assert_success()
+            .assert_stdout_utf8("^HELLO\n$");
+    }
+
+    #[test]
+    fn stdin_envs() {
+        let testcall = TestCall::external_command(Path::new("/bin/sh"));
+
+        testcall
+            .call_with_stdin_envs(
+                ["-c", "read line; echo $line $GREETING"],
+                [("GREETING", "world")],
+                b"hello\n",
+            )
+            .assert_success()
+            .assert_stdout_utf8("^hello world\n$");
+    }
+
+    #[test]
+    fn stdin_file() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        std::fs::write(tmpdir.path().join("input"), "one\ntwo\n").expect("input written");
+        let mut testcall = TestCall::external_command(Path::new("wc"));
+
+        testcall
+            .call_stdin_file(["-l"], tmpdir.path().join("input"))
+            .assert_success()
+            .assert_stdout_utf8("^ *2\n$");
+        testcall
+            .current_dir(&tmpdir)
+            .call_stdin_file(["-c"], "input")
+            .assert_stdout_utf8("^ *8\n$");
+    }
+
+    #[test]
+    #[should_panic(expected = "opening stdin file")]
+    fn stdin_file_missing() {
+        TestCall::external_command(Path::new("cat")).call_stdin_file(NO_ARGS, "/nonexistent");
+    }
+
+    #[test]
+    fn stdin_reader() {
+        use std::io::Read;
+        let testcall = TestCall::external_command(Path::new("wc"));
+
+        testcall
+            .call_with_stdin_reader(["-c"], std::io::repeat(b'x').take(64 << 20))
+            .assert_success()
+            .assert_stdout_utf8("^ *67108864\n$");
+    }
+
+    #[test]
+    fn stdin_reader_early_exit() {
+        use std::io::Read;
+        let testcall = TestCall::external_command(Path::new("head"));
+
+        testcall
+            .call_with_stdin_reader(["-c", "3"], std::io::repeat(b'y').take(16 << 20))
+            .assert_success()
+            .assert_stdout_utf8("^yyy$");
+    }
+
+    #[test]
+    fn config_file() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("cat"));
+        testcall.config_file(&tmpdir, "cat.conf", "key = value\n", ConfigVia::Flag("--"));
+
+        testcall
+            .call()
+            .assert_success()
+            .assert_stdout_utf8("^key = value\n$");
+    }
+
+    #[test]
+    fn tee_output() {
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.tee_output(true);
+
+        testcall
+            .call_args(["-c", "echo teed stdout; echo teed stderr >&2"])
+            .assert_success()
+            .assert_stdout_utf8("^teed stdout\n$")
+            .assert_stderr_utf8("^teed stderr\n$");
+    }
 }
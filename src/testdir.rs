@@ -0,0 +1,1557 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use testpath::{Fixtures, PathAssertions, SubPath, TestPath};
+
+use crate::failure::{Failure, TestError};
+use crate::normalize::slash_path;
+use crate::testcall::env_flag;
+use crate::{CallOutput, Captured};
+
+#[cfg(unix)]
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+
+/// How often the polling assertions check the filesystem.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Assertions on the content of a test directory which augment the 'testpath' facilities.
+pub trait DirAssertions: TestPath {
+    /// Asserts that 'path' within the test directory comes into existence before 'timeout'
+    /// expires. Polls the filesystem, meant for files created asynchronously by spawned
+    /// processes (pidfiles, sockets, logs).
+    #[track_caller]
+    fn assert_exists_within<P>(&self, path: P, timeout: Duration) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        if !poll_until(timeout, || path.exists()) {
+            Failure::new(
+                "assert_exists_within",
+                format!(
+                    "path did not appear within {:?}: {:?}",
+                    timeout,
+                    slash_path(&*path)
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Asserts that 'path' within the test directory vanishes before 'timeout' expires.
+    #[track_caller]
+    fn assert_gone_within<P>(&self, path: P, timeout: Duration) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        if !poll_until(timeout, || path.symlink_metadata().is_err()) {
+            Failure::new(
+                "assert_gone_within",
+                format!(
+                    "path did not vanish within {:?}: {:?}",
+                    timeout,
+                    slash_path(&*path)
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Like 'assert_tree_equal()' but returns the failure instead of panicking.
+    fn try_tree_equal<P, E>(&self, path: P, expected: E) -> Result<&Self, TestError>
+    where
+        P: AsRef<Path>,
+        E: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        let expected = sub_path(self, expected);
+        let differences = tree_differences(&expected, &path);
+        if !differences.is_empty() {
+            return Err(Failure::new(
+                "assert_tree_equal",
+                format!(
+                    "{:?} differs from {:?} (-missing +extra ~differing)",
+                    slash_path(&*path),
+                    slash_path(&*expected)
+                ),
+            )
+            .with_diff(render_tree_differences(&differences))
+            .into());
+        }
+        Ok(self)
+    }
+
+    /// Asserts that the trees at 'path' and 'expected' within the test directory are equal.
+    /// Names, file types, file contents and symlink targets are compared recursively,
+    /// symlinks are not followed. On failure all missing, extra and differing entries are
+    /// listed.
+    #[track_caller]
+    fn assert_tree_equal<P, E>(&self, path: P, expected: E) -> &Self
+    where
+        P: AsRef<Path>,
+        E: AsRef<Path>,
+        Self: Sized,
+    {
+        if let Err(err) = self.try_tree_equal(path, expected) {
+            err.fail();
+        }
+        self
+    }
+
+    /// Asserts that the permission bits (including setuid, setgid and sticky) of 'path'
+    /// within the test directory are exactly 'mode', like '0o644'. Symlinks are followed.
+    #[cfg(unix)]
+    #[track_caller]
+    fn assert_mode<P>(&self, path: P, mode: u32) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        let actual = file_mode("assert_mode", &path);
+        if actual != mode {
+            Failure::new(
+                "assert_mode",
+                format!(
+                    "{:?} has mode {:04o}, expected {:04o}",
+                    slash_path(&*path),
+                    actual,
+                    mode
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Asserts that 'path' within the test directory is a file with at least one executable
+    /// bit set.
+    #[cfg(unix)]
+    #[track_caller]
+    fn assert_executable<P>(&self, path: P) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        if !path.is_file() || file_mode("assert_executable", &path) & 0o111 == 0 {
+            Failure::new(
+                "assert_executable",
+                format!("{:?} is not an executable file", slash_path(&*path)),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Asserts that 'path' within the test directory is a named pipe.
+    #[cfg(unix)]
+    #[track_caller]
+    fn assert_is_fifo<P>(&self, path: P) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        use std::os::unix::fs::FileTypeExt;
+        let path = sub_path(self, path);
+        if !path
+            .symlink_metadata()
+            .map(|metadata| metadata.file_type().is_fifo())
+            .unwrap_or(false)
+        {
+            Failure::new(
+                "assert_is_fifo",
+                format!("{:?} is not a fifo", slash_path(&*path)),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Asserts that 'path' was modified after 'other', both within the test directory.
+    #[track_caller]
+    fn assert_newer_than<P, O>(&self, path: P, other: O) -> &Self
+    where
+        P: AsRef<Path>,
+        O: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        let other = sub_path(self, other);
+        let (mtime, other_mtime) = (
+            mtime("assert_newer_than", &path),
+            mtime("assert_newer_than", &other),
+        );
+        if mtime <= other_mtime {
+            Failure::new(
+                "assert_newer_than",
+                format!(
+                    "{:?} (modified {:?}) is not newer than {:?} (modified {:?})",
+                    slash_path(&*path),
+                    mtime,
+                    slash_path(&*other),
+                    other_mtime
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Asserts that the modification time of 'path' within the test directory lies in
+    /// 'range', like 'start..SystemTime::now()'.
+    #[track_caller]
+    fn assert_mtime_within<P, R>(&self, path: P, range: R) -> &Self
+    where
+        P: AsRef<Path>,
+        R: RangeBounds<SystemTime> + Debug,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        let mtime = mtime("assert_mtime_within", &path);
+        if !range.contains(&mtime) {
+            Failure::new(
+                "assert_mtime_within",
+                format!(
+                    "{:?} was modified at {:?}, expected within {:?}",
+                    slash_path(&*path),
+                    mtime,
+                    range
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Asserts that the SHA-256 digest of the file 'path' within the test directory is 'hex'
+    /// (case insensitive). The file is streamed, large outputs are never loaded as whole.
+    #[track_caller]
+    fn assert_sha256<P>(&self, path: P, hex: &str) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        use sha2::Digest;
+        let path = sub_path(self, path);
+        let mut hasher = sha2::Sha256::new();
+        hash_file("assert_sha256", &path, &mut hasher);
+        let digest = to_hex(&hasher.finalize());
+        if !digest.eq_ignore_ascii_case(hex) {
+            Failure::new(
+                "assert_sha256",
+                format!(
+                    "{:?} has sha256 {}, expected {}",
+                    slash_path(&*path),
+                    digest,
+                    hex
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Asserts that the BLAKE3 digest of the file 'path' within the test directory is 'hex'
+    /// (case insensitive).
+    #[cfg(feature = "blake3")]
+    #[track_caller]
+    fn assert_blake3<P>(&self, path: P, hex: &str) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        let mut hasher = blake3::Hasher::new();
+        hash_file("assert_blake3", &path, &mut hasher);
+        let digest = hasher.finalize().to_hex();
+        if !digest.eq_ignore_ascii_case(hex) {
+            Failure::new(
+                "assert_blake3",
+                format!(
+                    "{:?} has blake3 {}, expected {}",
+                    slash_path(&*path),
+                    digest,
+                    hex
+                ),
+            )
+            .fail();
+        }
+        self
+    }
+
+    /// Records the state of the test directory (paths, types, sizes and digests) to assert
+    /// later which entries a call added, modified or removed.
+    #[track_caller]
+    fn snapshot(&self) -> DirSnapshot
+    where
+        Self: Sized,
+    {
+        let root = self.path().to_path_buf();
+        let mut entries = tree_entries(&root);
+        entries.remove(Path::new(""));
+        DirSnapshot { root, entries }
+    }
+
+    /// Returns the content of the existing file 'path' within the test directory, lossy
+    /// converted to utf8.
+    #[track_caller]
+    fn read_utf8<P>(&self, path: P) -> String
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        String::from_utf8_lossy(&read_file("read_utf8", &self.sub_path_exists(path))).into_owned()
+    }
+
+    /// Returns the content of the existing file 'path' within the test directory.
+    #[track_caller]
+    fn read_bytes<P>(&self, path: P) -> Vec<u8>
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        read_file("read_bytes", &self.sub_path_exists(path))
+    }
+
+    /// Like 'assert_file_utf8()' but returns the failure instead of panicking.
+    fn try_file_utf8<P>(&self, path: P, regex: &str) -> Result<&Self, TestError>
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        let (ok, utf8) =
+            crate::regex::regex_match_utf8(&read_file("assert_file_utf8", &path), regex);
+        if !ok {
+            return Err(Failure::new(
+                "assert_file_utf8",
+                format!("{:?} does not match", slash_path(&*path)),
+            )
+            .with_expected(regex)
+            .with_actual("file", &utf8)
+            .into());
+        }
+        Ok(self)
+    }
+
+    /// Applies a regex match check to the content of the file 'path' within the test
+    /// directory, will panic when the match failed.
+    #[track_caller]
+    fn assert_file_utf8<P>(&self, path: P, regex: &str) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        if let Err(err) = self.try_file_utf8(path, regex) {
+            err.fail();
+        }
+        self
+    }
+
+    /// Like 'assert_file_bytes()' but returns the failure instead of panicking.
+    fn try_file_bytes<P>(&self, path: P, regex: &str) -> Result<&Self, TestError>
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        let (ok, bytes) =
+            crate::regex::regex_match_bytes(&read_file("assert_file_bytes", &path), regex);
+        if !ok {
+            return Err(Failure::new(
+                "assert_file_bytes",
+                format!("{:?} does not match", slash_path(&*path)),
+            )
+            .with_expected(regex)
+            .with_actual("file", &bytes)
+            .into());
+        }
+        Ok(self)
+    }
+
+    /// Applies a regex match check to the content of the file 'path' within the test
+    /// directory, will panic when the match failed.
+    #[track_caller]
+    fn assert_file_bytes<P>(&self, path: P, regex: &str) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        if let Err(err) = self.try_file_bytes(path, regex) {
+            err.fail();
+        }
+        self
+    }
+
+    /// Applies a regex on the content of the file 'path' within the test directory, returns
+    /// the captures. The content is lossy converted to utf8.
+    #[track_caller]
+    fn file_captures_utf8<P>(&self, path: P, regex: &str) -> Captured
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        crate::regex::captures_utf8(
+            &read_file("file_captures_utf8", &sub_path(self, path)),
+            regex,
+        )
+    }
+}
+
+impl<T: TestPath> DirAssertions for T {}
+
+/// 'TestPath::sub_path' with 'path' re-joined from its components first, on windows '/' and
+/// '\' both end up as the native separator.
+#[track_caller]
+fn sub_path<'a, T: TestPath>(dir: &'a T, path: impl AsRef<Path>) -> SubPath<'a> {
+    dir.sub_path(path.as_ref().components().collect::<PathBuf>())
+}
+
+/// Feeds the content of the file at 'path' into 'hasher', fails as 'kind' when it can not be
+/// read.
+#[track_caller]
+fn hash_file(kind: &'static str, path: &Path, hasher: &mut impl std::io::Write) {
+    if let Err(err) = fs::File::open(path).and_then(|mut file| std::io::copy(&mut file, hasher)) {
+        Failure::new(
+            kind,
+            format!("can not read {:?}: {}", slash_path(path), err),
+        )
+        .fail();
+    }
+}
+
+/// Lowercase hex rendering of 'bytes'.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The modification time of 'path', fails as 'kind' when it can not be queried.
+#[track_caller]
+fn mtime(kind: &'static str, path: &Path) -> SystemTime {
+    match path.metadata().and_then(|metadata| metadata.modified()) {
+        Ok(mtime) => mtime,
+        Err(err) => Failure::new(
+            kind,
+            format!(
+                "can not get modification time of {:?}: {}",
+                slash_path(path),
+                err
+            ),
+        )
+        .fail(),
+    }
+}
+
+/// The permission bits of 'path', fails as 'kind' when it can not be stat'ed.
+#[cfg(unix)]
+#[track_caller]
+fn file_mode(kind: &'static str, path: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    match path.metadata() {
+        Ok(metadata) => metadata.permissions().mode() & 0o7777,
+        Err(err) => Failure::new(
+            kind,
+            format!("can not stat {:?}: {}", slash_path(path), err),
+        )
+        .fail(),
+    }
+}
+
+/// Reads the whole file at 'path', fails as 'kind' when it can not be read.
+#[track_caller]
+fn read_file(kind: &'static str, path: &Path) -> Vec<u8> {
+    match fs::read(path) {
+        Ok(content) => content,
+        Err(err) => Failure::new(
+            kind,
+            format!("can not read {:?}: {}", slash_path(path), err),
+        )
+        .fail(),
+    }
+}
+
+/// What is found at a path, as far as tree comparison is concerned. Files are represented by
+/// their length and SHA-256 digest.
+#[derive(PartialEq)]
+enum Entry {
+    Dir,
+    File(u64, Vec<u8>),
+    Symlink(PathBuf),
+    Other,
+}
+
+impl Entry {
+    fn read(path: &Path) -> Option<Entry> {
+        let file_type = path.symlink_metadata().ok()?.file_type();
+        Some(if file_type.is_dir() {
+            Entry::Dir
+        } else if file_type.is_file() {
+            use sha2::Digest;
+            let mut hasher = sha2::Sha256::new();
+            hash_file("snapshot", path, &mut hasher);
+            let len = path.symlink_metadata().ok()?.len();
+            Entry::File(len, hasher.finalize().to_vec())
+        } else if file_type.is_symlink() {
+            Entry::Symlink(fs::read_link(path).expect("readable symlink"))
+        } else {
+            Entry::Other
+        })
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Entry::Dir => "directory",
+            Entry::File(..) => "file",
+            Entry::Symlink(_) => "symlink",
+            Entry::Other => "special file",
+        }
+    }
+}
+
+/// Every entry below 'root' (including itself as the empty path) by its relative path.
+fn tree_entries(root: &Path) -> BTreeMap<PathBuf, Entry> {
+    let mut entries = BTreeMap::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let path = root.join(&relative);
+        let entry = match Entry::read(&path) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if entry == Entry::Dir {
+            for child in fs::read_dir(&path).expect("readable dir") {
+                pending.push(relative.join(child.expect("dir entry").file_name()));
+            }
+        }
+        entries.insert(relative, entry);
+    }
+    entries
+}
+
+/// The recorded state of a test directory, created by 'DirAssertions::snapshot()'.
+pub struct DirSnapshot {
+    root: PathBuf,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl DirSnapshot {
+    /// Asserts that since the snapshot was taken exactly the entries in 'added' were created,
+    /// those in 'modified' changed their content or type and those in 'removed' vanished.
+    /// Paths are relative to the test directory, directories count as entries too.
+    #[track_caller]
+    pub fn assert_changes(&self, added: &[&str], modified: &[&str], removed: &[&str]) -> &Self {
+        let mut now = tree_entries(&self.root);
+        now.remove(Path::new(""));
+
+        let (mut actual_added, mut actual_modified, mut actual_removed) =
+            (Vec::new(), Vec::new(), Vec::new());
+        for (path, before) in &self.entries {
+            match now.remove(path) {
+                None => actual_removed.push(path.clone()),
+                Some(after) if after != *before => actual_modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        actual_added.extend(now.into_keys());
+
+        let sorted = |paths: &[&str]| {
+            let mut paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+            paths.sort();
+            paths
+        };
+        let (added, modified, removed) = (sorted(added), sorted(modified), sorted(removed));
+        actual_modified.sort();
+        actual_removed.sort();
+        if (&added, &modified, &removed) != (&actual_added, &actual_modified, &actual_removed) {
+            // rendered with '/' separators to match the expectation on every platform
+            let slashed = |paths: &[PathBuf]| paths.iter().map(slash_path).collect::<Vec<_>>();
+            let render = |added: &[PathBuf], modified: &[PathBuf], removed: &[PathBuf]| {
+                format!(
+                    "added: {:?}\nmodified: {:?}\nremoved: {:?}",
+                    slashed(added),
+                    slashed(modified),
+                    slashed(removed)
+                )
+            };
+            Failure::new(
+                "assert_changes",
+                format!("unexpected changes in {:?}", self.root),
+            )
+            .with_expected(&render(&added, &modified, &removed))
+            .with_actual(
+                "changes",
+                &render(&actual_added, &actual_modified, &actual_removed),
+            )
+            .fail();
+        }
+        self
+    }
+}
+
+/// A difference between two trees, '-' missing, '+' extra or '~' differing, with the
+/// relative path and a description.
+type TreeDifference = (char, PathBuf, String);
+
+/// Compares the trees at 'expected' and 'actual', sorted by path.
+fn tree_differences(expected: &Path, actual: &Path) -> Vec<TreeDifference> {
+    let mut expected = tree_entries(expected);
+    let actual = tree_entries(actual);
+    let mut differences = Vec::new();
+    for (path, actual) in actual {
+        match expected.remove(&path) {
+            None => differences.push(('+', path, format!("extra {}", actual.kind()))),
+            Some(expected) if expected == actual => {}
+            Some(expected) => {
+                let description = match (&expected, &actual) {
+                    (Entry::File(expected, _), Entry::File(actual, _)) => format!(
+                        "content differs, {} bytes expected, {} bytes found",
+                        expected, actual
+                    ),
+                    (Entry::Symlink(expected), Entry::Symlink(actual)) => {
+                        format!("symlink to {:?}, expected {:?}", actual, expected)
+                    }
+                    _ => format!("{}, expected {}", actual.kind(), expected.kind()),
+                };
+                differences.push(('~', path, description));
+            }
+        }
+    }
+    differences.extend(
+        expected
+            .into_iter()
+            .map(|(path, expected)| ('-', path, format!("missing {}", expected.kind()))),
+    );
+    differences.sort_by(|a, b| a.1.cmp(&b.1));
+    differences
+}
+
+/// Renders 'differences' as indented tree, directories leading to a difference are shown
+/// for context.
+fn render_tree_differences(differences: &[TreeDifference]) -> String {
+    let mut listing = String::new();
+    let mut shown: Vec<PathBuf> = Vec::new();
+    for (marker, path, description) in differences {
+        let components: Vec<_> = path.components().collect();
+        for depth in 1..components.len() {
+            let parent: PathBuf = components[..depth].iter().collect();
+            if !shown.contains(&parent) {
+                listing.push_str(&format!(
+                    "  {}{}/\n",
+                    "  ".repeat(depth - 1),
+                    components[depth - 1].as_os_str().to_string_lossy()
+                ));
+                shown.push(parent);
+            }
+        }
+        let name = components
+            .last()
+            .map(|name| name.as_os_str().to_string_lossy())
+            .unwrap_or_else(|| ".".into());
+        listing.push_str(&format!(
+            "{} {}{}  ({})\n",
+            marker,
+            "  ".repeat(components.len().saturating_sub(1)),
+            name,
+            description
+        ));
+        shown.push(path.clone());
+    }
+    listing
+}
+
+/// Names that are legal on unix filesystems but tend to break naive directory walkers, shell
+/// scripts and output parsers.
+#[cfg(unix)]
+const PATHOLOGICAL_NAMES: &[&[u8]] = &[
+    b"new\nline",
+    b"carriage\rreturn",
+    b"tab\tseparated",
+    b"escape\x1b[31mred\x1b[0m",
+    b"bell\x07",
+    b" leading space",
+    b"trailing space ",
+    b"-dash-leading",
+    b"--double-dash",
+    b"*glob?[chars]",
+    b"quote'single",
+    b"quote\"double",
+    b"back\\slash",
+    b"$dollar",
+    b"emoji\xf0\x9f\x98\x80",
+    b"nfd-e\xcc\x81",
+    b"invalid-utf8-\xff\xfe",
+];
+
+/// Generators for fixtures which stress programs that walk directories. All generators are
+/// deterministic, calling them with the same parameters produces the same layout.
+pub trait DirFixtures: Fixtures {
+    /// Creates all 'entries' in one go, like '&[("etc/app.conf", "verbose = true\n"), ("var/log/",
+    /// "")]'. Each entry is a path and the content of the file, paths ending in a separator
+    /// create (possibly empty) directories and their content is ignored. Leading directories
+    /// are created as needed, files must not exist already.
+    #[track_caller]
+    fn create_tree<I, P, C>(&self, entries: I) -> &Self
+    where
+        I: IntoIterator<Item = (P, C)>,
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+        Self: Sized,
+    {
+        for (path, content) in entries {
+            let is_dir = path
+                .as_ref()
+                .to_string_lossy()
+                .ends_with(std::path::is_separator);
+            if is_dir {
+                fs::create_dir_all(&*sub_path(self, path)).expect("create directory");
+            } else {
+                self.create_file(path, content.as_ref());
+            }
+        }
+        self
+    }
+
+    /// Creates the named pipe 'path' (which must not exist) with mode 0o600. Leading
+    /// directories are created.
+    #[cfg(unix)]
+    #[track_caller]
+    fn create_fifo<P>(&self, path: P) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = self.sub_path_available(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("create directory");
+        }
+        let cpath = std::ffi::CString::new(path.as_os_str().as_bytes()).expect("path without nul");
+        // SAFETY: 'cpath' is a valid nul terminated string
+        let ret = unsafe { libc::mkfifo(cpath.as_ptr(), 0o600) };
+        assert!(
+            ret == 0,
+            "creating fifo {:?} failed: {}",
+            *path,
+            std::io::Error::last_os_error()
+        );
+        self
+    }
+
+    /// Creates the file 'path' (which must not exist) with 'size' pseudo random bytes. The
+    /// same 'seed' always produces the same content, large inputs can be generated instead
+    /// of being checked in.
+    #[track_caller]
+    fn create_file_random<P>(&self, path: P, size: usize, seed: u64) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let mut state = seed;
+        write_generated(&self.sub_path_available(path), size, |chunk| {
+            for bytes in chunk.chunks_mut(8) {
+                let random = splitmix64(&mut state).to_le_bytes();
+                bytes.copy_from_slice(&random[..bytes.len()]);
+            }
+        });
+        self
+    }
+
+    /// Creates the file 'path' (which must not exist) with 'size' times 'byte'.
+    #[track_caller]
+    fn create_file_filled<P>(&self, path: P, size: usize, byte: u8) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        write_generated(&self.sub_path_available(path), size, |chunk| {
+            chunk.fill(byte)
+        });
+        self
+    }
+
+    /// Unpacks the '.tar', '.tar.gz', '.tgz' or '.zip' file 'archive' into the test directory,
+    /// for large data sets checked in as single compressed file.
+    #[cfg(feature = "archives")]
+    #[track_caller]
+    fn install_archive<A>(&self, archive: A) -> &Self
+    where
+        A: AsRef<Path>,
+        Self: Sized,
+    {
+        crate::archive::unpack(archive.as_ref(), self.path());
+        self
+    }
+
+    /// Sets the permission bits of 'path' to 'mode', like '0o755'. Symlinks are followed.
+    #[cfg(unix)]
+    #[track_caller]
+    fn chmod<P>(&self, path: P, mode: u32) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let path = sub_path(self, path);
+        fs::set_permissions(&*path, fs::Permissions::from_mode(mode)).expect("permissions set");
+        self
+    }
+
+    /// Sets the modification time of the file or directory 'path', to test programs which
+    /// compare file ages deterministically.
+    #[track_caller]
+    fn set_mtime<P>(&self, path: P, time: SystemTime) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = sub_path(self, path);
+        #[cfg(unix)]
+        let file = fs::File::open(&*path);
+        #[cfg(not(unix))]
+        let file = fs::OpenOptions::new().write(true).open(&*path);
+        file.and_then(|file| file.set_modified(time))
+            .expect("modification time set");
+        self
+    }
+
+    /// Creates the directory 'dir' (which must not exist) and populates it with a tree that
+    /// is 'depth' levels deep. Every directory contains 'fanout' files 'fN' of 'file_size'
+    /// bytes and, unless the last level is reached, 'fanout' subdirectories 'dN'. Names are
+    /// kept short to allow deep trees before hitting path length limits. The work is
+    /// distributed over all available cpus.
+    #[track_caller]
+    fn create_deep_tree<P>(&self, dir: P, depth: usize, fanout: usize, file_size: usize) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        self.create_dir(&dir);
+        let root = sub_path(self, dir).to_path_buf();
+        let content = tree_file_content(file_size);
+
+        let subdirs = populate_dir(&root, depth, fanout, &content);
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = (subdirs.len() + workers - 1) / workers.max(1);
+
+        thread::scope(|scope| {
+            for chunk in subdirs.chunks(chunk_size.max(1)) {
+                let content = &content;
+                scope.spawn(move || {
+                    for subdir in chunk {
+                        populate_tree(subdir, depth - 1, fanout, content);
+                    }
+                });
+            }
+        });
+        self
+    }
+
+    /// Creates the directory 'dir' (which must not exist) populated with 'count' symlinks
+    /// named 'link-00000' and so on, all pointing to 'target'. 'target' is used verbatim as
+    /// link content and does not need to exist.
+    #[cfg(unix)]
+    #[track_caller]
+    fn create_symlink_farm<P, T>(&self, dir: P, target: T, count: usize) -> &Self
+    where
+        P: AsRef<Path>,
+        T: AsRef<Path>,
+        Self: Sized,
+    {
+        self.create_dir(&dir);
+        let dir = sub_path(self, dir);
+        for n in 0..count {
+            symlink(target.as_ref(), dir.join(format!("link-{:05}", n))).expect("create symlink");
+        }
+        self
+    }
+
+    /// Creates a symlink 'name' that points to a nonexistent target.
+    #[cfg(unix)]
+    #[track_caller]
+    fn create_dangling_symlink<P>(&self, name: P) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        let path = self.sub_path_available(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("create directory");
+        }
+        let mut target = path.file_name().expect("symlink name").to_os_string();
+        target.push(".dangling-target");
+        symlink(target, &*path).expect("create symlink");
+        self
+    }
+
+    /// Creates the directory 'dir' (which must not exist) containing a cycle of 'length'
+    /// symlinks: 'cycle-0' -> 'cycle-1' -> ... -> 'cycle-0'. Additionally 'dir/self' is a
+    /// link pointing to its own directory, forming a loop for walkers that follow links.
+    #[cfg(unix)]
+    #[track_caller]
+    fn create_symlink_cycle<P>(&self, dir: P, length: usize) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        assert!(length > 0, "cycle length must be positive");
+        self.create_dir(&dir);
+        let dir = sub_path(self, dir);
+        for n in 0..length {
+            symlink(
+                format!("cycle-{}", (n + 1) % length),
+                dir.join(format!("cycle-{}", n)),
+            )
+            .expect("create symlink");
+        }
+        symlink(".", dir.join("self")).expect("create symlink");
+        self
+    }
+
+    /// Creates the directory 'dir' (which must not exist) containing one small file for each
+    /// name with control characters, newlines, shell metacharacters, leading dashes, non
+    /// normalized unicode and invalid utf8. Each file contains its index in
+    /// 'PATHOLOGICAL_NAMES' order.
+    #[cfg(unix)]
+    #[track_caller]
+    fn create_pathological_names<P>(&self, dir: P) -> &Self
+    where
+        P: AsRef<Path>,
+        Self: Sized,
+    {
+        self.create_dir(&dir);
+        let dir = sub_path(self, dir);
+        for (n, name) in PATHOLOGICAL_NAMES.iter().enumerate() {
+            fs::write(dir.join(OsStr::from_bytes(name)), format!("{}\n", n)).expect("create file");
+        }
+        self
+    }
+}
+
+impl<T: Fixtures> DirFixtures for T {}
+
+/// A temporary test directory which is removed when dropped, unless the test is failing
+/// and keeping was requested with 'keep_on_failure()' or by setting 'TESTCALL_KEEP_TESTDIR'
+/// to anything but "0". The retained path is printed to stderr for post-mortem inspection.
+pub struct TestDir {
+    dir: Option<tempfile::TempDir>,
+    keep_on_failure: bool,
+}
+
+impl TestDir {
+    /// Creates a new, empty temporary directory.
+    #[track_caller]
+    pub fn new() -> Self {
+        TestDir {
+            dir: Some(tempfile::TempDir::new().expect("TempDir created")),
+            keep_on_failure: false,
+        }
+    }
+
+    /// Retains the directory when the current thread panics.
+    pub fn keep_on_failure(mut self, keep: bool) -> Self {
+        self.keep_on_failure = keep;
+        self
+    }
+}
+
+impl Default for TestDir {
+    fn default() -> Self {
+        TestDir::new()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        if thread::panicking() && (self.keep_on_failure || env_flag("TESTCALL_KEEP_TESTDIR")) {
+            if let Some(dir) = self.dir.take() {
+                eprintln!("keeping test directory of failed test: {:?}", dir.keep());
+            }
+        }
+    }
+}
+
+impl TestPath for TestDir {
+    fn path(&self) -> &Path {
+        self.dir.as_ref().expect("directory").path()
+    }
+}
+
+impl Fixtures for TestDir {
+    fn delete<N>(&self, name: &N) -> &Self
+    where
+        N: AsRef<Path> + ?Sized,
+    {
+        let path = sub_path(self, name);
+        if path.is_dir() {
+            fs::remove_dir_all(&*path).expect("directory deleted");
+        } else {
+            fs::remove_file(&*path).expect("file deleted");
+        }
+        self
+    }
+}
+
+impl PathAssertions for TestDir {}
+
+/// A test directory backend with lifecycle hooks, for directories which need more than a
+/// plain path like network mounts, FUSE mocks or overlay filesystems. Attach it with
+/// 'TestCall::dir_backend()', calls then run within 'path()'. Implementing 'Fixtures' and
+/// 'PathAssertions' (all methods have defaults) makes all fixture and assertion methods
+/// available on the backend, 'DirAssertions' and 'DirFixtures' come for free then.
+pub trait DirBackend: TestPath + Send + Sync {
+    /// Called when the backend is attached to a TestCall.
+    fn setup(&self) {}
+
+    /// Called with the fully configured command right before each call or spawn which
+    /// executes a process, pairs with 'after_call()'.
+    fn before_call(&self, _command: &mut Command) {}
+
+    /// Called with the output after each call finished or spawned child was waited for.
+    fn after_call(&self, _output: &CallOutput) {}
+
+    /// Called when the backend is detached with 'TestCall::detach_dir_backend()' or the
+    /// TestCall is dropped, after the last clone and spawned child using it are gone.
+    fn cleanup(&self) {}
+}
+
+/// Creates the file at 'path' with 'size' bytes, 'generate' fills successive chunks. Leading
+/// directories are created.
+fn write_generated<F: FnMut(&mut [u8])>(path: &Path, size: usize, mut generate: F) {
+    use std::io::Write;
+    const CHUNK: usize = 64 * 1024;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("create directory");
+    }
+    let mut file = fs::File::create(path).expect("create file");
+    let mut chunk = vec![0; CHUNK];
+    let mut left = size;
+    while left > 0 {
+        let chunk = &mut chunk[..left.min(CHUNK)];
+        generate(chunk);
+        file.write_all(chunk).expect("write file");
+        left -= chunk.len();
+    }
+}
+
+/// The splitmix64 generator, small and good enough for test data.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Deterministic file content for the generated trees.
+fn tree_file_content(size: usize) -> Vec<u8> {
+    b"testcall\n".iter().copied().cycle().take(size).collect()
+}
+
+/// Fills a single directory of a generated tree, returns the subdirectories created.
+fn populate_dir(dir: &Path, depth: usize, fanout: usize, content: &[u8]) -> Vec<PathBuf> {
+    for n in 0..fanout {
+        fs::write(dir.join(format!("f{}", n)), content).expect("create file");
+    }
+    if depth <= 1 {
+        return Vec::new();
+    }
+    (0..fanout)
+        .map(|n| {
+            let subdir = dir.join(format!("d{}", n));
+            fs::create_dir(&subdir).expect("create directory");
+            subdir
+        })
+        .collect()
+}
+
+/// Populates a generated tree without recursion, trees may be deeper than the stack allows.
+fn populate_tree(dir: &Path, depth: usize, fanout: usize, content: &[u8]) {
+    let mut pending = vec![(dir.to_path_buf(), depth)];
+    while let Some((dir, depth)) = pending.pop() {
+        for subdir in populate_dir(&dir, depth, fanout, content) {
+            pending.push((subdir, depth - 1));
+        }
+    }
+}
+
+/// Calls 'condition' repeatedly until it returns true or 'timeout' expires. The condition is
+/// always checked at least once and once more after the timeout expired.
+fn poll_until<F: FnMut() -> bool>(timeout: Duration, mut condition: F) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if condition() {
+            return true;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return condition();
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn exists_within() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&tmpdir);
+
+        let child = testcall.spawn_args(["-c", "sleep 0.1; touch created"]);
+
+        tmpdir.assert_exists_within("created", Duration::from_secs(5));
+        child.wait().assert_success();
+    }
+
+    #[test]
+    fn gone_within() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        std::fs::write(tmpdir.path().join("pidfile"), "").expect("file created");
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&tmpdir);
+
+        let child = testcall.spawn_args(["-c", "sleep 0.1; rm pidfile"]);
+
+        tmpdir.assert_gone_within("pidfile", Duration::from_secs(5));
+        child.wait().assert_success();
+    }
+
+    #[test]
+    fn deep_tree() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_deep_tree("tree", 3, 4, 16);
+
+        let mut dirs = 0;
+        let mut files = 0;
+        let mut pending = vec![tmpdir.path().join("tree")];
+        while let Some(dir) = pending.pop() {
+            dirs += 1;
+            for entry in std::fs::read_dir(dir).expect("readable dir") {
+                let path = entry.expect("dir entry").path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    assert_eq!(path.metadata().expect("metadata").len(), 16);
+                    files += 1;
+                }
+            }
+        }
+        assert_eq!(dirs, 1 + 4 + 16);
+        assert_eq!(files, dirs * 4);
+    }
+
+    #[test]
+    fn deep_narrow_tree() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_deep_tree("tree", 200, 1, 0);
+
+        let leaf: std::path::PathBuf = vec!["d0"; 199].into_iter().collect();
+        assert!(tmpdir.path().join("tree").join(leaf).join("f0").is_file());
+    }
+
+    #[test]
+    fn symlink_farm() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_symlink_farm("farm", "../target", 1000);
+
+        assert_eq!(
+            std::fs::read_dir(tmpdir.path().join("farm"))
+                .expect("readable dir")
+                .count(),
+            1000
+        );
+        assert_eq!(
+            std::fs::read_link(tmpdir.path().join("farm/link-00999")).expect("symlink"),
+            Path::new("../target")
+        );
+    }
+
+    #[test]
+    fn symlink_cycle_and_dangling() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .create_symlink_cycle("cycle", 3)
+            .create_dangling_symlink("dangling");
+
+        assert!(tmpdir.path().join("cycle/cycle-0").metadata().is_err());
+        assert!(tmpdir
+            .path()
+            .join("cycle/self/self/cycle-2")
+            .symlink_metadata()
+            .is_ok());
+        assert!(tmpdir.path().join("dangling").symlink_metadata().is_ok());
+        assert!(!tmpdir.path().join("dangling").exists());
+    }
+
+    #[test]
+    fn pathological_names() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_pathological_names("names");
+
+        assert_eq!(
+            std::fs::read_dir(tmpdir.path().join("names"))
+                .expect("readable dir")
+                .count(),
+            super::PATHOLOGICAL_NAMES.len()
+        );
+        tmpdir.assert_exists_within("names/new\nline", Duration::ZERO);
+    }
+
+    #[test]
+    #[should_panic]
+    fn exists_within_fail() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.assert_exists_within("never", Duration::from_millis(50));
+    }
+
+    #[test]
+    fn tree_equal() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_deep_tree("a", 3, 2, 16);
+        tmpdir.create_deep_tree("b", 3, 2, 16);
+        tmpdir.assert_tree_equal("a", "b");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "(-missing +extra ~differing):\n  d0/\n-   f0  (missing file)\n~   f1  (content differs, 16 bytes expected, 5 bytes found)\n+ extra  (extra file)\n~ f0  (directory, expected file)\n"
+    )]
+    fn tree_differs() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_deep_tree("expected", 2, 2, 16);
+        tmpdir.create_deep_tree("actual", 2, 2, 16);
+        let actual = tmpdir.path().join("actual");
+        std::fs::remove_file(actual.join("d0/f0")).expect("removed");
+        std::fs::write(actual.join("d0/f1"), "hello").expect("written");
+        std::fs::write(actual.join("extra"), "").expect("written");
+        std::fs::remove_file(actual.join("f0")).expect("removed");
+        std::fs::create_dir(actual.join("f0")).expect("created");
+
+        tmpdir.assert_tree_equal("actual", "expected");
+    }
+
+    #[test]
+    fn file_regex() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        std::fs::write(tmpdir.path().join("server.log"), "listening on port 8080\n")
+            .expect("written");
+
+        tmpdir
+            .assert_file_utf8("server.log", "^listening on port [0-9]+\n$")
+            .assert_file_bytes("server.log", "port 80");
+        let captures = tmpdir.file_captures_utf8("server.log", "port (?P<port>[0-9]+)");
+        assert_eq!(captures.get_as::<u16>("port"), 8080);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not match:\n^stopped\nfile was:\nlistening")]
+    fn file_regex_mismatch() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        std::fs::write(tmpdir.path().join("server.log"), "listening\n").expect("written");
+
+        tmpdir.assert_file_utf8("server.log", "^stopped");
+    }
+
+    #[test]
+    fn try_file_regex() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        std::fs::write(tmpdir.path().join("server.log"), "listening\n").expect("written");
+
+        assert!(tmpdir.try_file_utf8("server.log", "^listening").is_ok());
+        let err = tmpdir
+            .try_file_bytes("server.log", "^stopped")
+            .expect_err("file does not match");
+        assert_eq!(err.failure().kind(), "assert_file_bytes");
+    }
+
+    #[test]
+    fn create_tree() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_tree([
+            ("etc/app.conf", "verbose = true\n"),
+            ("etc/app.d/", ""),
+            ("var/log/app.log", ""),
+        ]);
+
+        tmpdir
+            .assert_file_utf8("etc/app.conf", "^verbose = true\n$")
+            .assert_file_bytes("var/log/app.log", "^$");
+        assert!(tmpdir.path().join("etc/app.d").is_dir());
+    }
+
+    #[test]
+    #[should_panic(expected = "path does not exist")]
+    fn create_tree_existing_file() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_tree([("file", "first"), ("file", "second")]);
+    }
+
+    #[test]
+    fn modes() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .create_tree([("bin/tool", "#!/bin/sh\necho tool\n"), ("secret", "")])
+            .chmod("bin/tool", 0o755)
+            .chmod("secret", 0o600);
+
+        tmpdir
+            .assert_mode("bin/tool", 0o755)
+            .assert_executable("bin/tool")
+            .assert_mode("secret", 0o600);
+    }
+
+    #[test]
+    #[should_panic(expected = "has mode 0600, expected 0644")]
+    fn mode_mismatch() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .create_tree([("secret", "")])
+            .chmod("secret", 0o600)
+            .assert_mode("secret", 0o644);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not an executable file")]
+    fn not_executable() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .create_tree([("script", "")])
+            .chmod("script", 0o644)
+            .assert_executable("script");
+    }
+
+    #[test]
+    fn mtimes() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        let tmpdir = TempDir::new().expect("TempDir created");
+        let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        tmpdir
+            .create_tree([("source.c", ""), ("source.o", ""), ("dir/", "")])
+            .set_mtime("source.c", old)
+            .set_mtime("dir", old);
+
+        tmpdir
+            .assert_newer_than("source.o", "source.c")
+            .assert_mtime_within("source.c", old..=old)
+            .assert_mtime_within("dir", ..=old)
+            .assert_mtime_within("source.o", old..SystemTime::now());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not newer than")]
+    fn mtime_older() {
+        use std::time::UNIX_EPOCH;
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .create_tree([("source.c", ""), ("source.o", "")])
+            .set_mtime("source.o", UNIX_EPOCH)
+            .assert_newer_than("source.o", "source.c");
+    }
+
+    #[test]
+    fn digests() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_tree([("hello", "hello\n")]);
+
+        tmpdir.assert_sha256(
+            "hello",
+            "5891B5B522D5DF086D0FF0B110FBD9D21BB4FC7163AF34D08286A2E846F6BE03",
+        );
+        #[cfg(feature = "blake3")]
+        tmpdir.assert_blake3(
+            "hello",
+            "8e4c7c1b99dbfd50e7a95185fead5ee1448fa904a2fdd778eaf5f2dbfd629a99",
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "has sha256 e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855, expected 00"
+    )]
+    fn sha256_mismatch() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .create_tree([("empty", "")])
+            .assert_sha256("empty", "00");
+    }
+
+    #[test]
+    fn testdir_removed() {
+        use testpath::{Fixtures, TestPath};
+        let dir = TestDir::new().keep_on_failure(true);
+        dir.create_tree([("file", "")]).delete("file");
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn testdir_kept_on_failure() {
+        use testpath::TestPath;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let failed = std::thread::spawn(move || {
+            let dir = TestDir::new().keep_on_failure(true);
+            dir.create_tree([("state", "broken")]);
+            sender.send(dir.path().to_path_buf()).expect("sent");
+            panic!("test failed");
+        })
+        .join();
+        assert!(failed.is_err());
+
+        let path = receiver.recv().expect("received");
+        assert!(path.join("state").is_file());
+        std::fs::remove_dir_all(path).expect("kept directory removed");
+    }
+
+    #[test]
+    fn dir_changes() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_tree([("keep", "same"), ("edit", "old"), ("gone", "")]);
+        let snapshot = tmpdir.snapshot();
+
+        TestCall::external_command(Path::new("sh"))
+            .current_dir(&tmpdir)
+            .call_args(["-c", "echo new >edit; rm gone; mkdir out; touch out/result"])
+            .assert_success();
+
+        snapshot.assert_changes(&["out", "out/result"], &["edit"], &["gone"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected changes in")]
+    fn dir_changes_unexpected() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        let snapshot = tmpdir.snapshot();
+        tmpdir.create_tree([("stray", "")]);
+
+        snapshot.assert_changes(&[], &[], &[]);
+    }
+
+    #[test]
+    fn generated_files() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir
+            .create_file_random("a/random", 100_003, 42)
+            .create_file_random("b/random", 100_003, 42)
+            .create_file_random("other", 100_003, 43)
+            .create_file_filled("zeros", 1000, 0);
+
+        tmpdir.assert_tree_equal("a", "b");
+        let random = std::fs::read(tmpdir.path().join("a/random")).expect("readable");
+        assert_eq!(random.len(), 100_003);
+        assert_ne!(
+            random,
+            std::fs::read(tmpdir.path().join("other")).expect("readable")
+        );
+        assert_eq!(
+            std::fs::read(tmpdir.path().join("zeros")).expect("readable"),
+            vec![0; 1000]
+        );
+    }
+
+    #[test]
+    fn fifo() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_fifo("run/pipe").assert_is_fifo("run/pipe");
+
+        let reader = TestCall::external_command(Path::new("sh"))
+            .current_dir(&tmpdir)
+            .spawn_args(["-c", "cat run/pipe"]);
+        std::fs::write(tmpdir.path().join("run/pipe"), "through the pipe\n").expect("written");
+        reader
+            .wait_timeout(Duration::from_secs(5))
+            .assert_stdout_utf8("^through the pipe\n$");
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a fifo")]
+    fn not_fifo() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_tree([("file", "")]).assert_is_fifo("file");
+    }
+
+    #[test]
+    fn read_helpers() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.create_tree([("out/result", "done\n")]);
+
+        assert_eq!(tmpdir.read_utf8("out/result"), "done\n");
+        assert_eq!(tmpdir.read_bytes("out/../out/result"), b"done\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "escaped from testpath")]
+    fn read_escaping() {
+        let tmpdir = TempDir::new().expect("TempDir created");
+        tmpdir.read_bytes("../etc/passwd");
+    }
+}
+
+#[cfg(test)]
+#[cfg(windows)]
+mod windows_test {
+    use crate::*;
+    use std::path::Path;
+    use testpath::TestPath;
+
+    #[test]
+    fn slash_and_backslash_paths() {
+        let testdir = TestDir::new();
+        testdir.create_tree([("etc/app.conf", "verbose = true\r\n"), ("var\\log\\", "")]);
+        let snapshot = testdir.snapshot();
+
+        testdir.create_tree([("var/log/app.log", "started\r\n")]);
+        testdir
+            .assert_file_utf8("etc\\app.conf", "^verbose = true\r\n$")
+            .assert_file_utf8("var/log/app.log", "^started");
+        assert!(testdir.sub_path("var/log").is_dir());
+        snapshot.assert_changes(&["var/log/app.log"], &[], &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "/etc/app.conf\" has sha256")]
+    fn backslash_path_rendered_slashed() {
+        let testdir = TestDir::new();
+        testdir.create_tree([("etc/app.conf", "verbose = true\r\n")]);
+        assert_eq!(
+            *super::sub_path(&testdir, "etc/app.conf"),
+            *testdir.sub_path("etc\\app.conf")
+        );
+        testdir.assert_sha256("etc\\app.conf", "00");
+    }
+
+    #[test]
+    fn cmd_in_testdir() {
+        let testdir = TestDir::new();
+        let mut testcall = TestCall::external_command(Path::new("cmd"));
+        testcall.current_dir(&testdir).normalize_newlines(true);
+
+        testcall
+            .call_args(["/C", "echo hello>out.txt && type out.txt"])
+            .assert_success()
+            .assert_stdout_utf8("^hello\n$");
+        testdir.assert_file_utf8("out.txt", "^hello\r\n$");
+    }
+}
@@ -0,0 +1,238 @@
+//! Running calls under 'strace' and asserting on the recorded syscalls ('trace' feature,
+//! linux only).
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::failure::Failure;
+use crate::fsaudit::{glob_regex, opened_paths, traced_calls};
+use crate::{CallOutput, TestCall, TestOutput, NO_ENVS};
+
+/// The output of 'TestCall::call_traced()' together with the files the call opened and the
+/// programs it executed, including those of its children. All 'TestOutput' assertions are
+/// available as well.
+#[derive(Debug)]
+pub struct SyscallTrace {
+    output: CallOutput,
+    log: PathBuf,
+    dir: PathBuf,
+    opened: Vec<String>,
+    execs: Vec<String>,
+}
+
+impl TestCall<'_> {
+    /// Calls the executable with 'args' under 'strace -f'. The raw log is written to
+    /// 'strace-<n>.log' in the directory set with 'current_dir()' (the temporary directory
+    /// otherwise) and kept for inspection.
+    #[track_caller]
+    pub fn call_traced<IA, S>(&self, args: IA) -> SyscallTrace
+    where
+        IA: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        static TRACES: AtomicUsize = AtomicUsize::new(0);
+        let dir = self
+            .dir
+            .map(|dir| dir.path().to_path_buf())
+            .unwrap_or_else(std::env::temp_dir);
+        let log = dir.join(format!(
+            "strace-{}-{}.log",
+            process::id(),
+            TRACES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let wrapper: Vec<OsString> = [
+            "strace",
+            "-f",
+            "-qq",
+            "-e",
+            "trace=open,openat,openat2,creat,execve,execveat",
+            "-o",
+        ]
+        .iter()
+        .map(OsString::from)
+        .chain([log.clone().into_os_string(), OsString::from("--")])
+        .collect();
+
+        let output = self.run(self.command_wrapped(&wrapper, args, NO_ENVS), None);
+        let text = fs::read_to_string(&log).unwrap_or_else(|err| {
+            panic!(
+                "no strace log ({}), is strace installed?\nstderr was:\n{}",
+                err,
+                String::from_utf8_lossy(&output.stderr)
+            )
+        });
+        SyscallTrace {
+            output,
+            opened: opened_paths(&text),
+            execs: exec_paths(&text),
+            log,
+            dir: self
+                .dir
+                .map(|dir| dir.path().to_path_buf())
+                .unwrap_or_else(|| std::env::current_dir().expect("current dir")),
+        }
+    }
+}
+
+impl SyscallTrace {
+    /// The output of the traced call.
+    pub fn call_output(&self) -> &CallOutput {
+        &self.output
+    }
+
+    /// The raw 'strace' log.
+    pub fn log(&self) -> &Path {
+        &self.log
+    }
+
+    /// The paths of all successfully opened files in order, as passed by the program.
+    pub fn opened(&self) -> &[String] {
+        &self.opened
+    }
+
+    /// The paths of all successfully executed programs in order, the traced executable
+    /// first.
+    pub fn execs(&self) -> &[String] {
+        &self.execs
+    }
+
+    /// Expects that a file matching the glob 'pattern' was opened. A pattern without '/'
+    /// matches the file name, others the path with relative paths resolved against the
+    /// working directory of the call. Globs are the ones of
+    /// 'TestCall::assert_file_access_within()'.
+    #[track_caller]
+    pub fn assert_opened(&self, pattern: &str) -> &Self {
+        if !self.any_match(&self.opened, pattern) {
+            Failure::new(
+                "assert_opened",
+                format!("no file matching {:?} was opened", pattern),
+            )
+            .with_actual("opened files", &self.opened.join("\n"))
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that no file matching the glob 'pattern' was opened.
+    #[track_caller]
+    pub fn assert_not_opened(&self, pattern: &str) -> &Self {
+        if self.any_match(&self.opened, pattern) {
+            Failure::new(
+                "assert_not_opened",
+                format!("a file matching {:?} was opened", pattern),
+            )
+            .with_actual("opened files", &self.opened.join("\n"))
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that a program matching the glob 'pattern' was executed, matched like
+    /// 'assert_opened()'.
+    #[track_caller]
+    pub fn assert_executed(&self, pattern: &str) -> &Self {
+        if !self.any_match(&self.execs, pattern) {
+            Failure::new(
+                "assert_executed",
+                format!("no program matching {:?} was executed", pattern),
+            )
+            .with_actual("executed programs", &self.execs.join("\n"))
+            .fail();
+        }
+        self
+    }
+
+    fn any_match(&self, paths: &[String], pattern: &str) -> bool {
+        let regex = glob_regex(pattern);
+        paths.iter().any(|path| {
+            let path = self.dir.join(path);
+            if pattern.contains('/') {
+                regex.is_match(&path.to_string_lossy())
+            } else {
+                path.file_name()
+                    .is_some_and(|name| regex.is_match(&name.to_string_lossy()))
+            }
+        })
+    }
+}
+
+impl TestOutput for SyscallTrace {
+    fn output(&self) -> &Output {
+        &self.output
+    }
+}
+
+/// Extracts the programs of all successful exec calls from a 'strace -f' log.
+fn exec_paths(log: &str) -> Vec<String> {
+    traced_calls(log, &["execve", "execveat"])
+        .into_iter()
+        .map(|call| call.path)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn execs() {
+        let log = r#"4711  execve("/usr/bin/sh", ["sh", "-c", "ls"], 0x7ffd /* 20 vars */) = 0
+4712  execve("/usr/local/bin/ls", ["ls"], 0x55 /* 20 vars */) = -1 ENOENT (No such file or directory)
+4712  execve("/usr/bin/ls", ["ls"], 0x55 /* 20 vars */) = 0
+4713  execveat(AT_FDCWD, "helper", ["helper"], 0x55 /* 1 var */, 0) = 0
+4714  execve("/usr/bin/cat", ["cat"], 0x55 /* 20 vars */ <unfinished ...>
+4715  execve("/usr/bin/tac", ["tac"], 0x55 /* 20 vars */ <unfinished ...>
+4714  <... execve resumed>)             = 0
+4715  <... execve resumed>)             = -1 EACCES (Permission denied)
+"#;
+        assert_eq!(
+            exec_paths(log),
+            ["/usr/bin/sh", "/usr/bin/ls", "helper", "/usr/bin/cat"]
+        );
+    }
+
+    #[test]
+    fn traced() {
+        // skipped where strace is not installed
+        if process::Command::new("strace").arg("-V").output().is_err() {
+            return;
+        }
+        let testdir = crate::TestDir::new();
+        let mut testcall = TestCall::external_command(Path::new("sh"));
+        testcall.current_dir(&testdir);
+
+        testcall
+            .call_traced(["-c", "echo x >out.txt; cat out.txt; ls"])
+            .assert_success()
+            .assert_stdout_utf8("^x\n")
+            .assert_opened("out.txt")
+            .assert_executed("sh")
+            .assert_executed("cat")
+            .assert_executed("ls");
+    }
+
+    #[test]
+    fn matching() {
+        let output = process::Command::new("true").output().expect("true runs");
+        let trace = SyscallTrace {
+            output: CallOutput::new(output, std::time::Duration::ZERO, None),
+            log: PathBuf::from("/work/strace.log"),
+            dir: PathBuf::from("/work"),
+            opened: vec![
+                String::from("/etc/ld.so.cache"),
+                String::from("conf/app.toml"),
+            ],
+            execs: vec![String::from("/usr/bin/sh")],
+        };
+
+        trace
+            .assert_success()
+            .assert_opened("app.toml")
+            .assert_opened("/work/conf/*.toml")
+            .assert_not_opened("*.secret")
+            .assert_not_opened("/conf/app.toml")
+            .assert_executed("sh");
+    }
+}
@@ -0,0 +1,457 @@
+//! Scripted unix domain socket servers and clients for testing programs which communicate
+//! over local sockets (unix only), re-exported through the 'net' module.
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use testpath::TestPath;
+
+use crate::failure::Failure;
+
+/// How often the received data is checked while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The data received on each accepted connection.
+type Connections = Arc<Mutex<Vec<Arc<Mutex<Vec<u8>>>>>>;
+
+/// The threads serving the accepted connections with a handle to shut their stream down.
+type Workers = Arc<Mutex<Vec<(UnixStream, JoinHandle<()>)>>>;
+
+/// The scripted behavior of a socket server for a tested client. Every connection is
+/// served the same script: the greeting is sent right after accepting, then each request
+/// (the data up to and including the delimiter, a newline by default) is answered with the
+/// next reply, no matter how the client splits it into writes. An unterminated request at
+/// the end of the stream is answered as well. By default nothing is sent and the
+/// connection stays open until the client closes it.
+#[derive(Clone, Debug)]
+pub struct SocketScript {
+    greeting: Vec<u8>,
+    replies: Vec<Vec<u8>>,
+    delimiter: u8,
+    close_after_replies: bool,
+}
+
+impl Default for SocketScript {
+    fn default() -> Self {
+        SocketScript {
+            greeting: Vec::new(),
+            replies: Vec::new(),
+            delimiter: b'\n',
+            close_after_replies: false,
+        }
+    }
+}
+
+impl SocketScript {
+    /// Creates a script which only records what clients send.
+    pub fn new() -> Self {
+        SocketScript::default()
+    }
+
+    /// Sets the byte which ends each request, for protocols not framed by lines.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets what is sent to each client right after it connected.
+    pub fn greeting(mut self, greeting: impl AsRef<[u8]>) -> Self {
+        self.greeting = greeting.as_ref().to_vec();
+        self
+    }
+
+    /// Adds a reply, sent for the next request.
+    pub fn reply(mut self, reply: impl AsRef<[u8]>) -> Self {
+        self.replies.push(reply.as_ref().to_vec());
+        self
+    }
+
+    /// Closes each connection once the last reply was sent, for clients which read until
+    /// the end of the stream.
+    pub fn close_after_replies(mut self, close: bool) -> Self {
+        self.close_after_replies = close;
+        self
+    }
+
+    /// Creates the listening socket 'name' in the test directory 'dir' (replacing an
+    /// existing file) and serves the script on it in the background until the returned
+    /// server is dropped.
+    #[track_caller]
+    pub fn listen<T: TestPath + ?Sized>(self, dir: &T, name: &str) -> SocketServer {
+        let path = dir.path().join(name);
+        let _ = fs::remove_file(&path);
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(err) => panic!("listening on {:?} failed: {}", path, err),
+        };
+        let connections: Connections = Arc::new(Mutex::new(Vec::new()));
+        let workers: Workers = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let acceptor = {
+            let connections = Arc::clone(&connections);
+            let workers = Arc::clone(&workers);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let (stream, handle) = match stream.and_then(|stream| {
+                        let handle = stream.try_clone()?;
+                        Ok((stream, handle))
+                    }) {
+                        Ok(streams) => streams,
+                        Err(_) => continue,
+                    };
+                    let received = {
+                        let mut connections = connections.lock().expect("connections locked");
+                        connections.push(Arc::new(Mutex::new(Vec::new())));
+                        Arc::clone(connections.last().expect("connection added"))
+                    };
+                    let script = self.clone();
+                    let worker = thread::spawn(move || {
+                        // the client may go away any time, that is not an error of the test
+                        let _ = script.serve(&stream, &received);
+                        // the handle kept for dropping the server holds the connection open
+                        let _ = stream.shutdown(Shutdown::Both);
+                    });
+                    workers
+                        .lock()
+                        .expect("workers locked")
+                        .push((handle, worker));
+                }
+            })
+        };
+        SocketServer {
+            path,
+            connections,
+            workers,
+            stop,
+            acceptor: Some(acceptor),
+        }
+    }
+
+    /// Runs the script on one connection, recording the received data.
+    fn serve(&self, mut stream: &UnixStream, received: &Mutex<Vec<u8>>) -> io::Result<()> {
+        stream.write_all(&self.greeting)?;
+        let mut replies = self.replies.iter();
+        let mut buffer = [0; 4096];
+        // the length of the request which is not complete yet
+        let mut pending = 0;
+        loop {
+            let n = stream.read(&mut buffer)?;
+            let data = &buffer[..n];
+            received
+                .lock()
+                .expect("received locked")
+                .extend_from_slice(data);
+            let requests = if n == 0 {
+                usize::from(pending > 0)
+            } else {
+                pending = match data.iter().rposition(|&byte| byte == self.delimiter) {
+                    Some(end) => n - end - 1,
+                    None => pending + n,
+                };
+                data.iter().filter(|&&byte| byte == self.delimiter).count()
+            };
+            for _ in 0..requests {
+                let reply = match replies.next() {
+                    Some(reply) => reply,
+                    None => break,
+                };
+                stream.write_all(reply)?;
+                if self.close_after_replies && replies.len() == 0 {
+                    return stream.shutdown(Shutdown::Both);
+                }
+            }
+            if n == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// A unix domain socket listening in the background, created with 'SocketScript::listen()'.
+/// Records the data of every connection. Dropping it stops listening, shuts the open
+/// connections down and removes the socket.
+pub struct SocketServer {
+    path: PathBuf,
+    connections: Connections,
+    workers: Workers,
+    stop: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl SocketServer {
+    /// The path of the socket, to be passed to the tested client.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The data received so far on each connection, in the order they were accepted.
+    pub fn connections(&self) -> Vec<Vec<u8>> {
+        self.connections
+            .lock()
+            .expect("connections locked")
+            .iter()
+            .map(|received| received.lock().expect("received locked").clone())
+            .collect()
+    }
+
+    /// The data received so far on all connections, concatenated.
+    pub fn received(&self) -> Vec<u8> {
+        self.connections().concat()
+    }
+
+    /// Waits until the received data matches 'regex' (lossy converted to utf8). Panics with
+    /// the data received until then when this did not happen within 'timeout'.
+    #[track_caller]
+    pub fn wait_for_received(&self, regex: &str, timeout: Duration) -> &Self {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let (ok, utf8) = crate::regex::regex_match_utf8(&self.received(), regex);
+            if ok {
+                return self;
+            }
+            if Instant::now() >= deadline {
+                Failure::new(
+                    "wait_for_received",
+                    format!(
+                        "{:?} did not receive a match within {:?}",
+                        self.path, timeout
+                    ),
+                )
+                .with_expected(regex)
+                .with_actual("received", &utf8)
+                .fail();
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Expects that the data received so far matches 'regex' (lossy converted to utf8).
+    #[track_caller]
+    pub fn assert_received_utf8(&self, regex: &str) -> &Self {
+        let (ok, utf8) = crate::regex::regex_match_utf8(&self.received(), regex);
+        if !ok {
+            Failure::new(
+                "assert_received_utf8",
+                String::from("received data does not match"),
+            )
+            .with_expected(regex)
+            .with_actual("received", &utf8)
+            .with_hint(crate::regex::closest_match(&utf8, regex))
+            .fail();
+        }
+        self
+    }
+
+    /// Expects that exactly 'count' connections were accepted so far.
+    #[track_caller]
+    pub fn assert_connections(&self, count: usize) -> &Self {
+        let accepted = self.connections.lock().expect("connections locked").len();
+        if accepted != count {
+            Failure::new(
+                "assert_connections",
+                format!("expected {} connections, accepted {}", count, accepted),
+            )
+            .fail();
+        }
+        self
+    }
+}
+
+impl Drop for SocketServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // wakes the acceptor up
+        let _ = UnixStream::connect(&self.path);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        for (stream, worker) in self.workers.lock().expect("workers locked").drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+            let _ = worker.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Waits until a program listens on the unix domain socket at 'path'. Panics with the last
+/// connection error when this did not happen within 'timeout'.
+#[track_caller]
+pub fn wait_for_socket<P: AsRef<Path>>(path: P, timeout: Duration) {
+    let path = path.as_ref();
+    let deadline = Instant::now() + timeout;
+    loop {
+        let error = match UnixStream::connect(path) {
+            Ok(_) => return,
+            Err(err) => err,
+        };
+        if Instant::now() >= deadline {
+            Failure::new(
+                "wait_for_socket",
+                format!(
+                    "{:?} did not accept connections within {:?}: {}",
+                    path, timeout, error
+                ),
+            )
+            .fail();
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Sends 'request' to the unix domain socket at 'path', closes the sending side and returns
+/// everything the program answers until it closes the connection. Panics on errors and
+/// when the answer did not end within 'timeout'.
+#[track_caller]
+pub fn socket_request<P: AsRef<Path>>(path: P, request: &[u8], timeout: Duration) -> Vec<u8> {
+    let path = path.as_ref();
+    let exchange = || -> io::Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.write_all(request)?;
+        stream.shutdown(Shutdown::Write)?;
+        let mut answer = Vec::new();
+        stream.read_to_end(&mut answer)?;
+        Ok(answer)
+    };
+    match exchange() {
+        Ok(answer) => answer,
+        Err(err) => Failure::new(
+            "socket_request",
+            format!("request to {:?} failed: {}", path, err),
+        )
+        .fail(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::*;
+    use crate::*;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn scripted_server() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let server = SocketScript::new()
+            .greeting("HELLO\n")
+            .reply("OK 1\n")
+            .reply("OK 2\n")
+            .close_after_replies(true)
+            .listen(&tmpdir, "daemon.sock");
+        assert!(server.path().starts_with(tmpdir.path()));
+
+        let first = socket_request(server.path(), b"PING\n", Duration::from_secs(5));
+        assert_eq!(first, b"HELLO\nOK 1\n");
+        server
+            .wait_for_received("^PING\n$", Duration::from_secs(5))
+            .assert_connections(1);
+        socket_request(server.path(), b"AGAIN\n", Duration::from_secs(5));
+        server
+            .wait_for_received("^PING\nAGAIN\n$", Duration::from_secs(5))
+            .assert_received_utf8("AGAIN")
+            .assert_connections(2);
+        assert_eq!(server.connections()[1], b"AGAIN\n");
+    }
+
+    #[test]
+    fn tested_client() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let server = SocketScript::new()
+            .reply("pong\n")
+            .close_after_replies(true)
+            .listen(&tmpdir, "client.sock");
+        if !Path::new("/usr/bin/python3").exists() {
+            return;
+        }
+        let testcall = TestCall::external_command(Path::new("/usr/bin/python3"));
+
+        testcall
+            .call_args([
+                "-c",
+                "import socket,sys; s=socket.socket(socket.AF_UNIX); s.connect(sys.argv[1]); \
+                 s.sendall(b'ping\\n'); print(s.recv(100).decode(), end='')",
+                &server.path().display().to_string(),
+            ])
+            .assert_success()
+            .assert_stdout_eq("pong\n");
+        server.assert_received_utf8("^ping\n$");
+    }
+
+    #[test]
+    fn framed_requests() {
+        use std::io::{Read, Write};
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let server = SocketScript::new()
+            .reply("1\n")
+            .reply("2\n")
+            .reply("3\n")
+            .reply("4\n")
+            .listen(&tmpdir, "framed.sock");
+
+        let mut client = std::os::unix::net::UnixStream::connect(server.path()).expect("connected");
+        client.write_all(b"PI").expect("written");
+        server.wait_for_received("^PI$", Duration::from_secs(5));
+        client.write_all(b"NG\nA\nB").expect("written");
+        client
+            .shutdown(std::net::Shutdown::Write)
+            .expect("shut down");
+        let mut answer = Vec::new();
+        client.read_to_end(&mut answer).expect("answer read");
+        assert_eq!(answer, b"1\n2\n3\n");
+    }
+
+    #[test]
+    fn drop_closes_connections() {
+        use std::io::Read;
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let server = SocketScript::new()
+            .greeting("HELLO\n")
+            .listen(&tmpdir, "open.sock");
+
+        let mut client = std::os::unix::net::UnixStream::connect(server.path()).expect("connected");
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("timeout set");
+        let mut greeting = [0; 6];
+        client.read_exact(&mut greeting).expect("greeting read");
+        drop(server);
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).expect("closed by the server");
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "received data does not match")]
+    fn received_mismatch() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let server = SocketScript::new().listen(&tmpdir, "silent.sock");
+
+        server.assert_received_utf8("anything");
+    }
+
+    #[test]
+    fn wait_for_daemon() {
+        let tmpdir = tempfile::TempDir::new().expect("TempDir created");
+        let path = tmpdir.path().join("late.sock");
+        let server_path = path.clone();
+        let server = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            let listener = std::os::unix::net::UnixListener::bind(server_path).expect("bound");
+            let _ = listener.accept();
+        });
+
+        wait_for_socket(&path, Duration::from_secs(5));
+        server.join().expect("server finished");
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt::{self, Display};
+
+/// A generic value decoded from a binary serialization format (CBOR, MessagePack).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    /// A CBOR tagged value.
+    Tagged(u64, Box<Value>),
+    /// A MessagePack extension type.
+    Ext(i8, Vec<u8>),
+}
+
+impl Value {
+    /// Looks up a value by a JSON pointer (RFC 6901) like "/items/0/name". Map keys are
+    /// matched as text or, when the segment is numeric, as integer. Tags are transparent.
+    pub fn pointer(&self, pointer: &str) -> Option<&Value> {
+        let mut current = self.untagged();
+        if pointer.is_empty() {
+            return Some(current);
+        }
+        for segment in pointer.strip_prefix('/')?.split('/') {
+            let segment = segment.replace("~1", "/").replace("~0", "~");
+            current = match current {
+                Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+                Value::Map(entries) => {
+                    let integer = segment.parse::<i128>().ok();
+                    entries
+                        .iter()
+                        .find(|(key, _)| match key {
+                            Value::Text(text) => *text == segment,
+                            Value::Integer(n) => Some(*n) == integer,
+                            _ => false,
+                        })
+                        .map(|(_, value)| value)?
+                }
+                _ => return None,
+            }
+            .untagged();
+        }
+        Some(current)
+    }
+
+    fn untagged(&self) -> &Value {
+        let mut current = self;
+        while let Value::Tagged(_, inner) = current {
+            current = inner;
+        }
+        current
+    }
+
+    /// Asserts that the value at 'pointer' exists and equals 'expected'.
+    #[track_caller]
+    pub fn assert_pointer<V: Into<Value>>(&self, pointer: &str, expected: V) -> &Self {
+        let expected = expected.into();
+        match self.pointer(pointer) {
+            Some(found) => assert!(
+                *found == expected,
+                "value at {:?} is {}, expected {}",
+                pointer,
+                found,
+                expected
+            ),
+            None => panic!("no value at {:?} in {}", pointer, self),
+        }
+        self
+    }
+
+    /// Asserts that a value exists at 'pointer'.
+    #[track_caller]
+    pub fn assert_pointer_exists(&self, pointer: &str) -> &Self {
+        assert!(
+            self.pointer(pointer).is_some(),
+            "no value at {:?} in {}",
+            pointer,
+            self
+        );
+        self
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::Bytes(bytes) => write!(f, "h'{}'", hex(bytes)),
+            Value::Text(text) => write!(f, "{:?}", text),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (n, item) in items.iter().enumerate() {
+                    write!(f, "{}{}", if n == 0 { "" } else { ", " }, item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (n, (key, value)) in entries.iter().enumerate() {
+                    write!(f, "{}{}: {}", if n == 0 { "" } else { ", " }, key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Tagged(tag, value) => write!(f, "{}({})", tag, value),
+            Value::Ext(kind, bytes) => write!(f, "ext{}(h'{}')", kind, hex(bytes)),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+macro_rules! value_from_integer {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Value {
+            fn from(n: $t) -> Self {
+                Value::Integer(i128::from(n))
+            }
+        })*
+    };
+}
+value_from_integer!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Float(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(String::from(text))
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(bytes: &[u8]) -> Self {
+        Value::Bytes(bytes.to_vec())
+    }
+}
+
+/// Decodes the first CBOR item from 'input'.
+#[cfg(feature = "cbor")]
+#[track_caller]
+pub fn decode_cbor(input: &[u8]) -> Value {
+    fn convert(value: ciborium::value::Value) -> Value {
+        use ciborium::value::Value as Cbor;
+        match value {
+            Cbor::Null => Value::Null,
+            Cbor::Bool(b) => Value::Bool(b),
+            Cbor::Integer(n) => Value::Integer(i128::from(n)),
+            Cbor::Float(n) => Value::Float(n),
+            Cbor::Bytes(bytes) => Value::Bytes(bytes),
+            Cbor::Text(text) => Value::Text(text),
+            Cbor::Array(items) => Value::Array(items.into_iter().map(convert).collect()),
+            Cbor::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (convert(key), convert(value)))
+                    .collect(),
+            ),
+            Cbor::Tag(tag, value) => Value::Tagged(tag, Box::new(convert(*value))),
+            _ => panic!("unsupported cbor value"),
+        }
+    }
+    convert(ciborium::de::from_reader(input).expect("valid cbor"))
+}
+
+/// Decodes the first MessagePack value from 'input'.
+#[cfg(feature = "msgpack")]
+#[track_caller]
+pub fn decode_msgpack(mut input: &[u8]) -> Value {
+    fn convert(value: rmpv::Value) -> Value {
+        use rmpv::Value as Msgpack;
+        match value {
+            Msgpack::Nil => Value::Null,
+            Msgpack::Boolean(b) => Value::Bool(b),
+            Msgpack::Integer(n) => Value::Integer(
+                n.as_i64()
+                    .map(i128::from)
+                    .or_else(|| n.as_u64().map(i128::from))
+                    .expect("valid integer"),
+            ),
+            Msgpack::F32(n) => Value::Float(f64::from(n)),
+            Msgpack::F64(n) => Value::Float(n),
+            Msgpack::String(text) => match text.into_str() {
+                Some(text) => Value::Text(text),
+                None => panic!("invalid utf8 in msgpack string"),
+            },
+            Msgpack::Binary(bytes) => Value::Bytes(bytes),
+            Msgpack::Array(items) => Value::Array(items.into_iter().map(convert).collect()),
+            Msgpack::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (convert(key), convert(value)))
+                    .collect(),
+            ),
+            Msgpack::Ext(kind, bytes) => Value::Ext(kind, bytes),
+        }
+    }
+    convert(rmpv::decode::read_value(&mut input).expect("valid msgpack"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> Value {
+        Value::Map(vec![
+            (
+                Value::from("items"),
+                Value::Array(vec![Value::from(1), Value::from("x/y")]),
+            ),
+            (
+                Value::from(7),
+                Value::Tagged(1, Box::new(Value::from(true))),
+            ),
+        ])
+    }
+
+    #[test]
+    fn pointer() {
+        let value = sample();
+        value
+            .assert_pointer("/items/0", 1)
+            .assert_pointer("/items/1", "x/y")
+            .assert_pointer("/7", true)
+            .assert_pointer_exists("");
+        assert_eq!(value.pointer("/items/2"), None);
+        assert_eq!(value.pointer("items"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "value at \"/items/0\" is 1, expected 2")]
+    fn pointer_mismatch() {
+        sample().assert_pointer("/items/0", 2);
+    }
+
+    #[test]
+    #[cfg(feature = "cbor")]
+    fn cbor() {
+        decode_cbor(b"\xa1\x61\x61\x82\x01\x61\x78")
+            .assert_pointer("/a/0", 1)
+            .assert_pointer("/a/1", "x");
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn msgpack() {
+        decode_msgpack(b"\x81\xa1\x61\x92\x01\xa1\x78")
+            .assert_pointer("/a/0", 1)
+            .assert_pointer("/a/1", "x");
+    }
+}
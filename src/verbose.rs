@@ -0,0 +1,99 @@
+//! Tracing of every invocation for understanding failing CI runs. Setting 'TESTCALL_VERBOSE'
+//! to anything but "0" prints each finished call to stderr, which the test harness shows
+//! for failing tests. With the 'log' feature every call is logged on the 'testcall' target
+//! as well, at debug level.
+use std::fmt::Write;
+use std::process::{Command, ExitStatus};
+use std::time::Duration;
+
+use crate::cmdline::shell_quote;
+use crate::output::describe_status;
+use crate::testcall::env_flag;
+
+/// Traces a finished call.
+pub(crate) fn call(command: &Command, status: ExitStatus, duration: Duration) {
+    let print = env_flag("TESTCALL_VERBOSE");
+    #[cfg(feature = "log")]
+    let logged = log::log_enabled!(target: "testcall", log::Level::Debug);
+    #[cfg(not(feature = "log"))]
+    let logged = false;
+    if !print && !logged {
+        return;
+    }
+    let description = describe(command, status, duration);
+    #[cfg(feature = "log")]
+    log::debug!(target: "testcall", "{}", description);
+    if print {
+        eprintln!("testcall: {}", description);
+    }
+}
+
+/// Renders the command line, the working directory, the variables which differ from the
+/// test's environment, the exit status and the duration of a call.
+fn describe(command: &Command, status: ExitStatus, duration: Duration) -> String {
+    let mut description = shell_quote(command.get_program());
+    for arg in command.get_args() {
+        let _ = write!(description, " {}", shell_quote(arg));
+    }
+    if let Some(dir) = command.get_current_dir() {
+        let _ = write!(description, "\n  cwd: {}", dir.display());
+    }
+    let mut envs = command.get_envs().peekable();
+    if envs.peek().is_some() {
+        description.push_str("\n  env:");
+        for (key, value) in envs {
+            let _ = match value {
+                Some(value) => write!(
+                    description,
+                    " +{}={}",
+                    key.to_string_lossy(),
+                    shell_quote(value)
+                ),
+                None => write!(description, " -{}", key.to_string_lossy()),
+            };
+        }
+    }
+    let _ = write!(
+        description,
+        "\n  -> {} in {:?}",
+        describe_status(status),
+        duration
+    );
+    description
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use super::describe;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, ExitStatus};
+    use std::time::Duration;
+
+    #[test]
+    fn description() {
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "exit 3"])
+            .current_dir("/tmp")
+            .env("GREETING", "hello world")
+            .env_remove("HOME");
+
+        assert_eq!(
+            describe(
+                &command,
+                ExitStatus::from_raw(3 << 8),
+                Duration::from_millis(5)
+            ),
+            "sh -c 'exit 3'\n  cwd: /tmp\n  env: +GREETING='hello world' -HOME\n  -> exit code 3 in 5ms"
+        );
+        assert_eq!(
+            describe(
+                &Command::new("true"),
+                ExitStatus::from_raw(0),
+                Duration::ZERO
+            ),
+            "true\n  -> exit code 0 in 0ns"
+        );
+    }
+}
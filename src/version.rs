@@ -0,0 +1,35 @@
+use regex::Regex;
+use semver::Version;
+
+/// Extracts the first semantic version (like '1.2.3', '0.1.0-beta.1+build') from 'input'.
+/// A leading 'v' is accepted. The input is lossy converted to utf8 first.
+pub fn extract_version(input: &[u8]) -> Option<Version> {
+    let re = Regex::new(
+        r"(?:^|[^0-9A-Za-z.])v?(\d+\.\d+\.\d+(?:-[0-9A-Za-z-]+(?:\.[0-9A-Za-z-]+)*)?(?:\+[0-9A-Za-z-]+(?:\.[0-9A-Za-z-]+)*)?)(?:$|[^0-9A-Za-z.]|\.(?:$|[^0-9]))",
+    )
+    .expect("valid regex");
+    let text = String::from_utf8_lossy(input);
+    let version = re
+        .captures_iter(&text)
+        .find_map(|captures| Version::parse(&captures[1]).ok());
+    version
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extract() {
+        assert_eq!(
+            extract_version(b"myprogram 0.1.2\n"),
+            Some(Version::new(0, 1, 2))
+        );
+        assert_eq!(
+            extract_version(b"tool v10.20.30-rc.1+abc (built 2021.01.01)"),
+            Version::parse("10.20.30-rc.1+abc").ok()
+        );
+        assert_eq!(extract_version(b"ip 192.168.1.1 version 1.2"), None);
+        assert_eq!(extract_version(b"no version"), None);
+    }
+}